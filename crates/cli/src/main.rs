@@ -1,12 +1,28 @@
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 
+use patchwaste_core::baseline::{self, BaselineOrder};
 use patchwaste_core::config::Config;
-use patchwaste_core::report::{BuildMetadata, Report};
-use patchwaste_core::types::Severity;
-use patchwaste_core::{analyse_dir, AnalyseOptions};
+use patchwaste_core::history::{self, BaselineStrategy, HistoryEntry};
+use patchwaste_core::http::RetryPolicy;
+use patchwaste_core::merge::{self, MetricMerge};
+use patchwaste_core::parser::{CounterMerge, DigitSeparators, InputEncoding};
+use patchwaste_core::report::{
+    round_floats, BuildMetadata, FormatVersion, JunitOptions, MarkdownOptions, Report,
+    StrictPreview,
+};
+use patchwaste_core::sink::{FileSink, OutputSink};
+use patchwaste_core::types::{
+    human_bytes, BaselineMetric, ConfidenceLevel, Severity, WasteBand, WasteBands,
+};
+use patchwaste_core::{
+    analyse_dir, analyse_dir_timed, analyse_reader_timed, analyse_url, analyse_url_timed,
+    AnalyseOptions, Timings,
+};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -21,43 +37,495 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Analyse {
-        #[arg(long)]
-        input: PathBuf,
+    Analyse(Box<AnalyseArgs>),
+    Merge(MergeArgs),
+    /// Baseline file helpers.
+    #[command(subcommand)]
+    Baseline(BaselineCommand),
+    /// Compare two report.json files.
+    Diff(DiffArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// Report of the earlier build, e.g. the last green one.
+    #[arg(long)]
+    base: PathBuf,
+
+    #[arg(long)]
+    head: PathBuf,
+
+    /// Exit 2 when new_bytes or waste_ratio rose by more than this fraction
+    /// of its base value.
+    #[arg(long, default_value_t = 0.0)]
+    threshold: f64,
+}
+
+#[derive(Subcommand, Debug)]
+enum BaselineCommand {
+    /// Analyse a BuildOutput and write its metrics as a baseline file.
+    Capture(CaptureArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct CaptureArgs {
+    #[arg(long)]
+    input: PathBuf,
+
+    #[arg(long, default_value = "baseline.json")]
+    out: PathBuf,
+
+    /// Overwrite `--out` if it already exists.
+    #[arg(long)]
+    force: bool,
+
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    #[arg(required = true)]
+    reports: Vec<PathBuf>,
+
+    #[arg(long, default_value = "patchwaste-out")]
+    out: PathBuf,
+
+    #[arg(long, default_value = "sum")]
+    metrics: MergeMetricsArg,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum MergeMetricsArg {
+    Sum,
+    Max,
+}
+
+impl From<MergeMetricsArg> for MetricMerge {
+    fn from(m: MergeMetricsArg) -> Self {
+        match m {
+            MergeMetricsArg::Sum => MetricMerge::Sum,
+            MergeMetricsArg::Max => MetricMerge::Max,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct AnalyseArgs {
+    /// BuildOutput directory, glob, or `-` to read a single log from stdin.
+    #[arg(long, required_unless_present = "input_url")]
+    input: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "input")]
+    input_url: Option<String>,
+
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "baseline")]
+    baseline_url: Option<String>,
+
+    /// Directory of baselines: `<branch>.json`, `main.json` or `default.json`
+    /// if present, otherwise the newest per `--baseline-dir-order`.
+    #[arg(long, conflicts_with_all = ["baseline", "baseline_url"])]
+    baseline_dir: Option<PathBuf>,
+
+    /// Baseline report JSON passed directly instead of as a file.
+    #[arg(long, conflicts_with_all = ["baseline", "baseline_url", "baseline_dir"])]
+    baseline_inline: Option<String>,
+
+    /// Use the report.json left in `--out` by the previous run as the baseline,
+    /// if there is one.
+    #[arg(
+        long,
+        conflicts_with_all = ["baseline", "baseline_url", "baseline_dir", "baseline_inline"]
+    )]
+    baseline_auto: bool,
+
+    #[arg(long, default_value = "mtime", requires = "baseline_dir")]
+    baseline_dir_order: BaselineOrderArg,
+
+    #[arg(long, default_value_t = 2)]
+    http_retries: u32,
+
+    #[arg(long, default_value_t = 500)]
+    http_backoff_ms: u64,
+
+    #[arg(long)]
+    budget_ratio: Option<f64>,
+
+    #[arg(long)]
+    budget_tolerance_bytes: Option<u64>,
+
+    /// Exit code when no log files were parsed, regardless of budgets and findings.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    empty_input_exit: Option<u8>,
+
+    /// Fail when delta_efficiency is below this floor; needs no baseline.
+    #[arg(long)]
+    min_delta_efficiency: Option<f64>,
+
+    #[arg(long)]
+    strict: bool,
+
+    /// With --strict, name every log missing the required counter instead
+    /// of stopping at the first.
+    #[arg(long)]
+    strict_collect_all: bool,
+
+    /// Also analyse in strict mode and record whether strict parsing and the
+    /// budget gates would pass, without changing the exit code.
+    #[arg(long)]
+    preview_strict: bool,
+
+    #[arg(long, default_value = "patchwaste-out")]
+    out: PathBuf,
+
+    /// Write outputs to `<out>/<run-id>/` so parallel jobs sharing `--out`
+    /// don't clobber each other; `auto` picks a unique id. The directory is
+    /// printed on stdout as `run_dir=<path>`.
+    #[arg(long, value_parser = parse_run_id)]
+    run_id: Option<String>,
+
+    #[arg(long)]
+    sha: Option<String>,
+
+    #[arg(long)]
+    branch: Option<String>,
+
+    #[arg(long)]
+    build_id: Option<String>,
+
+    /// Base commit of the change; recorded with the head sha as `compare_range`.
+    #[arg(long)]
+    base_sha: Option<String>,
+
+    /// Diff link template for `compare_range`, with `{base}` and `{head}` placeholders.
+    #[arg(long)]
+    compare_url_template: Option<String>,
+
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(long, default_value = "json")]
+    output_format: OutputFormat,
+
+    #[arg(long)]
+    pr_comment: Option<PathBuf>,
+
+    #[arg(long)]
+    explain_budget: bool,
+
+    /// Print a `<format>=<path>` line on stdout for every file written.
+    #[arg(long)]
+    print_paths: bool,
+
+    /// Print the report value at this JSON pointer (e.g. `/metrics/waste_ratio`)
+    /// on stdout instead of the metrics line; repeatable, one value per line.
+    #[arg(long = "select", value_name = "JSON_POINTER")]
+    select: Vec<String>,
+
+    /// What `--select` does with a pointer that matches nothing.
+    #[arg(long, default_value = "empty", requires = "select")]
+    select_missing: SelectMissingArg,
+
+    /// Show a boxed summary instead of the plain report on stderr, when stderr
+    /// is a terminal.
+    #[arg(long)]
+    tui: bool,
+
+    /// Print time spent walking, parsing, computing metrics, running rules and
+    /// rendering to stderr.
+    #[arg(long)]
+    profile: bool,
+
+    /// Include the counters as parsed, before fallbacks, in report.json.
+    #[arg(long)]
+    raw_counters: bool,
+
+    /// Treat each log without a depot id as its own depot, named by file, and
+    /// sum depots into the totals.
+    #[arg(long)]
+    root_logs_as_depots: bool,
+
+    /// Memory-map logs of at least this many bytes rather than reading them
+    /// into memory; helps with multi-gigabyte combined logs.
+    #[arg(long)]
+    mmap_min_bytes: Option<u64>,
+
+    #[arg(long, default_value = "new_bytes")]
+    baseline_metric: MetricArg,
+
+    #[arg(long)]
+    canonical_json: bool,
+
+    /// Leave output files untouched when their contents would not change;
+    /// report.json is compared ignoring volatile fields such as the build id.
+    #[arg(long)]
+    skip_unchanged: bool,
+
+    /// report.json schema version; fields added after it are omitted.
+    #[arg(long, default_value = "1.1.0")]
+    format_version: FormatVersionArg,
+
+    #[arg(long)]
+    max_offender_path_len: Option<usize>,
+
+    /// Keep only the N largest depots by new_bytes in the report.
+    #[arg(long)]
+    max_depots: Option<usize>,
+
+    #[arg(long)]
+    no_per_depot: bool,
+
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    #[arg(long, requires = "history")]
+    record_history: bool,
+
+    /// Derive the baseline from `--history`: the last run, the minimum, or the mean.
+    #[arg(
+        long,
+        requires = "history",
+        conflicts_with_all = ["baseline", "baseline_url", "baseline_dir", "baseline_inline", "baseline_auto"]
+    )]
+    baseline_strategy: Option<BaselineStrategyArg>,
+
+    /// Only the last N history entries feed `--baseline-strategy`.
+    #[arg(long, requires = "baseline_strategy")]
+    baseline_window: Option<usize>,
+
+    /// `<testsuite name>` in report.xml; defaults to `--report-title`, then "patchwaste".
+    #[arg(long)]
+    junit_suite_name: Option<String>,
+
+    /// Top heading of report.md, replacing "patchwaste report".
+    #[arg(long)]
+    report_title: Option<String>,
+
+    /// Round floats in report.json to this many decimal places.
+    #[arg(long)]
+    float_decimals: Option<u32>,
+
+    /// Prefix for the testcase classnames in report.xml.
+    #[arg(long, default_value = "patchwaste")]
+    junit_classname_prefix: String,
+
+    #[arg(long)]
+    collapsible_markdown: bool,
+
+    /// Colour finding severities in report.md with inline HTML.
+    #[arg(long)]
+    markdown_colour: bool,
+
+    /// Also show the baseline regression in basis points in report.md.
+    #[arg(long)]
+    regression_bps: bool,
+
+    #[arg(long)]
+    clean_out: bool,
+
+    #[arg(long)]
+    fail_on_confidence: Option<ConfidenceArg>,
+
+    #[arg(long)]
+    export_offenders: Option<OffenderExport>,
+
+    /// Also write the baseline comparison and build metadata alone to
+    /// comparison.json.
+    #[arg(long)]
+    baseline_output: bool,
+
+    #[arg(long, default_value = "auto")]
+    input_encoding: EncodingArg,
+
+    #[arg(long)]
+    path_root: Option<PathBuf>,
+
+    /// Label recorded as the input path in outputs instead of the scanned
+    /// `--input` directory; log sources under it are relabelled too.
+    #[arg(long, value_name = "LABEL")]
+    input_root: Option<String>,
 
-        #[arg(long)]
-        baseline: Option<PathBuf>,
+    #[arg(long)]
+    counter_merge: Option<CounterMergeArg>,
 
-        #[arg(long)]
-        budget_ratio: Option<f64>,
+    /// Digit-group separators in logged byte counts; `eu` reads `1.234.567`.
+    #[arg(long)]
+    digit_separators: Option<DigitSeparatorsArg>,
 
-        #[arg(long)]
-        strict: bool,
+    #[arg(long)]
+    depot_configs: bool,
 
-        #[arg(long, default_value = "patchwaste-out")]
-        out: PathBuf,
+    #[arg(long, value_parser = parse_min_confidence)]
+    min_confidence: Vec<(String, ConfidenceLevel)>,
 
-        #[arg(long)]
-        sha: Option<String>,
+    #[arg(long = "include-depot")]
+    include_depots: Vec<String>,
 
-        #[arg(long)]
-        branch: Option<String>,
+    #[arg(long = "exclude-depot")]
+    exclude_depots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum SelectMissingArg {
+    /// Print an empty line.
+    Empty,
+    /// Fail the run.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum FormatVersionArg {
+    #[value(name = "1.0.0")]
+    V1_0_0,
+    #[value(name = "1.1.0")]
+    V1_1_0,
+}
+
+impl From<FormatVersionArg> for FormatVersion {
+    fn from(v: FormatVersionArg) -> Self {
+        match v {
+            FormatVersionArg::V1_0_0 => FormatVersion::V1_0_0,
+            FormatVersionArg::V1_1_0 => FormatVersion::V1_1_0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum MetricArg {
+    #[value(name = "new_bytes")]
+    NewBytes,
+    #[value(name = "changed_content_bytes")]
+    ChangedContentBytes,
+}
+
+impl From<MetricArg> for BaselineMetric {
+    fn from(m: MetricArg) -> Self {
+        match m {
+            MetricArg::NewBytes => BaselineMetric::NewBytes,
+            MetricArg::ChangedContentBytes => BaselineMetric::ChangedContentBytes,
+        }
+    }
+}
 
-        #[arg(long)]
-        build_id: Option<String>,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum CounterMergeArg {
+    LastWins,
+    Sum,
+    Max,
+}
+
+impl From<CounterMergeArg> for CounterMerge {
+    fn from(m: CounterMergeArg) -> Self {
+        match m {
+            CounterMergeArg::LastWins => CounterMerge::LastWins,
+            CounterMergeArg::Sum => CounterMerge::Sum,
+            CounterMergeArg::Max => CounterMerge::Max,
+        }
+    }
+}
 
-        #[arg(long)]
-        config: Option<PathBuf>,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum DigitSeparatorsArg {
+    Default,
+    Eu,
+}
 
-        #[arg(long, default_value = "json")]
-        output_format: OutputFormat,
-    },
+impl From<DigitSeparatorsArg> for DigitSeparators {
+    fn from(d: DigitSeparatorsArg) -> Self {
+        match d {
+            DigitSeparatorsArg::Default => DigitSeparators::Default,
+            DigitSeparatorsArg::Eu => DigitSeparators::Eu,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum EncodingArg {
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Windows1252,
+}
+
+impl From<EncodingArg> for InputEncoding {
+    fn from(e: EncodingArg) -> Self {
+        match e {
+            EncodingArg::Auto => InputEncoding::Auto,
+            EncodingArg::Utf8 => InputEncoding::Utf8,
+            EncodingArg::Utf16le => InputEncoding::Utf16Le,
+            EncodingArg::Utf16be => InputEncoding::Utf16Be,
+            EncodingArg::Windows1252 => InputEncoding::Windows1252,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum OffenderExport {
+    Csv,
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum ConfidenceArg {
+    Low,
+    Medium,
+}
+
+impl From<ConfidenceArg> for ConfidenceLevel {
+    fn from(c: ConfidenceArg) -> Self {
+        match c {
+            ConfidenceArg::Low => ConfidenceLevel::Low,
+            ConfidenceArg::Medium => ConfidenceLevel::Medium,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum BaselineOrderArg {
+    Mtime,
+    FilenameDate,
+}
+
+impl From<BaselineOrderArg> for BaselineOrder {
+    fn from(o: BaselineOrderArg) -> Self {
+        match o {
+            BaselineOrderArg::Mtime => BaselineOrder::Mtime,
+            BaselineOrderArg::FilenameDate => BaselineOrder::FilenameDate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum BaselineStrategyArg {
+    Last,
+    Min,
+    Mean,
+}
+
+impl From<BaselineStrategyArg> for BaselineStrategy {
+    fn from(s: BaselineStrategyArg) -> Self {
+        match s {
+            BaselineStrategyArg::Last => BaselineStrategy::Last,
+            BaselineStrategyArg::Min => BaselineStrategy::Min,
+            BaselineStrategyArg::Mean => BaselineStrategy::Mean,
+        }
+    }
 }
 
 #[derive(Debug, Clone, clap::ValueEnum)]
 enum OutputFormat {
     Json,
     Junit,
+    /// findings.csv only.
+    FindingsCsv,
+    /// report.env only, `PATCHWASTE_*=value` lines for `source`.
+    Env,
+    /// report.sarif only, findings as SARIF 2.1.0 for code scanning.
+    Sarif,
     All,
 }
 
@@ -103,31 +571,15 @@ fn main() -> std::process::ExitCode {
     let cli = Cli::parse();
 
     let res = match cli.cmd {
-        Commands::Analyse {
-            input,
-            baseline,
-            budget_ratio,
-            strict,
-            out,
-            sha,
-            branch,
-            build_id,
-            config,
-            output_format,
-        } => {
-            let cfg = load_config(config.as_deref());
-            let budget_ratio = budget_ratio.or(cfg.budget_ratio);
-            let strict = strict || cfg.strict.unwrap_or(false);
-            let build_metadata = resolve_build_metadata(sha, branch, build_id);
-            run_analyse(
-                &input,
-                baseline.as_deref(),
-                budget_ratio,
-                strict,
-                &out,
-                build_metadata,
-                &output_format,
-            )
+        Commands::Analyse(args) => {
+            let cfg = load_config(args.config.as_deref());
+            run_analyse(&args, &cfg)
+        }
+        Commands::Merge(args) => run_merge(&args),
+        Commands::Diff(args) => run_diff(&args),
+        Commands::Baseline(BaselineCommand::Capture(args)) => {
+            let cfg = load_config(args.config.as_deref());
+            run_capture(&args, &cfg)
         }
     };
 
@@ -147,6 +599,85 @@ fn main() -> std::process::ExitCode {
     }
 }
 
+/// Parses `--min-confidence <metric>=<low|medium|high>`.
+fn parse_min_confidence(s: &str) -> Result<(String, ConfidenceLevel), String> {
+    let (metric, level) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected <metric>=<level>, got {s:?}"))?;
+    if !matches!(metric, "new_bytes" | "changed_content_bytes") {
+        return Err(format!(
+            "unknown metric {metric:?} (expected new_bytes or changed_content_bytes)"
+        ));
+    }
+    let level = match level {
+        "low" => ConfidenceLevel::Low,
+        "medium" => ConfidenceLevel::Medium,
+        "high" => ConfidenceLevel::High,
+        other => return Err(format!("unknown confidence level {other:?}")),
+    };
+    Ok((metric.to_string(), level))
+}
+
+/// Depot ids from the CLI, falling back to the config list when none were given.
+fn depot_list(cli: &[String], cfg: &[String]) -> Vec<String> {
+    if cli.is_empty() {
+        cfg.to_vec()
+    } else {
+        cli.to_vec()
+    }
+}
+
+fn run_merge(args: &MergeArgs) -> anyhow::Result<std::process::ExitCode> {
+    let s = style();
+
+    print_banner();
+
+    let mut reports = Vec::new();
+    for path in &args.reports {
+        let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        let report: Report = serde_json::from_slice(&bytes)
+            .with_context(|| format!("parse report {}", path.display()))?;
+        reports.push((merge::report_label(path), report));
+    }
+    let report = merge::merge_reports(&reports, args.metrics.into());
+
+    let out = args.out.as_path();
+    std::fs::create_dir_all(out).with_context(|| format!("create out dir {}", out.display()))?;
+    let sink = FileSink::new(out);
+    let json = serde_json::to_vec_pretty(&report).context("serialize report json")?;
+    sink.write("report.json", &json)?;
+    sink.write("report.md", report.to_markdown().as_bytes())?;
+
+    print_report(&report, out, &WasteBands::default());
+
+    let exit = match &report.budget {
+        Some(b) if !b.pass => {
+            eprintln!(
+                "  {red}{bold}BUDGET FAILED{reset}  {dim}({}){reset}",
+                b.reason,
+                red = s.red,
+                bold = s.bold,
+                dim = s.dim,
+                reset = s.reset,
+            );
+            std::process::ExitCode::from(2)
+        }
+        _ => {
+            eprintln!(
+                "  {green}{bold}PASS{reset}",
+                green = s.green,
+                bold = s.bold,
+                reset = s.reset
+            );
+            std::process::ExitCode::from(0)
+        }
+    };
+
+    eprintln!();
+
+    Ok(exit)
+}
+
 fn print_banner() {
     let s = style();
     eprintln!(
@@ -158,14 +689,12 @@ fn print_banner() {
     );
 }
 
-fn waste_colour(ratio: f64) -> &'static str {
+fn waste_colour(ratio: f64, bands: &WasteBands) -> &'static str {
     let s = style();
-    if ratio < 0.3 {
-        s.green
-    } else if ratio < 0.5 {
-        s.yellow
-    } else {
-        s.red
+    match bands.band(ratio) {
+        WasteBand::Green => s.green,
+        WasteBand::Amber => s.yellow,
+        WasteBand::Red => s.red,
     }
 }
 
@@ -178,6 +707,50 @@ fn severity_colour(sev: &Severity) -> &'static str {
     }
 }
 
+/// The report as report.json holds it: pinned to `version`, optionally
+/// canonicalised and with floats rounded.
+fn report_json_value(
+    report: &Report,
+    version: FormatVersion,
+    float_decimals: Option<u32>,
+    canonical: bool,
+) -> anyhow::Result<serde_json::Value> {
+    let mut pinned = report.clone();
+    if canonical {
+        pinned.canonicalize();
+    }
+    let mut value = pinned
+        .to_json_value(version)
+        .context("serialize report json")?;
+    if let Some(decimals) = float_decimals {
+        round_floats(&mut value, decimals);
+    }
+    Ok(value)
+}
+
+fn print_profile(t: &Timings, render: Duration) {
+    let s = style();
+    let phases = [
+        ("walk", t.walk),
+        ("parse", t.parse),
+        ("metrics", t.metrics),
+        ("rules", t.rules),
+        ("render", render),
+    ];
+
+    eprintln!();
+    eprintln!("  {bold}profile{reset}", bold = s.bold, reset = s.reset);
+    for (phase, elapsed) in phases {
+        eprintln!(
+            "  {dim}{:<8}{reset}{:>10.3}ms",
+            phase,
+            elapsed.as_secs_f64() * 1000.0,
+            dim = s.dim,
+            reset = s.reset
+        );
+    }
+}
+
 fn commas(n: u64) -> String {
     let s = n.to_string();
     let bytes = s.as_bytes();
@@ -191,20 +764,22 @@ fn commas(n: u64) -> String {
     result
 }
 
-fn print_report(report: &Report, out: &Path) {
+fn print_report(report: &Report, out: &Path, bands: &WasteBands) {
     let s = style();
-    let wc = waste_colour(report.metrics.waste_ratio);
+    let wc = waste_colour(report.metrics.waste_ratio, bands);
 
     eprintln!(
-        "  {dim}new_bytes             {reset}{bold}{}{reset}",
+        "  {dim}new_bytes             {reset}{bold}{}{reset} {dim}({}){reset}",
         commas(report.metrics.new_bytes),
+        human_bytes(report.metrics.new_bytes),
         dim = s.dim,
         bold = s.bold,
         reset = s.reset
     );
     eprintln!(
-        "  {dim}changed_content_bytes {reset}{bold}{}{reset}",
+        "  {dim}changed_content_bytes {reset}{bold}{}{reset} {dim}({}){reset}",
         commas(report.metrics.changed_content_bytes),
+        human_bytes(report.metrics.changed_content_bytes),
         dim = s.dim,
         bold = s.bold,
         reset = s.reset
@@ -225,6 +800,18 @@ fn print_report(report: &Report, out: &Path) {
         reset = s.reset
     );
 
+    if let Some((depot, ratio)) = &report.metrics_extra.worst_depot_waste {
+        eprintln!(
+            "  {dim}worst_depot_waste     {reset}{wc}{bold}{:.3}{reset} {dim}(depot {}){reset}",
+            ratio,
+            depot,
+            dim = s.dim,
+            wc = waste_colour(*ratio, bands),
+            bold = s.bold,
+            reset = s.reset
+        );
+    }
+
     if !report.findings.is_empty() {
         eprintln!();
         for f in &report.findings {
@@ -255,8 +842,125 @@ fn print_report(report: &Report, out: &Path) {
     eprintln!();
 }
 
+/// Inner width of the `--tui` box, in characters.
+const TUI_WIDTH: usize = 58;
+
+fn tui_lines(report: &Report, bands: &WasteBands) -> Vec<String> {
+    let m = &report.metrics;
+    let grade = match bands.band(m.waste_ratio) {
+        WasteBand::Green => "GREEN",
+        WasteBand::Amber => "AMBER",
+        WasteBand::Red => "RED",
+    };
+    let mut lines = vec![
+        format!("new_bytes              {}", human_bytes(m.new_bytes)),
+        format!(
+            "changed_content_bytes  {}",
+            human_bytes(m.changed_content_bytes)
+        ),
+        format!("waste_ratio            {:.3}  {grade}", m.waste_ratio),
+    ];
+    if let Some(b) = &report.budget {
+        lines.push(format!(
+            "budget                 {}",
+            if b.pass { "PASS" } else { "FAIL" }
+        ));
+    }
+    if !report.offenders.is_empty() {
+        lines.push(String::new());
+        lines.push("top offenders".to_string());
+        for o in report.offenders.iter().take(3) {
+            lines.push(format!("  {:>10}  {}", human_bytes(o.bytes), o.path));
+        }
+    }
+    lines.push(String::new());
+    if report.findings.is_empty() {
+        lines.push("no findings".to_string());
+    } else {
+        lines.push(format!("findings ({})", report.findings.len()));
+        for f in &report.findings {
+            lines.push(format!("  {:<6}  {}", format!("{:?}", f.severity), f.id));
+        }
+    }
+    lines
+}
+
+/// Draws `lines` in a rounded box, cutting lines longer than `width`.
+fn render_box(lines: &[String], width: usize) -> String {
+    let title = " patchwaste ";
+    let mut s = format!(
+        "\u{256d}\u{2500}{title}{}\u{256e}\n",
+        "\u{2500}".repeat(width + 1 - title.chars().count())
+    );
+    for line in lines {
+        let cut: String = line.chars().take(width).collect();
+        let pad = width - cut.chars().count();
+        s.push_str(&format!("\u{2502} {cut}{} \u{2502}\n", " ".repeat(pad)));
+    }
+    s.push_str(&format!(
+        "\u{2570}{}\u{256f}\n",
+        "\u{2500}".repeat(width + 2)
+    ));
+    s
+}
+
+fn budget_explanation(report: &Report) -> Vec<String> {
+    if let Some(b) = report.budget.as_ref().filter(|b| b.policy.is_some()) {
+        let mut lines: Vec<String> = b.clauses.iter().map(|c| c.reason.clone()).collect();
+        lines.push(format!(
+            "result              = {} ({})",
+            if b.pass { "PASS" } else { "FAIL" },
+            b.reason
+        ));
+        return lines;
+    }
+    let (Some(cmp), Some(b)) = (&report.baseline_comparison, &report.budget) else {
+        return vec!["budget gate not evaluated (needs --baseline and --budget-ratio)".to_string()];
+    };
+    let ratio = b.regression_ratio.unwrap_or_default();
+    let threshold = b.threshold_regression_ratio.unwrap_or_default();
+    vec![
+        format!("baseline_new_bytes  = {}", cmp.baseline_new_bytes),
+        format!("current_new_bytes   = {}", report.metrics.new_bytes),
+        format!(
+            "regression_ratio    = {} / {} = {:.3}",
+            report.metrics.new_bytes, cmp.baseline_new_bytes, ratio
+        ),
+        format!("threshold           = {threshold:.3}"),
+        match b.tolerance_bytes {
+            Some(t) => format!("tolerance           = {} bytes", t),
+            None => "tolerance           = none".to_string(),
+        },
+        format!(
+            "result              = {} ({:.3} {} {:.3}{})",
+            if b.pass { "PASS" } else { "FAIL" },
+            ratio,
+            if ratio <= threshold { "<=" } else { ">" },
+            threshold,
+            if b.pass && ratio > threshold {
+                ", within tolerance"
+            } else {
+                ""
+            }
+        ),
+    ]
+}
+
+fn print_budget_explanation(report: &Report) {
+    let s = style();
+    eprintln!(
+        "  {bold}budget explanation{reset}",
+        bold = s.bold,
+        reset = s.reset
+    );
+    for line in budget_explanation(report) {
+        eprintln!("  {dim}{}{reset}", line, dim = s.dim, reset = s.reset);
+    }
+    eprintln!();
+}
+
 fn load_config(path: Option<&Path>) -> Config {
-    match path {
+    let cfg = match path {
         Some(p) => Config::load(p).unwrap_or_else(|e| {
             eprintln!(
                 "{}{}warning:{} failed to load config {}: {}",
@@ -268,8 +972,29 @@ fn load_config(path: Option<&Path>) -> Config {
             );
             Config::default()
         }),
-        None => Config::discover().unwrap_or_default(),
+        None => Config::discover()
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "{}{}warning:{} failed to load discovered config: {:#}",
+                    style().bold,
+                    style().yellow,
+                    style().reset,
+                    e
+                );
+                None
+            })
+            .unwrap_or_default(),
+    };
+    for warning in cfg.validate() {
+        eprintln!(
+            "{}{}warning:{} config: {}",
+            style().bold,
+            style().yellow,
+            style().reset,
+            warning
+        );
     }
+    cfg
 }
 
 fn resolve_build_metadata(
@@ -299,72 +1024,572 @@ fn resolve_build_metadata(
     })
 }
 
-fn run_analyse(
-    input: &Path,
-    baseline: Option<&Path>,
-    budget_ratio: Option<f64>,
-    strict: bool,
-    out: &Path,
-    build_metadata: Option<BuildMetadata>,
-    output_format: &OutputFormat,
-) -> anyhow::Result<std::process::ExitCode> {
+fn parse_run_id(s: &str) -> Result<String, String> {
+    let ok = !s.is_empty()
+        && s != "."
+        && s != ".."
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if ok {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "run id {s:?} must be letters, digits, '-', '_' or '.', and not a path"
+        ))
+    }
+}
+
+/// Unique enough for concurrent jobs on one machine or runner: the time in
+/// nanoseconds and the process id.
+fn auto_run_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("run-{nanos:x}-{:x}", std::process::id())
+}
+
+/// Files `analyse` may write into `--out`; `--clean-out` removes only these.
+const OUTPUT_FILES: &[&str] = &[
+    "report.json",
+    "report.md",
+    "report.xml",
+    "offenders.csv",
+    "offenders.ndjson",
+    "findings.csv",
+    "report.env",
+    "report.sarif",
+    "comparison.json",
+];
+
+/// One line per pointer: strings unquoted, anything else as compact JSON.
+fn select_values(
+    value: &serde_json::Value,
+    pointers: &[String],
+    missing: SelectMissingArg,
+) -> anyhow::Result<Vec<String>> {
+    pointers
+        .iter()
+        .map(|ptr| match (value.pointer(ptr), missing) {
+            (Some(serde_json::Value::String(s)), _) => Ok(s.clone()),
+            (Some(v), _) => Ok(v.to_string()),
+            (None, SelectMissingArg::Empty) => Ok(String::new()),
+            (None, SelectMissingArg::Error) => {
+                anyhow::bail!("--select {ptr:?} matches nothing in the report")
+            }
+        })
+        .collect()
+}
+
+fn clean_out_dir(out: &Path) -> anyhow::Result<()> {
+    for name in OUTPUT_FILES {
+        let path = out.join(name);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("remove {}", path.display())),
+        }
+    }
+    Ok(())
+}
+
+fn run_diff(args: &DiffArgs) -> anyhow::Result<std::process::ExitCode> {
+    let s = style();
+    let load = |path: &Path| -> anyhow::Result<Report> {
+        let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        serde_json::from_slice(&bytes).with_context(|| format!("parse report {}", path.display()))
+    };
+    let diff = load(&args.base)?.diff(&load(&args.head)?);
+
+    eprintln!();
+    for m in &diff.metrics {
+        let regressed = m.regressed(args.threshold);
+        let (base, head, delta) = if m.metric == "waste_ratio" {
+            (
+                format!("{:.3}", m.base),
+                format!("{:.3}", m.head),
+                format!("{:+.3}", m.head - m.base),
+            )
+        } else {
+            (
+                commas(m.base as u64),
+                commas(m.head as u64),
+                format!("{:+}", m.head as i64 - m.base as i64),
+            )
+        };
+        let change = m
+            .change
+            .map(|c| format!(", {:+.1}%", c * 100.0))
+            .unwrap_or_default();
+        eprintln!(
+            "  {dim}{:<22}{reset}{base} -> {colour}{bold}{head}{reset} {dim}({delta}{change}){reset}",
+            m.metric,
+            colour = if regressed { s.red } else { "" },
+            dim = s.dim,
+            bold = s.bold,
+            reset = s.reset,
+        );
+    }
+    for id in &diff.findings_added {
+        eprintln!("  {}+ {id}{}", s.red, s.reset);
+    }
+    for id in &diff.findings_removed {
+        eprintln!("  {}- {id}{}", s.green, s.reset);
+    }
+    eprintln!();
+
+    let regressions = diff.regressions(args.threshold);
+    if regressions.is_empty() {
+        return Ok(std::process::ExitCode::from(0));
+    }
+    let names: Vec<&str> = regressions.iter().map(|m| m.metric.as_str()).collect();
+    eprintln!(
+        "  {red}{bold}REGRESSED{reset}  {dim}({} beyond {:.1}%){reset}",
+        names.join(", "),
+        args.threshold * 100.0,
+        red = s.red,
+        bold = s.bold,
+        dim = s.dim,
+        reset = s.reset,
+    );
+    eprintln!();
+    Ok(std::process::ExitCode::from(2))
+}
+
+/// A sink over `path`'s directory and the file name to write there, for
+/// outputs named by a flag rather than placed in `--out`.
+fn sink_for_file(path: &Path) -> anyhow::Result<(FileSink, String)> {
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => Ok((FileSink::new(dir), name.to_string_lossy().into_owned())),
+        _ => anyhow::bail!("invalid output path {}", path.display()),
+    }
+}
+
+/// Analysis options taken from `cfg` alone. `analyse` layers its flags on
+/// top and `baseline capture` uses them as they are, so a captured baseline
+/// is computed the same way as the runs later compared against it.
+fn config_options(cfg: &Config) -> anyhow::Result<AnalyseOptions> {
+    Ok(AnalyseOptions {
+        strict: cfg.strict.unwrap_or(false),
+        budget_ratio: cfg.budget_ratio,
+        compare_url_template: cfg.compare_url_template.clone(),
+        rule_thresholds: cfg.rule_thresholds(),
+        depot_classes: cfg.depot_classes(),
+        sidecar_name: cfg
+            .sidecar_name
+            .clone()
+            .unwrap_or_else(|| AnalyseOptions::default().sidecar_name),
+        max_offender_path_len: cfg.max_offender_path_len,
+        max_depots: cfg.max_depots,
+        per_depot: cfg.per_depot.unwrap_or(true),
+        counter_patterns: cfg.counter_patterns()?,
+        counter_merge: cfg.counter_merge.unwrap_or_default(),
+        digit_separators: cfg.digit_separators.unwrap_or_default(),
+        include_depots: depot_list(&[], &cfg.include_depots),
+        exclude_depots: depot_list(&[], &cfg.exclude_depots),
+        budget_tolerance_bytes: cfg.budget_tolerance_bytes,
+        depot_budgets: cfg.depot_budgets.clone(),
+        min_confidence: cfg.min_confidence.clone(),
+        min_delta_efficiency: cfg.min_delta_efficiency,
+        budget_expression: cfg.budget_expression.clone(),
+        depot_id_pattern: cfg.depot_id_pattern()?,
+        strict_collect_all: cfg.strict_collect_all.unwrap_or(false),
+        accepted_findings: cfg.accepted.clone(),
+        ..AnalyseOptions::default()
+    })
+}
+
+fn run_capture(args: &CaptureArgs, cfg: &Config) -> anyhow::Result<std::process::ExitCode> {
+    if args.out.exists() && !args.force {
+        anyhow::bail!(
+            "{} already exists; pass --force to overwrite it",
+            args.out.display()
+        );
+    }
+
+    let opts = AnalyseOptions {
+        run_rules: false,
+        ..config_options(cfg)?
+    };
+    let report = patchwaste_core::analyse_dir(&args.input, opts)?;
+
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs());
+    let json = serde_json::to_vec_pretty(&report.to_baseline_json(created_at))
+        .context("serialize baseline json")?;
+    let (sink, name) = sink_for_file(&args.out)?;
+    sink.write(&name, &json)?;
+
+    eprintln!(
+        "captured new_bytes={} into {}",
+        report.metrics.new_bytes,
+        args.out.display()
+    );
+    Ok(std::process::ExitCode::SUCCESS)
+}
+
+fn run_analyse(args: &AnalyseArgs, cfg: &Config) -> anyhow::Result<std::process::ExitCode> {
     let s = style();
 
     print_banner();
 
+    let out_dir = match &args.run_id {
+        Some(id) if id == "auto" => args.out.join(auto_run_id()),
+        Some(id) => args.out.join(id),
+        None => args.out.clone(),
+    };
+    let out = out_dir.as_path();
+    let budget_ratio = args.budget_ratio.or(cfg.budget_ratio);
+    let strict = args.strict || cfg.strict.unwrap_or(false);
+    let report_title = args
+        .report_title
+        .clone()
+        .or_else(|| cfg.report_title.clone());
+    let float_decimals = args.float_decimals.or(cfg.float_decimals);
+    let build_metadata =
+        resolve_build_metadata(args.sha.clone(), args.branch.clone(), args.build_id.clone());
+
+    let baseline_path = match &args.baseline_dir {
+        Some(dir) => {
+            let branch = build_metadata.as_ref().and_then(|m| m.branch.as_deref());
+            match baseline::for_branch_in_dir(dir, branch) {
+                Some(path) => Some(path),
+                None => Some(baseline::latest_in_dir(
+                    dir,
+                    args.baseline_dir_order.into(),
+                )?),
+            }
+        }
+        None if args.baseline_auto => Some(out.join("report.json")).filter(|p| p.is_file()),
+        None => args.baseline.clone(),
+    };
+
+    let base = config_options(cfg)?;
+    let mut min_confidence = base.min_confidence.clone();
+    for (metric, level) in &args.min_confidence {
+        match metric.as_str() {
+            "new_bytes" => min_confidence.new_bytes = Some(*level),
+            _ => min_confidence.changed_content_bytes = Some(*level),
+        }
+    }
+
     let opts = AnalyseOptions {
         strict,
         budget_ratio,
-        baseline_path: baseline.map(|p| p.to_path_buf()),
+        baseline_path,
+        baseline_metric: args.baseline_metric.into(),
+        baseline_url: args.baseline_url.clone(),
+        baseline_inline: args.baseline_inline.clone(),
+        http_retry: RetryPolicy {
+            retries: args.http_retries,
+            backoff: std::time::Duration::from_millis(args.http_backoff_ms),
+            ..RetryPolicy::default()
+        },
         build_metadata,
-        ..AnalyseOptions::default()
+        base_sha: args.base_sha.clone(),
+        compare_url_template: args
+            .compare_url_template
+            .clone()
+            .or_else(|| base.compare_url_template.clone()),
+        max_offender_path_len: args.max_offender_path_len.or(base.max_offender_path_len),
+        max_depots: args.max_depots.or(base.max_depots),
+        per_depot: !args.no_per_depot && base.per_depot,
+        history_path: args.history.clone(),
+        baseline_strategy: args.baseline_strategy.map(BaselineStrategy::from),
+        baseline_window: args.baseline_window,
+        counter_merge: args
+            .counter_merge
+            .map(CounterMerge::from)
+            .unwrap_or(base.counter_merge),
+        digit_separators: args
+            .digit_separators
+            .map(DigitSeparators::from)
+            .unwrap_or(base.digit_separators),
+        input_encoding: args.input_encoding.into(),
+        path_root: args.path_root.clone(),
+        input_root: args.input_root.clone(),
+        include_depots: depot_list(&args.include_depots, &cfg.include_depots),
+        exclude_depots: depot_list(&args.exclude_depots, &cfg.exclude_depots),
+        budget_tolerance_bytes: args.budget_tolerance_bytes.or(base.budget_tolerance_bytes),
+        min_confidence,
+        depot_configs: args.depot_configs,
+        raw_counters: args.raw_counters,
+        root_logs_as_depots: args.root_logs_as_depots,
+        min_delta_efficiency: args.min_delta_efficiency.or(base.min_delta_efficiency),
+        strict_collect_all: args.strict_collect_all || base.strict_collect_all,
+        mmap_min_bytes: args.mmap_min_bytes,
+        ..base
     };
 
-    let mut report = analyse_dir(input, opts)?;
-    report.inputs.input_path = input.display().to_string();
+    let preview_opts = args.preview_strict.then(|| AnalyseOptions {
+        strict: true,
+        ..opts.clone()
+    });
+    let (mut report, timings) = match (&args.input, &args.input_url) {
+        (Some(input), _) if input.as_os_str() == "-" => {
+            analyse_reader_timed(&mut std::io::stdin().lock(), opts)?
+        }
+        (Some(input), _) => analyse_dir_timed(input, opts)?,
+        (None, Some(url)) => analyse_url_timed(url, opts)?,
+        (None, None) => anyhow::bail!("one of --input or --input-url is required"),
+    };
+    if let Some(preview_opts) = preview_opts {
+        let strict = match (&args.input, &args.input_url) {
+            (Some(input), _) if input.as_os_str() == "-" => None,
+            (Some(input), _) => Some(analyse_dir(input, preview_opts)),
+            (None, Some(url)) => Some(analyse_url(url, preview_opts)),
+            (None, None) => None,
+        };
+        match strict {
+            Some(result) => report.strict_preview = Some(StrictPreview::from_result(&result)),
+            None => eprintln!(
+                "{}{}warning:{} --preview-strict cannot re-read stdin; preview skipped",
+                s.bold, s.yellow, s.reset
+            ),
+        }
+    }
+    let render_start = Instant::now();
 
     std::fs::create_dir_all(out).with_context(|| format!("create out dir {}", out.display()))?;
+    if args.clean_out {
+        clean_out_dir(out)?;
+    }
 
-    let write_json_md = matches!(output_format, OutputFormat::Json | OutputFormat::All);
-    let write_junit = matches!(output_format, OutputFormat::Junit | OutputFormat::All);
+    let write_json_md = matches!(args.output_format, OutputFormat::Json | OutputFormat::All);
+    let write_junit = matches!(args.output_format, OutputFormat::Junit | OutputFormat::All);
+    let write_findings_csv = matches!(
+        args.output_format,
+        OutputFormat::FindingsCsv | OutputFormat::All
+    );
+    let write_env = matches!(args.output_format, OutputFormat::Env | OutputFormat::All);
+    let write_sarif = matches!(args.output_format, OutputFormat::Sarif | OutputFormat::All);
 
-    if write_json_md {
-        let json_path = out.join("report.json");
-        let md_path = out.join("report.md");
+    let sink = FileSink::new(out).skip_unchanged(args.skip_unchanged);
+    let mut written: Vec<(&str, PathBuf)> = Vec::new();
+    if args.run_id.is_some() {
+        // Always shown: an `auto` id can't be known any other way.
+        println!("run_dir={}", out.display());
+    }
 
-        let json = serde_json::to_vec_pretty(&report).context("serialize report json")?;
-        std::fs::write(&json_path, json)
-            .with_context(|| format!("write {}", json_path.display()))?;
+    if write_json_md {
+        let version = FormatVersion::from(args.format_version);
+        let via_value = version != FormatVersion::default() || float_decimals.is_some();
+        let previous = (args.skip_unchanged && !via_value)
+            .then(|| std::fs::read(sink.path("report.json")).ok())
+            .flatten()
+            .filter(|old| report.same_as_json(old))
+            .and_then(|old| serde_json::from_slice::<Report>(&old).ok());
+        if let Some(previous) = &previous {
+            // Keep the previous file so downstream steps see no change, and
+            // render the other outputs with its volatile fields so they stay
+            // byte-identical too and agree with it.
+            report.adopt_volatile_fields(previous);
+        } else if via_value {
+            let value = report_json_value(&report, version, float_decimals, args.canonical_json)?;
+            let json = serde_json::to_vec_pretty(&value).context("serialize report json")?;
+            sink.write("report.json", &json)?;
+        } else if args.canonical_json {
+            let json = report
+                .to_canonical_json()
+                .context("serialize report json")?;
+            sink.write("report.json", &json)?;
+        } else {
+            sink.write_with("report.json", &mut |w| Ok(report.write_json(w)?))?;
+        }
 
-        let md = report.to_markdown();
-        std::fs::write(&md_path, md).with_context(|| format!("write {}", md_path.display()))?;
+        let md = report.to_markdown_with(&MarkdownOptions {
+            collapsible: args.collapsible_markdown,
+            severity_colour: args.markdown_colour,
+            regression_bps: args.regression_bps,
+            title: report_title
+                .clone()
+                .unwrap_or_else(|| MarkdownOptions::default().title),
+            ..MarkdownOptions::default()
+        });
+        sink.write("report.md", md.as_bytes())?;
+        written.push(("report_json", sink.path("report.json")));
+        written.push(("report_md", sink.path("report.md")));
     }
 
     if write_junit {
-        let xml_path = out.join("report.xml");
-        let xml = report.to_junit_xml();
-        std::fs::write(&xml_path, xml).with_context(|| format!("write {}", xml_path.display()))?;
+        let xml = report.to_junit_xml_with(&JunitOptions {
+            suite_name: args
+                .junit_suite_name
+                .clone()
+                .or_else(|| report_title.clone())
+                .unwrap_or_else(|| "patchwaste".to_string()),
+            classname_prefix: args.junit_classname_prefix.clone(),
+        });
+        sink.write("report.xml", xml.as_bytes())?;
+        written.push(("report_xml", sink.path("report.xml")));
+    }
+
+    if write_findings_csv {
+        sink.write("findings.csv", report.findings_csv().as_bytes())?;
+        written.push(("findings_csv", sink.path("findings.csv")));
+    }
+
+    if write_env {
+        sink.write("report.env", report.to_env().as_bytes())?;
+        written.push(("report_env", sink.path("report.env")));
+    }
+
+    if write_sarif {
+        let sarif =
+            serde_json::to_vec_pretty(&report.to_sarif()).context("serialize report sarif")?;
+        sink.write("report.sarif", &sarif)?;
+        written.push(("report_sarif", sink.path("report.sarif")));
+    }
+
+    if let Some(format) = args.export_offenders {
+        let (key, name, contents) = match format {
+            OffenderExport::Csv => ("offenders_csv", "offenders.csv", report.offenders_csv()),
+            OffenderExport::Ndjson => (
+                "offenders_ndjson",
+                "offenders.ndjson",
+                report
+                    .offenders_ndjson()
+                    .context("serialize offenders ndjson")?,
+            ),
+        };
+        sink.write(name, contents.as_bytes())?;
+        written.push((key, sink.path(name)));
     }
 
+    if args.baseline_output {
+        match report.comparison_json() {
+            Some(value) => {
+                let json =
+                    serde_json::to_vec_pretty(&value).context("serialize comparison json")?;
+                sink.write("comparison.json", &json)?;
+                written.push(("comparison_json", sink.path("comparison.json")));
+            }
+            None => eprintln!(
+                "{}{}warning:{} --baseline-output without a baseline; comparison.json not written",
+                style().bold,
+                style().yellow,
+                style().reset
+            ),
+        }
+    }
+
+    if let (true, Some(history_path)) = (args.record_history, &args.history) {
+        history::append(history_path, &HistoryEntry::from_report(&report))?;
+    }
+
+    if let Some(comment_path) = &args.pr_comment {
+        let (comment_sink, name) = sink_for_file(comment_path)?;
+        comment_sink
+            .skip_unchanged(args.skip_unchanged)
+            .write(&name, report.to_pr_comment().as_bytes())?;
+        written.push(("pr_comment", comment_path.clone()));
+    }
+
+    let render = render_start.elapsed();
+
     // Machine-parseable line on stdout
-    println!(
-        "new_bytes={} changed_content_bytes={} waste_ratio={:.3}",
-        report.metrics.new_bytes, report.metrics.changed_content_bytes, report.metrics.waste_ratio
-    );
+    if args.select.is_empty() {
+        println!(
+            "new_bytes={} changed_content_bytes={} waste_ratio={:.3}",
+            report.metrics.new_bytes,
+            report.metrics.changed_content_bytes,
+            report.metrics.waste_ratio
+        );
+    } else {
+        // Select from report.json as written, not the in-memory report.
+        let value = report_json_value(
+            &report,
+            FormatVersion::from(args.format_version),
+            float_decimals,
+            args.canonical_json,
+        )?;
+        for line in select_values(&value, &args.select, args.select_missing)? {
+            println!("{line}");
+        }
+    }
+    if args.print_paths {
+        for (key, path) in &written {
+            println!("{key}={}", path.display());
+        }
+    }
 
     // Human-readable output on stderr
-    print_report(&report, out);
+    let bands = cfg.waste_bands.unwrap_or_default();
+    if args.tui && std::io::stderr().is_terminal() {
+        eprint!("{}", render_box(&tui_lines(&report, &bands), TUI_WIDTH));
+    } else {
+        print_report(&report, out, &bands);
+    }
+
+    if args.explain_budget {
+        print_budget_explanation(&report);
+    }
+
+    if args.profile {
+        print_profile(&timings, render);
+    }
+
+    // ConfidenceLevel orders High < Medium < Low, so "at or below" is `>=`.
+    let overall = report.confidence.overall;
+    let low_confidence = args
+        .fail_on_confidence
+        .is_some_and(|level| overall >= ConfidenceLevel::from(level));
+
+    let empty_input_exit = args
+        .empty_input_exit
+        .filter(|_| report.inputs.sources.is_empty());
 
     let exit = match &report.budget {
+        _ if empty_input_exit.is_some() => {
+            eprintln!(
+                "  {red}{bold}NO INPUT FILES{reset}  {dim}(no logs parsed under the input){reset}",
+                red = s.red,
+                bold = s.bold,
+                dim = s.dim,
+                reset = s.reset,
+            );
+            std::process::ExitCode::from(empty_input_exit.unwrap_or(0))
+        }
         Some(b) if !b.pass => {
+            let detail = match (b.regression_ratio, b.threshold_regression_ratio) {
+                (Some(ratio), Some(threshold)) if b.policy.is_none() => {
+                    format!("{ratio:.2}x > {threshold:.2}x budget")
+                }
+                _ => b.failure_detail(),
+            };
             eprintln!(
-                "  {red}{bold}BUDGET FAILED{reset}  {dim}({:.2}x > {:.2}x budget){reset}",
-                report
-                    .baseline_comparison
-                    .as_ref()
-                    .map(|c| c.regression_ratio)
-                    .unwrap_or(0.0),
-                b.threshold_regression_ratio,
+                "  {red}{bold}BUDGET FAILED{reset}  {dim}({detail}){reset}",
+                red = s.red,
+                bold = s.bold,
+                dim = s.dim,
+                reset = s.reset,
+            );
+            std::process::ExitCode::from(2)
+        }
+        _ if report.efficiency_floor.as_ref().is_some_and(|e| !e.pass) => {
+            let e = report.efficiency_floor.as_ref().unwrap();
+            eprintln!(
+                "  {red}{bold}EFFICIENCY FLOOR FAILED{reset}  {dim}({:.3} < {:.3} floor){reset}",
+                e.delta_efficiency,
+                e.min_delta_efficiency,
+                red = s.red,
+                bold = s.bold,
+                dim = s.dim,
+                reset = s.reset,
+            );
+            std::process::ExitCode::from(2)
+        }
+        _ if !report.budget_passed() => {
+            let failed: Vec<&str> = report
+                .per_depot
+                .iter()
+                .filter(|d| d.budget.as_ref().is_some_and(|b| !b.pass))
+                .map(|d| d.depot_id.as_str())
+                .collect();
+            eprintln!(
+                "  {red}{bold}DEPOT BUDGET FAILED{reset}  {dim}(depots {}){reset}",
+                failed.join(", "),
                 red = s.red,
                 bold = s.bold,
                 dim = s.dim,
@@ -372,6 +1597,17 @@ fn run_analyse(
             );
             std::process::ExitCode::from(2)
         }
+        _ if low_confidence => {
+            eprintln!(
+                "  {red}{bold}LOW CONFIDENCE{reset}  {dim}(overall {:?} is too low to trust the gate){reset}",
+                overall,
+                red = s.red,
+                bold = s.bold,
+                dim = s.dim,
+                reset = s.reset,
+            );
+            std::process::ExitCode::from(3)
+        }
         _ => {
             eprintln!(
                 "  {green}{bold}PASS{reset}",
@@ -393,6 +1629,30 @@ mod tests {
     use super::*;
     use serial_test::serial;
 
+    #[test]
+    fn render_box_pads_and_cuts_lines_to_width() {
+        let lines = vec!["short".to_string(), "x".repeat(40)];
+        let drawn = render_box(&lines, 20);
+        let widths: Vec<usize> = drawn.lines().map(|l| l.chars().count()).collect();
+        assert_eq!(widths, [24, 24, 24, 24]);
+        assert!(drawn.starts_with("\u{256d}\u{2500} patchwaste "));
+        assert!(drawn.contains(&format!("\u{2502} {} \u{2502}", "x".repeat(20))));
+    }
+
+    #[test]
+    fn config_options_carry_parse_settings_from_config() {
+        let cfg = Config {
+            max_depots: Some(3),
+            per_depot: Some(false),
+            include_depots: vec!["12345".to_string()],
+            ..Config::default()
+        };
+        let opts = config_options(&cfg).unwrap();
+        assert_eq!(opts.max_depots, Some(3));
+        assert!(!opts.per_depot);
+        assert_eq!(opts.include_depots, ["12345"]);
+    }
+
     #[test]
     fn commas_formats_numbers() {
         assert_eq!(commas(0), "0");
@@ -403,9 +1663,10 @@ mod tests {
     #[test]
     #[serial]
     fn waste_colour_thresholds() {
-        assert_eq!(waste_colour(0.1), style().green);
-        assert_eq!(waste_colour(0.4), style().yellow);
-        assert_eq!(waste_colour(0.8), style().red);
+        let bands = WasteBands::default();
+        assert_eq!(waste_colour(0.1, &bands), style().green);
+        assert_eq!(waste_colour(0.4, &bands), style().yellow);
+        assert_eq!(waste_colour(0.8, &bands), style().red);
     }
 
     #[test]
@@ -27,6 +27,37 @@ fn cli_analyse_writes_reports_and_exits_0_without_budget() {
     assert!(std::path::Path::new("patchwaste-out-test/report.md").exists());
 }
 
+#[test]
+fn cli_analyse_warns_when_patchwaste_config_fails_to_load() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("patchwaste-bad-config-{nonce}"));
+    fs::create_dir_all(&dir).unwrap();
+    let bad = dir.join("bad.toml");
+    fs::write(&bad, "budget_ratio = \"not a number\"\n").unwrap();
+
+    for path in [bad, dir.join("missing.toml")] {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.env("PATCHWASTE_CONFIG", &path).args([
+            "analyse",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--out",
+            dir.join("out").to_str().unwrap(),
+        ]);
+        cmd.assert().success().stderr(
+            predicate::str::contains("failed to load discovered config")
+                .and(predicate::str::contains("PATCHWASTE_CONFIG=")),
+        );
+    }
+
+    let _ = fs::remove_dir_all(dir);
+}
+
 #[test]
 fn cli_analyse_exits_2_when_budget_fails() {
     let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -57,6 +88,254 @@ fn cli_analyse_exits_2_when_budget_fails() {
     let _ = fs::remove_file(baseline_path);
 }
 
+#[test]
+fn cli_analyse_accepts_inline_baseline() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-inline-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--baseline-inline",
+        r#"{"new_bytes":1000}"#,
+        "--output-format",
+        "json",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let comparison = &report["baseline_comparison"];
+    assert_eq!(comparison["baseline_new_bytes"], 1000);
+    let ratio = comparison["regression_ratio"].as_f64().unwrap();
+    assert!((ratio - 12_345.678).abs() < 1e-6, "ratio={ratio}");
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_writes_standalone_comparison() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-comparison-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--baseline-inline",
+        r#"{"new_bytes":1000}"#,
+        "--baseline-output",
+        "--sha",
+        "abc123",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("comparison.json")).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let comparison = &value["baseline_comparison"];
+    assert!(comparison["regression_ratio"].is_number());
+    assert!(comparison["delta_new_bytes"].is_number());
+    assert_eq!(value["build_metadata"]["sha"], "abc123");
+    assert!(value.get("findings").is_none());
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_records_compare_range() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-range-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--sha",
+        "def456",
+        "--base-sha",
+        "abc123",
+        "--compare-url-template",
+        "https://example.com/compare/{base}...{head}",
+        "--output-format",
+        "json",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let range = &report["compare_range"];
+    assert_eq!(range["base"], "abc123");
+    assert_eq!(range["head"], "def456");
+    assert_eq!(range["url"], "https://example.com/compare/abc123...def456");
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_format_version_1_0_0_omits_newer_fields() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-version-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--format-version",
+        "1.0.0",
+        "--output-format",
+        "json",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(report["report_version"], "1.0.0");
+    assert!(report.get("offenders").is_none());
+    assert!(report["metrics"].get("new_bytes").is_some());
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_empty_input_uses_configured_exit_code() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let input_dir = format!("patchwaste-in-empty-{nonce}");
+    let out_dir = format!("patchwaste-out-empty-{nonce}");
+    fs::create_dir_all(&input_dir).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args(["analyse", "--input", &input_dir, "--out", &out_dir]);
+    cmd.assert().code(0);
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        &input_dir,
+        "--empty-input-exit",
+        "2",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("NO INPUT FILES"));
+
+    let _ = fs::remove_dir_all(&input_dir);
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_baseline_auto_compares_to_previous_run() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-auto-{nonce}");
+    let report_path = std::path::Path::new(&out_dir).join("report.json");
+    let run = || {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args([
+            "analyse",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--baseline-auto",
+            "--output-format",
+            "json",
+            "--out",
+            &out_dir,
+        ]);
+        cmd.assert().success();
+        let json = fs::read_to_string(&report_path).unwrap();
+        serde_json::from_str::<serde_json::Value>(&json).unwrap()
+    };
+
+    let first = run();
+    assert!(first["baseline_comparison"].is_null());
+
+    let second = run();
+    assert_eq!(
+        second["baseline_comparison"]["baseline_new_bytes"],
+        12345678
+    );
+    assert_eq!(second["baseline_comparison"]["regression_ratio"], 1.0);
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_explains_failing_budget() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let baseline_path = std::env::temp_dir().join(format!("patchwaste-explain-{nonce}.json"));
+
+    fs::write(&baseline_path, r#"{"metrics":{"new_bytes":1000}}"#).unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--baseline",
+        baseline_path.to_str().unwrap(),
+        "--budget-ratio",
+        "1.25",
+        "--explain-budget",
+        "--out",
+        "patchwaste-out-test",
+    ]);
+
+    cmd.assert()
+        .code(2)
+        .stderr(predicate::str::contains("baseline_new_bytes  = 1000"))
+        .stderr(predicate::str::contains("12345.678"))
+        .stderr(predicate::str::contains("FAIL"));
+
+    let _ = fs::remove_file(baseline_path);
+}
+
 #[test]
 fn cli_analyse_writes_junit_xml_when_requested() {
     let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -94,14 +373,733 @@ fn cli_analyse_writes_junit_xml_when_requested() {
 }
 
 #[test]
-fn cli_analyse_errors_on_missing_input() {
+fn cli_analyse_uses_report_title_for_headings() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-title-{nonce}");
+
     let mut cmd = cargo_bin_cmd!("patchwaste");
     cmd.args([
         "analyse",
         "--input",
-        "does-not-exist",
+        fixture_path.to_str().unwrap(),
+        "--output-format",
+        "all",
+        "--report-title",
+        "Nightly depot waste",
         "--out",
-        "patchwaste-out-test",
+        &out_dir,
     ]);
-    cmd.assert().failure().code(1);
+
+    cmd.assert().success();
+
+    let out_path = std::path::Path::new(&out_dir);
+    let md = fs::read_to_string(out_path.join("report.md")).unwrap();
+    assert!(md.starts_with("# Nightly depot waste\n"));
+    let xml = fs::read_to_string(out_path.join("report.xml")).unwrap();
+    assert!(xml.contains(r#"<testsuite name="Nightly depot waste""#));
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_writes_sourceable_env_file() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-env-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--output-format",
+        "all",
+        "--out",
+        &out_dir,
+    ]);
+
+    cmd.assert().success();
+
+    let out_path = std::path::Path::new(&out_dir);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(out_path.join("report.json")).unwrap()).unwrap();
+    let env = fs::read_to_string(out_path.join("report.env")).unwrap();
+    let ratio: f64 = env
+        .lines()
+        .find_map(|l| l.strip_prefix("PATCHWASTE_WASTE_RATIO="))
+        .expect("PATCHWASTE_WASTE_RATIO line")
+        .parse()
+        .unwrap();
+    assert_eq!(ratio, report["metrics"]["waste_ratio"].as_f64().unwrap());
+    assert!(env
+        .lines()
+        .all(|l| l.starts_with("PATCHWASTE_") && !l.contains(' ')));
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_namespaces_outputs_by_run_id() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-runid-{nonce}");
+
+    for run_id in ["linux-x64", "auto"] {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args([
+            "analyse",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--run-id",
+            run_id,
+            "--print-paths",
+            "--out",
+            &out_dir,
+        ]);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let stdout = String::from_utf8(output).unwrap();
+        let run_dir = stdout
+            .lines()
+            .find_map(|l| l.strip_prefix("run_dir="))
+            .expect("run_dir line");
+        assert_eq!(stdout.matches("run_dir=").count(), 1);
+        let run_dir = std::path::Path::new(run_dir);
+        assert_eq!(run_dir.parent().unwrap(), std::path::Path::new(&out_dir));
+        assert!(run_dir.join("report.json").is_file());
+    }
+
+    // The run directory is printed even without --print-paths.
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--run-id",
+        "windows-x64",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "run_dir={}",
+            std::path::Path::new(&out_dir).join("windows-x64").display()
+        )));
+
+    let out_path = std::path::Path::new(&out_dir);
+    assert!(out_path.join("linux-x64/report.json").is_file());
+    assert!(!out_path.join("report.json").exists());
+    assert_eq!(fs::read_dir(out_path).unwrap().count(), 3);
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--run-id",
+        "../escape",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().failure();
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_tui_falls_back_when_not_a_terminal() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-tui-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.env("NO_COLOR", "1").args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--tui",
+        "--out",
+        &out_dir,
+    ]);
+
+    // stderr is a pipe here, so the plain report is printed instead of the box.
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("waste_ratio"))
+        .stderr(predicate::str::contains('\u{256d}').not())
+        .stderr(predicate::str::contains('\u{1b}').not());
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_clean_out_removes_stale_outputs() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-clean-{nonce}");
+    let out_path = std::path::Path::new(&out_dir);
+    fs::create_dir_all(out_path).unwrap();
+    fs::write(out_path.join("report.xml"), "<stale/>").unwrap();
+    fs::write(out_path.join("notes.txt"), "keep me").unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--output-format",
+        "json",
+        "--clean-out",
+        "--out",
+        &out_dir,
+    ]);
+
+    cmd.assert().success();
+
+    assert!(out_path.join("report.json").exists());
+    assert!(!out_path.join("report.xml").exists());
+    assert!(out_path.join("notes.txt").exists());
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_prints_written_paths() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-paths-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--output-format",
+        "json",
+        "--print-paths",
+        "--out",
+        &out_dir,
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).unwrap();
+    let json_line = stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("report_json="))
+        .expect("report_json line");
+    let written = std::path::Path::new(json_line);
+    assert_eq!(written, std::path::Path::new(&out_dir).join("report.json"));
+    assert!(written.is_file());
+    assert!(!stdout.contains("report_xml="));
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_selects_report_fields_by_json_pointer() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-select-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--select",
+        "/metrics/new_bytes",
+        "--select",
+        "/no/such/field",
+        "--out",
+        &out_dir,
+    ]);
+
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stdout = String::from_utf8(output).unwrap();
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(format!("{out_dir}/report.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        stdout,
+        format!("{}\n\n", report["metrics"]["new_bytes"].as_u64().unwrap())
+    );
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--select",
+        "/no/such/field",
+        "--select-missing",
+        "error",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains("/no/such/field"));
+
+    // Selection reads report.json as written, rounding included.
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--select",
+        "/metrics/waste_ratio",
+        "--float-decimals",
+        "1",
+        "--out",
+        &out_dir,
+    ]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(format!("{out_dir}/report.json")).unwrap())
+            .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!("{}\n", report["metrics"]["waste_ratio"])
+    );
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_profile_prints_phase_timings() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-profile-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--profile",
+        "--out",
+        &out_dir,
+    ]);
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::is_match(r"parse.*[0-9]ms").unwrap())
+        .stderr(predicate::str::contains("render"));
+
+    // Stdin input is timed too.
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args(["analyse", "--input", "-", "--profile", "--out", &out_dir])
+        .write_stdin("PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=500\n");
+
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::is_match(r"parse.*[0-9]ms").unwrap())
+        .stderr(predicate::str::contains("rules"));
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_fails_on_low_confidence_when_requested() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let input_dir = format!("patchwaste-in-pretty-{nonce}");
+    let out_dir = format!("patchwaste-out-pretty-{nonce}");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(
+        std::path::Path::new(&input_dir).join("preview.log"),
+        "Predicted update size: 1,048,576 bytes\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        &input_dir,
+        "--fail-on-confidence",
+        "medium",
+        "--out",
+        &out_dir,
+    ]);
+
+    cmd.assert()
+        .code(3)
+        .stderr(predicate::str::contains("LOW CONFIDENCE"));
+
+    let _ = fs::remove_dir_all(&input_dir);
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_merge_fails_when_any_report_failed_budget() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let root = format!("patchwaste-out-merge-{nonce}");
+    let root_path = std::path::Path::new(&root);
+    fs::create_dir_all(root_path).unwrap();
+    let baseline = root_path.join("baseline.json");
+    fs::write(&baseline, r#"{"metrics":{"new_bytes":1000}}"#).unwrap();
+
+    let big_baseline = root_path.join("big-baseline.json");
+    fs::write(&big_baseline, r#"{"metrics":{"new_bytes":99999999}}"#).unwrap();
+
+    for (platform, base, code) in [("win", &big_baseline, 0), ("linux", &baseline, 2)] {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args([
+            "analyse",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--baseline",
+            base.to_str().unwrap(),
+            "--budget-ratio",
+            "1.25",
+            "--out",
+            root_path.join(platform).to_str().unwrap(),
+        ]);
+        cmd.assert().code(code);
+    }
+
+    let merged = root_path.join("merged");
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "merge",
+        root_path.join("win/report.json").to_str().unwrap(),
+        root_path.join("linux/report.json").to_str().unwrap(),
+        "--out",
+        merged.to_str().unwrap(),
+    ]);
+
+    cmd.assert().code(2);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(merged.join("report.json")).unwrap()).unwrap();
+    assert_eq!(json["budget"]["pass"], false);
+    assert!(json["budget"]["reason"].as_str().unwrap().contains("linux"));
+    assert!(json["findings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|f| f["source_report"] == "win"));
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn cli_baseline_capture_writes_a_loadable_baseline() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let root = format!("patchwaste-out-capture-{nonce}");
+    fs::create_dir_all(&root).unwrap();
+    let baseline = std::path::Path::new(&root).join("baseline.json");
+    let capture = || {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args([
+            "baseline",
+            "capture",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--out",
+            baseline.to_str().unwrap(),
+        ]);
+        cmd
+    };
+
+    capture()
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("new_bytes=12345678"));
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&baseline).unwrap()).unwrap();
+    assert_eq!(json["metrics"]["new_bytes"], 12_345_678);
+    assert_eq!(json["metrics"]["changed_content_bytes"], 2_000_000);
+
+    capture()
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("--force"));
+    capture().arg("--force").assert().success();
+
+    // The captured file works as --baseline: no regression against itself.
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        fixture_path.to_str().unwrap(),
+        "--baseline",
+        baseline.to_str().unwrap(),
+        "--budget-ratio",
+        "1.0",
+        "--out",
+        std::path::Path::new(&root).join("out").to_str().unwrap(),
+    ]);
+    cmd.assert().success();
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn cli_analyse_preview_strict_reports_failure_without_gating() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let input_dir = format!("patchwaste-in-preview-{nonce}");
+    let out_dir = format!("patchwaste-out-preview-{nonce}");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(
+        std::path::Path::new(&input_dir).join("preview.log"),
+        "CHANGED_CONTENT_BYTES=400\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        &input_dir,
+        "--preview-strict",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().code(0);
+
+    let out = std::path::Path::new(&out_dir);
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(out.join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["strict_preview"]["pass"], false);
+    assert!(report["strict_preview"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("PREDICTED_UPDATE_BYTES"));
+    let md = fs::read_to_string(out.join("report.md")).unwrap();
+    assert!(md.contains("## Strict preview\n\n- pass: `false`"));
+
+    let _ = fs::remove_dir_all(&input_dir);
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_diff_prints_deltas_and_fails_past_threshold() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let root = format!("patchwaste-out-diff-{nonce}");
+    let report = |name: &str, log: &str| {
+        let out = std::path::Path::new(&root).join(name);
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args(["analyse", "--input", "-", "--out", out.to_str().unwrap()])
+            .write_stdin(log.to_string());
+        cmd.assert().success();
+        out.join("report.json").to_str().unwrap().to_string()
+    };
+    let base = report(
+        "base",
+        "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=900\n",
+    );
+    let head = report(
+        "head",
+        "PREDICTED_UPDATE_BYTES=1200\nCHANGED_CONTENT_BYTES=300\n",
+    );
+
+    let diff = |threshold: &str| {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.env("NO_COLOR", "1").args([
+            "diff",
+            "--base",
+            &base,
+            "--head",
+            &head,
+            "--threshold",
+            threshold,
+        ]);
+        cmd
+    };
+
+    diff("0.1")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("1,000 -> 1,200"))
+        .stderr(predicate::str::contains("+ HIGH_WASTE_RATIO"))
+        .stderr(predicate::str::contains("REGRESSED"));
+
+    // waste_ratio went from 0.1 to 0.75, so only a huge threshold passes.
+    diff("10").assert().code(0);
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn cli_analyse_errors_on_missing_input() {
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        "does-not-exist",
+        "--out",
+        "patchwaste-out-test",
+    ]);
+    cmd.assert().failure().code(1);
+}
+
+#[test]
+fn cli_analyse_skip_unchanged_keeps_mtimes_on_a_no_op_rerun() {
+    let fixture_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-unchanged-{nonce}");
+    let out_path = std::path::Path::new(&out_dir);
+    let mtime = |name: &str| {
+        fs::metadata(out_path.join(name))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+
+    let run = |build_id: &str| {
+        let mut cmd = cargo_bin_cmd!("patchwaste");
+        cmd.args([
+            "analyse",
+            "--input",
+            fixture_path.to_str().unwrap(),
+            "--skip-unchanged",
+            "--build-id",
+            build_id,
+            "--out",
+            &out_dir,
+        ]);
+        cmd.assert().success();
+    };
+
+    run("1");
+    let (json, md) = (mtime("report.json"), mtime("report.md"));
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    run("1");
+    assert_eq!(mtime("report.json"), json);
+    assert_eq!(mtime("report.md"), md);
+
+    // The build id is volatile: both files stay, still agreeing on it.
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    run("2");
+    assert_eq!(mtime("report.json"), json);
+    assert_eq!(mtime("report.md"), md);
+    let md_text = fs::read_to_string(out_path.join("report.md")).unwrap();
+    assert!(md_text.contains("- build_id: `1`"));
+
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_input_root_labels_the_scanned_directory() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let input_dir = format!("patchwaste-in-root-{nonce}");
+    let out_dir = format!("patchwaste-out-root-{nonce}");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(
+        std::path::Path::new(&input_dir).join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=400\n",
+    )
+    .unwrap();
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args([
+        "analyse",
+        "--input",
+        &input_dir,
+        "--input-root",
+        "BuildOutput",
+        "--out",
+        &out_dir,
+    ]);
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(report["inputs"]["input_path"], "BuildOutput");
+    assert_eq!(
+        report["inputs"]["sources"],
+        serde_json::json!(["BuildOutput/preview.log"])
+    );
+    assert!(!json.contains(&input_dir));
+
+    let _ = fs::remove_dir_all(&input_dir);
+    let _ = fs::remove_dir_all(&out_dir);
+}
+
+#[test]
+fn cli_analyse_reads_a_single_log_from_stdin() {
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let out_dir = format!("patchwaste-out-stdin-{nonce}");
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args(["analyse", "--input", "-", "--out", &out_dir])
+        .write_stdin("PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=400\n");
+    cmd.assert().success();
+
+    let json = fs::read_to_string(std::path::Path::new(&out_dir).join("report.json")).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(report["metrics"]["new_bytes"], 1000);
+    assert_eq!(report["inputs"]["sources"], serde_json::json!(["<stdin>"]));
+
+    let mut cmd = cargo_bin_cmd!("patchwaste");
+    cmd.args(["analyse", "--input", "-", "--strict", "--out", &out_dir])
+        .write_stdin("CHANGED_CONTENT_BYTES=400\n");
+    cmd.assert()
+        .code(1)
+        .stderr(predicate::str::contains("PREDICTED_UPDATE_BYTES"));
+
+    let _ = fs::remove_dir_all(&out_dir);
 }
@@ -1,23 +1,72 @@
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    http::{self, RetryPolicy},
+    types::FileOffender,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Baseline {
     pub baseline_new_bytes: u64,
+    #[serde(default)]
+    pub baseline_changed_content_bytes: u64,
+    #[serde(default)]
+    pub offenders: Vec<FileOffender>,
+    /// new_bytes per depot id, from the baseline report's `per_depot`.
+    #[serde(default)]
+    pub depot_new_bytes: BTreeMap<String, u64>,
+    /// Budget versioned with the baseline, used when neither the CLI nor the
+    /// config sets one.
+    #[serde(default)]
+    pub budget_ratio: Option<f64>,
+    /// When the baseline was captured, as Unix seconds. Read from a top-level
+    /// `created_at` holding Unix seconds or an RFC 3339 UTC timestamp.
+    #[serde(default)]
+    pub created_at: Option<u64>,
 }
 
 impl Baseline {
+    /// Reads a report's `metrics`, or bare top-level `new_bytes` and
+    /// `changed_content_bytes` values when there is no `metrics` object. A
+    /// top-level `budget_ratio` is kept as the baseline's own budget.
     pub fn from_report_json(bytes: &[u8]) -> anyhow::Result<Self> {
         let v: serde_json::Value = serde_json::from_slice(bytes).context("parse json")?;
-        let nb = v
-            .get("metrics")
-            .and_then(|m| m.get("new_bytes"))
-            .and_then(|n| n.as_u64())
-            .unwrap_or(0);
+        let metrics = v.get("metrics").unwrap_or(&v);
+        let metric = |name: &str| metrics.get(name).and_then(|n| n.as_u64()).unwrap_or(0);
+        let offenders = v
+            .get("offenders")
+            .and_then(|o| serde_json::from_value(o.clone()).ok())
+            .unwrap_or_default();
+        let depot_new_bytes = v
+            .get("per_depot")
+            .and_then(|d| d.as_array())
+            .map(|depots| {
+                depots
+                    .iter()
+                    .filter_map(|d| {
+                        let id = d.get("depot_id")?.as_str()?;
+                        let nb = d.get("metrics")?.get("new_bytes")?.as_u64()?;
+                        Some((id.to_string(), nb))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
         Ok(Self {
-            baseline_new_bytes: nb,
+            baseline_new_bytes: metric("new_bytes"),
+            baseline_changed_content_bytes: metric("changed_content_bytes"),
+            offenders,
+            depot_new_bytes,
+            budget_ratio: v.get("budget_ratio").and_then(|r| r.as_f64()),
+            created_at: v.get("created_at").and_then(|c| match c {
+                serde_json::Value::String(s) => parse_rfc3339_utc(s),
+                other => other.as_u64(),
+            }),
         })
     }
 
@@ -25,6 +74,106 @@ impl Baseline {
         let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
         Self::from_report_json(&bytes)
     }
+
+    pub fn fetch_url(url: &str, retry: &RetryPolicy) -> anyhow::Result<Self> {
+        let bytes = http::get(url, retry, u64::MAX)?
+            .with_context(|| format!("baseline at {url} is too large"))?;
+        Self::from_report_json(&bytes)
+    }
+}
+
+static RE_RFC3339: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:[T ](\d{2}):(\d{2}):(\d{2})(?:\.\d+)?(?:Z|[+-]00:?00)?)?$",
+    )
+    .expect("valid regex")
+});
+
+/// Unix seconds for `YYYY-MM-DD[THH:MM:SS[.fff]][Z]`; only UTC offsets are
+/// accepted.
+pub fn parse_rfc3339_utc(s: &str) -> Option<u64> {
+    let c = RE_RFC3339.captures(s.trim())?;
+    let num = |i: usize| c.get(i).map_or(Some(0), |m| m.as_str().parse::<i64>().ok());
+    let (y, m, d) = (num(1)?, num(2)?, num(3)?);
+    let (hh, mm, ss) = (num(4)?, num(5)?, num(6)?);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) || hh > 23 || mm > 59 || ss > 60 {
+        return None;
+    }
+    // Days from civil date (Howard Hinnant's algorithm), proleptic Gregorian.
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    u64::try_from(days * 86_400 + hh * 3600 + mm * 60 + ss).ok()
+}
+
+/// How [`latest_in_dir`] orders the candidate baselines in a directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BaselineOrder {
+    /// Most recently modified file wins.
+    #[default]
+    Mtime,
+    /// Latest `YYYY-MM-DD` or `YYYYMMDD` date in the file name wins.
+    FilenameDate,
+}
+
+static RE_FILENAME_DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\d{4})-?(\d{2})-?(\d{2})").expect("valid regex"));
+
+/// Picks the newest `*.json` baseline in `dir`. Ties are broken by file name,
+/// the lexically greatest winning.
+pub fn latest_in_dir(dir: &Path, order: BaselineOrder) -> anyhow::Result<PathBuf> {
+    if !dir.is_dir() {
+        anyhow::bail!("baseline dir {} does not exist", dir.display());
+    }
+
+    let mut candidates: Vec<(u128, PathBuf)> = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let key = match order {
+            BaselineOrder::Mtime => std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .with_context(|| format!("stat {}", path.display()))?
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0),
+            BaselineOrder::FilenameDate => {
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                RE_FILENAME_DATE
+                    .captures(name)
+                    .and_then(|c| format!("{}{}{}", &c[1], &c[2], &c[3]).parse().ok())
+                    .unwrap_or(0)
+            }
+        };
+        candidates.push((key, path));
+    }
+
+    candidates.sort();
+    candidates
+        .pop()
+        .map(|(_, path)| path)
+        .with_context(|| format!("no *.json baselines in {}", dir.display()))
+}
+
+/// Baselines tried, in order, when the branch has none of its own.
+const BRANCH_FALLBACKS: &[&str] = &["main", "default"];
+
+/// Picks `<dir>/<branch>.json`, then `main.json`, then `default.json`;
+/// `None` when none of them exist. Path separators in the branch name become
+/// `-`, so `feature/login` looks for `feature-login.json`.
+pub fn for_branch_in_dir(dir: &Path, branch: Option<&str>) -> Option<PathBuf> {
+    let branch = branch.map(|b| b.replace(['/', '\\'], "-"));
+    branch
+        .as_deref()
+        .into_iter()
+        .chain(BRANCH_FALLBACKS.iter().copied())
+        .map(|name| dir.join(format!("{name}.json")))
+        .find(|path| path.is_file())
 }
 
 #[cfg(test)]
@@ -37,4 +186,114 @@ mod tests {
         let baseline = Baseline::from_report_json(bytes).unwrap();
         assert_eq!(baseline.baseline_new_bytes, 0);
     }
+
+    #[test]
+    fn created_at_reads_unix_seconds_and_rfc3339() {
+        assert_eq!(parse_rfc3339_utc("1970-01-01"), Some(0));
+        assert_eq!(
+            parse_rfc3339_utc("2024-02-29T12:30:00Z"),
+            Some(1_709_209_800)
+        );
+        assert_eq!(parse_rfc3339_utc("2024-02-29T12:30:00+02:00"), None);
+        assert_eq!(parse_rfc3339_utc("yesterday"), None);
+
+        let b = Baseline::from_report_json(br#"{"created_at":1700000000}"#).unwrap();
+        assert_eq!(b.created_at, Some(1_700_000_000));
+        let b = Baseline::from_report_json(br#"{"created_at":"2000-03-01T00:00:00Z"}"#).unwrap();
+        assert_eq!(b.created_at, Some(951_868_800));
+    }
+
+    #[test]
+    fn latest_in_dir_picks_most_recently_modified() {
+        use std::time::{Duration, SystemTime};
+
+        let dir = tempfile::tempdir().unwrap();
+        let older = dir.path().join("b-older.json");
+        let newer = dir.path().join("a-newer.json");
+        std::fs::write(&older, "{}").unwrap();
+        std::fs::write(&newer, "{}").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let now = SystemTime::now();
+        let set_mtime = |p: &Path, t: SystemTime| {
+            std::fs::File::options()
+                .write(true)
+                .open(p)
+                .unwrap()
+                .set_modified(t)
+                .unwrap();
+        };
+        set_mtime(&older, now - Duration::from_secs(3600));
+        set_mtime(&newer, now);
+
+        let picked = latest_in_dir(dir.path(), BaselineOrder::Mtime).unwrap();
+        assert_eq!(picked, newer);
+
+        assert!(latest_in_dir(&dir.path().join("missing"), BaselineOrder::Mtime).is_err());
+    }
+
+    #[test]
+    fn for_branch_in_dir_prefers_branch_then_main_then_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let pick = |branch: Option<&str>| for_branch_in_dir(dir.path(), branch);
+
+        assert_eq!(pick(Some("feature/login")), None);
+
+        std::fs::write(dir.path().join("default.json"), "{}").unwrap();
+        assert_eq!(
+            pick(Some("feature/login")),
+            Some(dir.path().join("default.json"))
+        );
+
+        std::fs::write(dir.path().join("main.json"), "{}").unwrap();
+        assert_eq!(
+            pick(Some("feature/login")),
+            Some(dir.path().join("main.json"))
+        );
+        assert_eq!(pick(None), Some(dir.path().join("main.json")));
+
+        std::fs::write(dir.path().join("feature-login.json"), "{}").unwrap();
+        assert_eq!(
+            pick(Some("feature/login")),
+            Some(dir.path().join("feature-login.json"))
+        );
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn fetch_url_retries_transient_server_errors() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::time::Duration;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/baseline.json", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let body = r#"{"metrics":{"new_bytes":4242}}"#;
+            for attempt in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                let resp = if attempt < 2 {
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                };
+                stream.write_all(resp.as_bytes()).unwrap();
+            }
+        });
+
+        let retry = RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let baseline = Baseline::fetch_url(&url, &retry).unwrap();
+        assert_eq!(baseline.baseline_new_bytes, 4242);
+        server.join().unwrap();
+    }
 }
@@ -1,18 +1,131 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
+use regex::Regex;
 use serde::Deserialize;
 
+use crate::{
+    parser::{compile_depot_id_pattern, CounterMerge, CounterPatterns, DigitSeparators},
+    report::BudgetExpression,
+    rules::{MinConfidence, RuleThresholds},
+    types::{DepotClass, WasteBands},
+};
+
+pub const CONFIG_FILE_NAME: &str = "patchwaste.toml";
+
 #[derive(Debug, Clone, Deserialize, Default)]
 #[serde(default)]
 pub struct Config {
     pub app_id: Option<u64>,
-    pub depot_ids: Vec<u64>,
+    #[serde(deserialize_with = "depot_id_list")]
+    pub depot_ids: Vec<String>,
     pub branches: Vec<String>,
     pub budget_ratio: Option<f64>,
     pub strict: Option<bool>,
+    pub strict_collect_all: Option<bool>,
     pub depot_budgets: HashMap<String, f64>,
+    /// `[accepted]`: build id to finding ids reviewed and accepted for it.
+    pub accepted: HashMap<String, Vec<String>>,
+    #[serde(deserialize_with = "depot_id_list")]
+    pub code_depots: Vec<String>,
+    #[serde(deserialize_with = "depot_id_list")]
+    pub content_depots: Vec<String>,
+    /// Waste ratio at which `HIGH_WASTE_RATIO` fires; defaults to 0.50.
+    pub waste_ratio_threshold: Option<f64>,
+    pub code_waste_ratio_threshold: Option<f64>,
+    pub content_waste_ratio_threshold: Option<f64>,
+    pub sidecar_name: Option<String>,
+    pub max_offender_path_len: Option<usize>,
+    pub max_depots: Option<usize>,
+    pub per_depot: Option<bool>,
+    pub patterns: PatternsConfig,
+    pub waste_bands: Option<WasteBands>,
+    pub budget_tolerance_bytes: Option<u64>,
+    pub counter_merge: Option<CounterMerge>,
+    pub digit_separators: Option<DigitSeparators>,
+    pub min_confidence: MinConfidence,
+    #[serde(deserialize_with = "depot_id_list")]
+    pub include_depots: Vec<String>,
+    #[serde(deserialize_with = "depot_id_list")]
+    pub exclude_depots: Vec<String>,
+    pub offender_sum_factor: Option<f64>,
+    pub compare_url_template: Option<String>,
+    /// `[min, max]` plausible changed_content_bytes / new_bytes ratio.
+    pub counter_ratio_bounds: Option<(f64, f64)>,
+    pub min_delta_efficiency: Option<f64>,
+    pub report_title: Option<String>,
+    /// Decimal places floats are rounded to in report.json.
+    pub float_decimals: Option<u32>,
+    /// Share of changed_content_bytes at which `LARGE_TOP_OFFENDER` fires.
+    pub top_offender_changed_share: Option<f64>,
+    /// Multiple of the next offender at which `LARGE_TOP_OFFENDER` escalates.
+    pub top_offender_outlier_factor: Option<f64>,
+    pub max_baseline_age_days: Option<u64>,
+    pub efficient_change_growth: Option<f64>,
+    /// `[budget_expression]`: compound budget replacing `budget_ratio`.
+    pub budget_expression: Option<BudgetExpression>,
+    /// Regex whose first capture group is the depot id in log file or
+    /// directory names, replacing the default `\d{5,}`.
+    pub depot_id_pattern: Option<String>,
+}
+
+/// Reads depot ids given as integers or strings, so ids captured by a
+/// custom `depot_id_pattern` can be listed too.
+fn depot_id_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DepotId {
+        Number(u64),
+        Name(String),
+    }
+
+    let ids = Vec::<DepotId>::deserialize(deserializer)?;
+    Ok(ids
+        .into_iter()
+        .map(|id| match id {
+            DepotId::Number(n) => n.to_string(),
+            DepotId::Name(s) => s,
+        })
+        .collect())
+}
+
+/// A config value that parses but is probably a mistake.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigWarning {
+    /// `budget_ratio` is zero or negative, so every patch fails the budget.
+    NonPositiveBudgetRatio(f64),
+    /// A `[depot_budgets]` ratio is zero or negative.
+    NonPositiveDepotBudget { depot_id: String, ratio: f64 },
+    /// `[depot_budgets]` names a depot missing from a non-empty `depot_ids`.
+    UnlistedDepotBudget { depot_id: String },
+}
+
+impl std::fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigWarning::NonPositiveBudgetRatio(ratio) => {
+                write!(f, "budget_ratio = {ratio} is not positive")
+            }
+            ConfigWarning::NonPositiveDepotBudget { depot_id, ratio } => {
+                write!(f, "depot_budgets.{depot_id} = {ratio} is not positive")
+            }
+            ConfigWarning::UnlistedDepotBudget { depot_id } => {
+                write!(f, "depot_budgets.{depot_id} is not listed in depot_ids")
+            }
+        }
+    }
+}
+
+/// `[patterns]` table: extra counter regexes, each with a capture group for the value.
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(default)]
+pub struct PatternsConfig {
+    pub predicted_update_bytes: Vec<String>,
+    pub changed_content_bytes: Vec<String>,
 }
 
 impl Config {
@@ -21,15 +134,169 @@ impl Config {
             std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
         let config: Config =
             toml::from_str(&contents).with_context(|| format!("parse {}", path.display()))?;
+        config
+            .counter_patterns()
+            .with_context(|| format!("invalid [patterns] in {}", path.display()))?;
+        config
+            .depot_id_pattern()
+            .with_context(|| format!("invalid depot_id_pattern in {}", path.display()))?;
         Ok(config)
     }
 
-    pub fn discover() -> Option<Self> {
-        let path = Path::new("patchwaste.toml");
-        if path.exists() {
-            Config::load(path).ok()
-        } else {
-            None
+    /// Checks for values that load fine but are unlikely to be intended.
+    pub fn validate(&self) -> Vec<ConfigWarning> {
+        let mut warnings = Vec::new();
+        if let Some(ratio) = self.budget_ratio.filter(|r| *r <= 0.0) {
+            warnings.push(ConfigWarning::NonPositiveBudgetRatio(ratio));
+        }
+        let mut depots: Vec<_> = self.depot_budgets.iter().collect();
+        depots.sort_by(|a, b| a.0.cmp(b.0));
+        for (depot_id, ratio) in depots {
+            if *ratio <= 0.0 {
+                warnings.push(ConfigWarning::NonPositiveDepotBudget {
+                    depot_id: depot_id.clone(),
+                    ratio: *ratio,
+                });
+            }
+            if !self.depot_ids.is_empty() && !self.depot_ids.iter().any(|id| id == depot_id) {
+                warnings.push(ConfigWarning::UnlistedDepotBudget {
+                    depot_id: depot_id.clone(),
+                });
+            }
+        }
+        warnings
+    }
+
+    pub fn counter_patterns(&self) -> anyhow::Result<CounterPatterns> {
+        CounterPatterns::compile(
+            &self.patterns.predicted_update_bytes,
+            &self.patterns.changed_content_bytes,
+        )
+    }
+
+    pub fn depot_id_pattern(&self) -> anyhow::Result<Option<Regex>> {
+        self.depot_id_pattern
+            .as_deref()
+            .map(compile_depot_id_pattern)
+            .transpose()
+    }
+
+    /// Depot id to class mapping; a depot listed in both tables is treated as code.
+    pub fn depot_classes(&self) -> HashMap<String, DepotClass> {
+        let mut classes = HashMap::new();
+        for id in &self.content_depots {
+            classes.insert(id.clone(), DepotClass::Content);
+        }
+        for id in &self.code_depots {
+            classes.insert(id.clone(), DepotClass::Code);
+        }
+        classes
+    }
+
+    pub fn rule_thresholds(&self) -> RuleThresholds {
+        RuleThresholds {
+            waste_ratio: self
+                .waste_ratio_threshold
+                .unwrap_or(RuleThresholds::default().waste_ratio),
+            code_waste_ratio: self.code_waste_ratio_threshold,
+            content_waste_ratio: self.content_waste_ratio_threshold,
+            waste_bands: self.waste_bands,
+            offender_sum_factor: self
+                .offender_sum_factor
+                .unwrap_or(RuleThresholds::default().offender_sum_factor),
+            counter_ratio_bounds: self
+                .counter_ratio_bounds
+                .unwrap_or(RuleThresholds::default().counter_ratio_bounds),
+            top_offender_changed_share: self.top_offender_changed_share,
+            top_offender_outlier_factor: self
+                .top_offender_outlier_factor
+                .unwrap_or(RuleThresholds::default().top_offender_outlier_factor),
+            max_baseline_age_days: self
+                .max_baseline_age_days
+                .unwrap_or(RuleThresholds::default().max_baseline_age_days),
+            efficient_change_growth: self
+                .efficient_change_growth
+                .unwrap_or(RuleThresholds::default().efficient_change_growth),
+            ..RuleThresholds::default()
+        }
+    }
+
+    /// Finds and loads the config used when `--config` is not given.
+    ///
+    /// Search order:
+    /// 1. the file named by the `PATCHWASTE_CONFIG` environment variable
+    /// 2. `patchwaste.toml` in the current directory, then in each parent
+    ///    directory up to the filesystem root
+    ///
+    /// `Ok(None)` when neither exists; a file that is found but fails to load
+    /// is an error rather than a silent fallback to the defaults.
+    pub fn discover() -> anyhow::Result<Option<Self>> {
+        if let Some(p) = std::env::var_os("PATCHWASTE_CONFIG") {
+            let path = PathBuf::from(p);
+            return Config::load(&path)
+                .map(Some)
+                .with_context(|| format!("PATCHWASTE_CONFIG={}", path.display()));
+        }
+        let Some(path) = std::env::current_dir()
+            .ok()
+            .and_then(|dir| Self::find_upwards(&dir))
+        else {
+            return Ok(None);
+        };
+        Config::load(&path).map(Some)
+    }
+
+    /// Returns the nearest `patchwaste.toml` in `start` or any of its ancestors.
+    pub fn find_upwards(start: &Path) -> Option<PathBuf> {
+        start
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|p| p.is_file())
+    }
+
+    /// `base` with this config's rule thresholds layered on top, for a depot
+    /// config merged under the root config.
+    pub fn overlay_thresholds(&self, base: &RuleThresholds) -> RuleThresholds {
+        RuleThresholds {
+            waste_ratio: self.waste_ratio_threshold.unwrap_or(base.waste_ratio),
+            code_waste_ratio: self.code_waste_ratio_threshold.or(base.code_waste_ratio),
+            content_waste_ratio: self
+                .content_waste_ratio_threshold
+                .or(base.content_waste_ratio),
+            waste_bands: self.waste_bands.or(base.waste_bands),
+            offender_sum_factor: self.offender_sum_factor.unwrap_or(base.offender_sum_factor),
+            counter_ratio_bounds: self
+                .counter_ratio_bounds
+                .unwrap_or(base.counter_ratio_bounds),
+            top_offender_changed_share: self
+                .top_offender_changed_share
+                .or(base.top_offender_changed_share),
+            top_offender_outlier_factor: self
+                .top_offender_outlier_factor
+                .unwrap_or(base.top_offender_outlier_factor),
+            max_baseline_age_days: self
+                .max_baseline_age_days
+                .unwrap_or(base.max_baseline_age_days),
+            efficient_change_growth: self
+                .efficient_change_growth
+                .unwrap_or(base.efficient_change_growth),
+            ..base.clone()
+        }
+    }
+}
+
+/// Loads every `patchwaste.toml` below `root`, excluding `root` itself, as
+/// `(directory, config)` pairs sorted by directory.
+pub fn discover_nested(root: &Path) -> anyhow::Result<Vec<(PathBuf, Config)>> {
+    let mut configs = Vec::new();
+    for entry in walkdir::WalkDir::new(root).min_depth(2).follow_links(false) {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.file_name() == CONFIG_FILE_NAME {
+            let path = entry.path();
+            let config = Config::load(path)?;
+            configs.push((path.parent().unwrap_or(root).to_path_buf(), config));
         }
     }
+    configs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(configs)
 }
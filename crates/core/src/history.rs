@@ -0,0 +1,152 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{baseline::Baseline, report::Report};
+
+/// One prior run. History files are newline-delimited JSON, oldest first; each
+/// line is either a full `report.json` or the compact form written by `append`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryEntry {
+    pub new_bytes: u64,
+    pub changed_content_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_id: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn from_report(report: &Report) -> Self {
+        let meta = report.build_metadata.as_ref();
+        Self {
+            new_bytes: report.metrics.new_bytes,
+            changed_content_bytes: report.metrics.changed_content_bytes,
+            sha: meta.and_then(|m| m.sha.clone()),
+            build_id: meta.and_then(|m| m.build_id.clone()),
+        }
+    }
+
+    fn from_json(v: &serde_json::Value) -> Self {
+        let metrics = v.get("metrics").unwrap_or(v);
+        let num = |name: &str| metrics.get(name).and_then(|n| n.as_u64()).unwrap_or(0);
+        let meta = v.get("build_metadata").unwrap_or(v);
+        let text = |name: &str| meta.get(name).and_then(|s| s.as_str()).map(str::to_string);
+        Self {
+            new_bytes: num("new_bytes"),
+            changed_content_bytes: num("changed_content_bytes"),
+            sha: text("sha"),
+            build_id: text("build_id"),
+        }
+    }
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Vec<HistoryEntry>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let v: serde_json::Value = serde_json::from_str(line)
+                .with_context(|| format!("parse {} line {}", path.display(), i + 1))?;
+            Ok(HistoryEntry::from_json(&v))
+        })
+        .collect()
+}
+
+pub fn append(path: &Path, entry: &HistoryEntry) -> anyhow::Result<()> {
+    let mut f = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("open {}", path.display()))?;
+    let line = serde_json::to_string(entry).context("serialize history entry")?;
+    writeln!(f, "{line}").with_context(|| format!("write {}", path.display()))
+}
+
+/// How [`baseline_from`] turns prior runs into one baseline value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BaselineStrategy {
+    /// The most recent run.
+    #[default]
+    Last,
+    /// The smallest run, so the baseline only ever ratchets down.
+    Min,
+    /// The rounded mean of the runs.
+    Mean,
+}
+
+/// Baseline from the last `window` entries (all when `None`), or `None` for an
+/// empty history. Offender and depot data are not kept in history, so the
+/// baseline carries counters only.
+pub fn baseline_from(
+    entries: &[HistoryEntry],
+    strategy: BaselineStrategy,
+    window: Option<usize>,
+) -> Option<Baseline> {
+    let window = window.unwrap_or(entries.len()).min(entries.len());
+    let recent = &entries[entries.len() - window..];
+    let pick = |value: fn(&HistoryEntry) -> u64| -> Option<u64> {
+        let mut values = recent.iter().map(value);
+        match strategy {
+            BaselineStrategy::Last => values.next_back(),
+            BaselineStrategy::Min => values.min(),
+            BaselineStrategy::Mean => {
+                let n = recent.len() as f64;
+                (n > 0.0).then(|| (values.map(|v| v as f64).sum::<f64>() / n).round() as u64)
+            }
+        }
+    };
+    Some(Baseline {
+        baseline_new_bytes: pick(|e| e.new_bytes)?,
+        baseline_changed_content_bytes: pick(|e| e.changed_content_bytes)?,
+        offenders: Vec::new(),
+        depot_new_bytes: Default::default(),
+        budget_ratio: None,
+        created_at: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(new_bytes: &[u64]) -> Vec<HistoryEntry> {
+        new_bytes
+            .iter()
+            .map(|&n| HistoryEntry {
+                new_bytes: n,
+                changed_content_bytes: n / 2,
+                sha: None,
+                build_id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn baseline_strategies_over_a_fixed_series() {
+        let history = series(&[500, 300, 400, 1000, 600]);
+        let new_bytes = |strategy, window| {
+            baseline_from(&history, strategy, window)
+                .unwrap()
+                .baseline_new_bytes
+        };
+
+        assert_eq!(new_bytes(BaselineStrategy::Last, None), 600);
+        assert_eq!(new_bytes(BaselineStrategy::Min, None), 300);
+        assert_eq!(new_bytes(BaselineStrategy::Mean, None), 560);
+
+        assert_eq!(new_bytes(BaselineStrategy::Last, Some(2)), 600);
+        assert_eq!(new_bytes(BaselineStrategy::Min, Some(3)), 400);
+        assert_eq!(new_bytes(BaselineStrategy::Mean, Some(2)), 800);
+        assert_eq!(new_bytes(BaselineStrategy::Mean, Some(50)), 560);
+
+        let b = baseline_from(&history, BaselineStrategy::Min, None).unwrap();
+        assert_eq!(b.baseline_changed_content_bytes, 150);
+        assert!(baseline_from(&[], BaselineStrategy::Last, None).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use crate::parser::{ParseStats, SkipReason, SkippedFile};
+
+/// Retry behaviour for HTTP fetches. Server errors and transport failures
+/// (timeouts, refused connections) are retried; client errors such as 404 are not.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// Delay before the first retry; doubled on each subsequent attempt, up
+    /// to one minute.
+    pub backoff: Duration,
+    pub timeout: Duration,
+}
+
+/// Upper bound on the delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt + 1`: `backoff * 2^attempt`, capped
+    /// at [`MAX_BACKOFF`] so large retry counts cannot overflow.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(attempt))
+            .map_or(MAX_BACKOFF, |d| d.min(MAX_BACKOFF))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Downloads `url`, or `None` once the body turns out larger than `limit`
+/// bytes; the download stops there rather than reading the rest.
+#[cfg(feature = "http")]
+pub fn get(url: &str, retry: &RetryPolicy, limit: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    use std::io::Read;
+
+    use anyhow::Context;
+
+    let agent = ureq::AgentBuilder::new().timeout(retry.timeout).build();
+    let mut attempt = 0;
+    loop {
+        match agent.get(url).call() {
+            Ok(resp) => {
+                let mut bytes = Vec::new();
+                resp.into_reader()
+                    .take(limit.saturating_add(1))
+                    .read_to_end(&mut bytes)
+                    .with_context(|| format!("read body of {url}"))?;
+                return Ok((bytes.len() as u64 <= limit).then_some(bytes));
+            }
+            Err(e) => {
+                let retryable = match &e {
+                    ureq::Error::Status(code, _) => *code >= 500,
+                    ureq::Error::Transport(_) => true,
+                };
+                if !retryable || attempt >= retry.retries {
+                    return Err(anyhow::Error::new(e)
+                        .context(format!("GET {url} failed after {} attempt(s)", attempt + 1)));
+                }
+                std::thread::sleep(retry.delay(attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "http"))]
+pub fn get(url: &str, _retry: &RetryPolicy, _limit: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    anyhow::bail!("cannot fetch {url}: patchwaste-core was built without the `http` feature")
+}
+
+/// `(url, body)` of each log downloaded by [`fetch_logs`].
+pub type FetchedLogs = Vec<(String, Vec<u8>)>;
+
+/// Fetches a single log, or a JSON index (`["a.log", ...]` or `{"logs": [...]}`)
+/// of log URLs resolved relative to `url`. Like the directory walker, logs
+/// stop counting once `max_total_bytes` would be exceeded: the download that
+/// crosses the cap is cut short, and it and every later log are recorded as
+/// [`SkipReason::ScanLimit`].
+pub fn fetch_logs(
+    url: &str,
+    retry: &RetryPolicy,
+    max_total_bytes: u64,
+) -> anyhow::Result<(FetchedLogs, ParseStats)> {
+    let mut stats = ParseStats::default();
+    let skip = |stats: &mut ParseStats, path: &str| {
+        stats.files_skipped.push(SkippedFile {
+            path: path.to_string(),
+            reason: SkipReason::ScanLimit,
+        })
+    };
+
+    let Some(body) = get(url, retry, max_total_bytes)? else {
+        stats.files_seen = 1;
+        skip(&mut stats, url);
+        return Ok((Vec::new(), stats));
+    };
+    let Some(index) = parse_index(&body) else {
+        stats.files_seen = 1;
+        stats.files_matched = 1;
+        stats.files_parsed = 1;
+        stats.bytes_scanned = body.len() as u64;
+        return Ok((vec![(url.to_string(), body)], stats));
+    };
+
+    stats.files_seen = index.len();
+    stats.files_matched = index.len();
+    let mut logs = Vec::new();
+    let mut limit_reached = false;
+    for entry in index {
+        let log_url = resolve_url(url, &entry);
+        let remaining = max_total_bytes - stats.bytes_scanned;
+        let bytes = if limit_reached {
+            None
+        } else {
+            get(&log_url, retry, remaining)?
+        };
+        let Some(bytes) = bytes else {
+            limit_reached = true;
+            skip(&mut stats, &log_url);
+            continue;
+        };
+        stats.bytes_scanned += bytes.len() as u64;
+        stats.files_parsed += 1;
+        logs.push((log_url, bytes));
+    }
+    Ok((logs, stats))
+}
+
+fn parse_index(body: &[u8]) -> Option<Vec<String>> {
+    let v: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let list = match &v {
+        serde_json::Value::Array(_) => &v,
+        serde_json::Value::Object(o) => o.get("logs")?,
+        _ => return None,
+    };
+    serde_json::from_value(list.clone()).ok()
+}
+
+/// Resolves an index entry against the index URL: absolute URLs are kept,
+/// `/path` entries replace the whole path and anything else replaces the
+/// last path segment.
+fn resolve_url(base: &str, entry: &str) -> String {
+    if entry.contains("://") {
+        return entry.to_string();
+    }
+    let Some(scheme_end) = base.find("://") else {
+        return entry.to_string();
+    };
+    if entry.starts_with("//") {
+        return format!("{}:{entry}", &base[..scheme_end]);
+    }
+    let authority_start = scheme_end + 3;
+    let path_start = base[authority_start..]
+        .find(['/', '?', '#'])
+        .map_or(base.len(), |i| authority_start + i);
+    let origin = &base[..path_start];
+    if entry.starts_with('/') {
+        return format!("{origin}{entry}");
+    }
+    let path = base[path_start..].split(['?', '#']).next().unwrap_or("");
+    let dir = path.rfind('/').map_or("", |i| &path[..i]);
+    format!("{origin}{dir}/{entry}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_and_caps_without_overflow() {
+        let retry = RetryPolicy {
+            backoff: Duration::from_millis(500),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(retry.delay(0), Duration::from_millis(500));
+        assert_eq!(retry.delay(2), Duration::from_secs(2));
+        assert_eq!(retry.delay(32), MAX_BACKOFF);
+        assert_eq!(retry.delay(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn resolve_url_handles_root_index_and_rooted_entries() {
+        for base in ["http://host", "http://host/", "http://host/index.json"] {
+            assert_eq!(resolve_url(base, "a.log"), "http://host/a.log", "{base}");
+            assert_eq!(resolve_url(base, "/logs/a.log"), "http://host/logs/a.log");
+        }
+
+        let base = "https://ci.example/builds/42/index.json?token=x";
+        assert_eq!(
+            resolve_url(base, "depot/a.log"),
+            "https://ci.example/builds/42/depot/a.log"
+        );
+        assert_eq!(resolve_url(base, "/a.log"), "https://ci.example/a.log");
+        assert_eq!(
+            resolve_url(base, "//cdn.example/a.log"),
+            "https://cdn.example/a.log"
+        );
+        assert_eq!(
+            resolve_url(base, "http://other/a.log"),
+            "http://other/a.log"
+        );
+    }
+}
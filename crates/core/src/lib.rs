@@ -1,29 +1,103 @@
 pub mod baseline;
 pub mod config;
+pub mod history;
+pub mod http;
+pub mod merge;
 pub mod parser;
 pub mod report;
 pub mod rules;
+pub mod sink;
+pub mod stats;
 pub mod types;
 
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 
 use crate::{
     baseline::Baseline,
     parser::ParseMode,
-    report::{BudgetResult, DepotReport, Report},
-    rules::run_rules,
-    types::{ConfidenceLevel, Metrics},
+    report::{DepotReport, Report},
+    rules::{
+        run_baseline_rules, run_rules, run_strict_preview_rules, run_trend_rules, RuleThresholds,
+    },
+    types::{BaselineMetric, ConfidenceLevel, DepotClass, Metrics},
 };
 
 #[derive(Debug, Clone)]
 pub struct AnalyseOptions {
     pub strict: bool,
     pub baseline_path: Option<std::path::PathBuf>,
+    /// Takes precedence over a `budget_ratio` carried by the baseline file.
     pub budget_ratio: Option<f64>,
     pub max_total_bytes_scanned: u64,
     pub build_metadata: Option<report::BuildMetadata>,
+    /// Base commit; with `build_metadata.sha` this records a `compare_range`.
+    pub base_sha: Option<String>,
+    /// Diff URL template for `compare_range`, e.g. `.../compare/{base}...{head}`.
+    pub compare_url_template: Option<String>,
+    pub rule_thresholds: RuleThresholds,
+    pub depot_classes: HashMap<String, DepotClass>,
+    pub sidecar_name: String,
+    /// Metric reported in `baseline_comparison`; the budget gate always uses new_bytes.
+    pub baseline_metric: BaselineMetric,
+    pub baseline_url: Option<String>,
+    /// Baseline report JSON given directly, e.g. `{"new_bytes":1000}`.
+    pub baseline_inline: Option<String>,
+    pub http_retry: http::RetryPolicy,
+    pub max_offender_path_len: Option<usize>,
+    pub per_depot: bool,
+    pub history_path: Option<std::path::PathBuf>,
+    pub counter_patterns: parser::CounterPatterns,
+    pub counter_merge: parser::CounterMerge,
+    pub digit_separators: parser::DigitSeparators,
+    pub input_encoding: parser::InputEncoding,
+    /// Store report paths relative to this directory.
+    pub path_root: Option<std::path::PathBuf>,
+    /// Label recorded in place of the scanned input directory, e.g. when CI
+    /// analyses a temporary copy of BuildOutput.
+    pub input_root: Option<String>,
+    pub include_depots: Vec<String>,
+    pub exclude_depots: Vec<String>,
+    /// Growth in new_bytes that passes a budget even above its ratio; applies
+    /// to the aggregate and per-depot budgets alike.
+    pub budget_tolerance_bytes: Option<u64>,
+    pub min_confidence: rules::MinConfidence,
+    /// Budget ratio per depot id; depots without an entry are not gated.
+    pub depot_budgets: HashMap<String, f64>,
+    /// Apply `patchwaste.toml` files found in subdirectories of the input to
+    /// the depots whose logs live under them.
+    pub depot_configs: bool,
+    /// Record the parsed counters, before fallbacks, as `raw_counters`.
+    pub raw_counters: bool,
+    /// Fail the budget when delta_efficiency drops below this, baseline or not.
+    pub min_delta_efficiency: Option<f64>,
+    /// See [`parser::ParseOptions::root_logs_as_depots`].
+    pub root_logs_as_depots: bool,
+    /// Run the rules; when false, `findings` stay empty everywhere and only
+    /// metrics, budgets and baselines are computed.
+    pub run_rules: bool,
+    /// Keep only this many depots, the largest by new_bytes; the rest are
+    /// counted in `depots_omitted`.
+    pub max_depots: Option<usize>,
+    /// Derive the baseline from `history_path` when no other baseline is given.
+    pub baseline_strategy: Option<history::BaselineStrategy>,
+    /// Most recent history entries [`AnalyseOptions::baseline_strategy`] looks
+    /// at; all of them when `None`.
+    pub baseline_window: Option<usize>,
+    /// Replaces the `budget_ratio` gate on the aggregate when set.
+    pub budget_expression: Option<report::BudgetExpression>,
+    /// See [`parser::ParseOptions::depot_id_pattern`].
+    pub depot_id_pattern: Option<regex::Regex>,
+    /// See [`parser::ParseOptions::strict_collect_all`].
+    pub strict_collect_all: bool,
+    /// Finding ids accepted per build id; findings of the current
+    /// `build_metadata.build_id` listed here are demoted.
+    pub accepted_findings: HashMap<String, Vec<String>>,
+    /// See [`parser::ParseOptions::mmap_min_bytes`].
+    pub mmap_min_bytes: Option<u64>,
 }
 
 impl Default for AnalyseOptions {
@@ -34,76 +108,366 @@ impl Default for AnalyseOptions {
             budget_ratio: None,
             max_total_bytes_scanned: 50 * 1024 * 1024,
             build_metadata: None,
+            base_sha: None,
+            compare_url_template: None,
+            rule_thresholds: RuleThresholds::default(),
+            depot_classes: HashMap::new(),
+            sidecar_name: "counters.json".to_string(),
+            baseline_metric: BaselineMetric::NewBytes,
+            baseline_url: None,
+            baseline_inline: None,
+            http_retry: http::RetryPolicy::default(),
+            max_offender_path_len: None,
+            per_depot: true,
+            history_path: None,
+            counter_patterns: parser::CounterPatterns::default(),
+            counter_merge: parser::CounterMerge::LastWins,
+            digit_separators: parser::DigitSeparators::Default,
+            input_encoding: parser::InputEncoding::Auto,
+            path_root: None,
+            input_root: None,
+            include_depots: Vec::new(),
+            exclude_depots: Vec::new(),
+            budget_tolerance_bytes: None,
+            min_confidence: rules::MinConfidence::default(),
+            depot_budgets: HashMap::new(),
+            depot_configs: false,
+            raw_counters: false,
+            min_delta_efficiency: None,
+            root_logs_as_depots: false,
+            run_rules: true,
+            max_depots: None,
+            baseline_strategy: None,
+            baseline_window: None,
+            budget_expression: None,
+            depot_id_pattern: None,
+            strict_collect_all: false,
+            accepted_findings: HashMap::new(),
+            mmap_min_bytes: None,
+        }
+    }
+}
+
+impl AnalyseOptions {
+    fn parse_mode(&self) -> ParseMode {
+        if self.strict {
+            ParseMode::Strict
+        } else {
+            ParseMode::BestEffort
+        }
+    }
+
+    fn parse_options(&self) -> parser::ParseOptions {
+        parser::ParseOptions {
+            mode: self.parse_mode(),
+            max_total_bytes_scanned: self.max_total_bytes_scanned,
+            sidecar_name: self.sidecar_name.clone(),
+            per_depot: self.per_depot,
+            log: parser::LogOptions {
+                patterns: self.counter_patterns.clone(),
+                counter_merge: self.counter_merge,
+                digit_separators: self.digit_separators,
+            },
+            encoding: self.input_encoding,
+            include_depots: self.include_depots.clone(),
+            exclude_depots: self.exclude_depots.clone(),
+            root_logs_as_depots: self.root_logs_as_depots,
+            depot_id_pattern: self.depot_id_pattern.clone(),
+            strict_collect_all: self.strict_collect_all,
+            mmap_min_bytes: self.mmap_min_bytes,
         }
     }
 }
 
 pub fn analyse_dir(input: &Path, opts: AnalyseOptions) -> anyhow::Result<Report> {
-    let parse_mode = if opts.strict {
-        ParseMode::Strict
+    analyse_dir_timed(input, opts).map(|(report, _)| report)
+}
+
+/// Wall-clock time spent in each analysis phase.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// Listing the input directory, expanding the glob or downloading logs.
+    pub walk: Duration,
+    /// Reading, decoding and parsing logs.
+    pub parse: Duration,
+    /// Aggregate and per-depot metrics.
+    pub metrics: Duration,
+    /// Rules, baseline comparison and budgets.
+    pub rules: Duration,
+}
+
+/// Like [`analyse_dir`], also returning how long each phase took.
+pub fn analyse_dir_timed(input: &Path, opts: AnalyseOptions) -> anyhow::Result<(Report, Timings)> {
+    let mut timings = Timings::default();
+    let parse_opts = opts.parse_options();
+    let context = || format!("failed to parse BuildOutput at {}", input.display());
+
+    let start = Instant::now();
+    let files = if parser::is_glob_pattern(input) {
+        parser::glob_files(&input.to_string_lossy(), parse_opts.mode)
+    } else {
+        parser::dir_files(input)
+    }
+    .with_context(context)?;
+    timings.walk = start.elapsed();
+
+    let start = Instant::now();
+    let (parsed, _) = parser::parse_log_files(&files, &parse_opts).with_context(context)?;
+    timings.parse = start.elapsed();
+
+    let depot_configs = if opts.depot_configs && input.is_dir() {
+        config::discover_nested(input).context("failed to load depot configs")?
     } else {
-        ParseMode::BestEffort
+        Vec::new()
     };
 
-    let parsed = parser::parse_buildoutput_dir(input, parse_mode, opts.max_total_bytes_scanned)
-        .with_context(|| format!("failed to parse BuildOutput at {}", input.display()))?;
+    let report = build_report(input, parsed, &depot_configs, opts, &mut timings)?;
+    Ok((report, timings))
+}
+
+/// Analyses logs already held in memory as `(source name, contents)` pairs.
+/// `input` is recorded as the report's input path.
+pub fn analyse_logs(
+    input: &str,
+    logs: &[(String, Vec<u8>)],
+    opts: AnalyseOptions,
+) -> anyhow::Result<Report> {
+    analyse_logs_timed(input, logs, opts, &mut Timings::default())
+}
+
+fn analyse_logs_timed(
+    input: &str,
+    logs: &[(String, Vec<u8>)],
+    opts: AnalyseOptions,
+    timings: &mut Timings,
+) -> anyhow::Result<Report> {
+    let start = Instant::now();
+    let parsed = parser::parse_logs(logs, &opts.parse_options())
+        .with_context(|| format!("failed to parse logs from {input}"))?;
+    timings.parse = start.elapsed();
+    build_report(Path::new(input), parsed, &[], opts, timings)
+}
+
+/// Analyses one log read from `r`, typically stdin, recorded as
+/// [`parser::STDIN_SOURCE`].
+pub fn analyse_reader<R: std::io::BufRead>(
+    r: &mut R,
+    opts: AnalyseOptions,
+) -> anyhow::Result<Report> {
+    analyse_reader_timed(r, opts).map(|(report, _)| report)
+}
+
+/// Like [`analyse_reader`], also returning how long each phase took.
+/// Reading `r` counts as parsing.
+pub fn analyse_reader_timed<R: std::io::BufRead>(
+    r: &mut R,
+    opts: AnalyseOptions,
+) -> anyhow::Result<(Report, Timings)> {
+    let mut timings = Timings::default();
+    let source = parser::STDIN_SOURCE;
+    let start = Instant::now();
+    let parsed = parser::parse_buildoutput_reader(r, source, &opts.parse_options())
+        .with_context(|| format!("failed to parse log from {source}"))?;
+    timings.parse = start.elapsed();
+    let report = build_report(Path::new(source), parsed, &[], opts, &mut timings)?;
+    Ok((report, timings))
+}
+
+/// Fetches a log, or a JSON index listing log URLs, and analyses it in memory.
+/// Downloads count against `max_total_bytes_scanned`.
+pub fn analyse_url(url: &str, opts: AnalyseOptions) -> anyhow::Result<Report> {
+    analyse_url_timed(url, opts).map(|(report, _)| report)
+}
+
+/// Like [`analyse_url`], also returning how long each phase took.
+/// Downloading counts as the walk.
+pub fn analyse_url_timed(url: &str, opts: AnalyseOptions) -> anyhow::Result<(Report, Timings)> {
+    let mut timings = Timings::default();
+    let start = Instant::now();
+    let (logs, _) = http::fetch_logs(url, &opts.http_retry, opts.max_total_bytes_scanned)?;
+    timings.walk = start.elapsed();
+    let report = analyse_logs_timed(url, &logs, opts, &mut timings)?;
+    Ok((report, timings))
+}
+
+/// The config in the deepest directory containing the depot's first log.
+fn nearest_depot_config<'a>(
+    depot: &parser::DepotOutput,
+    configs: &'a [(std::path::PathBuf, config::Config)],
+) -> Option<&'a config::Config> {
+    let source = Path::new(depot.sources.first()?);
+    configs
+        .iter()
+        .filter(|(dir, _)| source.starts_with(dir))
+        .max_by_key(|(dir, _)| dir.components().count())
+        .map(|(_, cfg)| cfg)
+}
 
+fn build_report(
+    input: &Path,
+    mut parsed: parser::ParsedBuildOutput,
+    depot_configs: &[(std::path::PathBuf, config::Config)],
+    opts: AnalyseOptions,
+    timings: &mut Timings,
+) -> anyhow::Result<Report> {
+    let parse_mode = parsed.mode;
+    let start = Instant::now();
     let (metrics, confidence) = compute_metrics(&parsed);
+    let mut depot_metrics: Vec<_> = parsed
+        .per_depot
+        .iter()
+        .map(|d| {
+            let depot_parsed = parser::ParsedBuildOutput {
+                offenders: d.offenders.clone(),
+                offender_tally: d.offender_tally.clone(),
+                offender_bytes: d.offender_bytes,
+                ..parser::ParsedBuildOutput::from_counters(parse_mode, d.counters.clone())
+            };
+            let (metrics, confidence) = compute_metrics(&depot_parsed);
+            (depot_parsed, metrics, confidence)
+        })
+        .collect();
+    let depots_omitted = opts.max_depots.and_then(|max| {
+        let total = depot_metrics.len();
+        let keep = largest_depots(&depot_metrics, max);
+        let mut flags = keep.iter();
+        parsed.per_depot.retain(|_| *flags.next().unwrap());
+        let mut flags = keep.iter();
+        depot_metrics.retain(|_| *flags.next().unwrap());
+        (total > max).then_some(total - max)
+    });
+    timings.metrics = start.elapsed();
+    let start = Instant::now();
 
-    let findings = run_rules(&parsed, &metrics);
+    let history = match &opts.history_path {
+        Some(p) => Some(
+            history::load(p).with_context(|| format!("failed to load history {}", p.display()))?,
+        ),
+        None => None,
+    };
+
+    let mut findings = Vec::new();
+    if opts.run_rules {
+        findings = run_rules(&parsed, &metrics, &opts.rule_thresholds, None);
+        findings.extend(run_strict_preview_rules(&parsed));
+        findings.sort_by(|a, b| a.id.cmp(&b.id));
+
+        if let Some(history) = &history {
+            let mut series: Vec<u64> = history.iter().map(|h| h.new_bytes).collect();
+            series.push(metrics.new_bytes);
+            findings.extend(run_trend_rules(&series, &opts.rule_thresholds));
+            findings.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+    }
 
     let baseline = if let Some(p) = &opts.baseline_path {
         Some(
             Baseline::load_json(p)
                 .with_context(|| format!("failed to load baseline {}", p.display()))?,
         )
+    } else if let Some(url) = &opts.baseline_url {
+        Some(
+            Baseline::fetch_url(url, &opts.http_retry)
+                .with_context(|| format!("failed to fetch baseline {url}"))?,
+        )
+    } else if let Some(json) = &opts.baseline_inline {
+        Some(
+            Baseline::from_report_json(json.as_bytes())
+                .context("failed to parse inline baseline")?,
+        )
+    } else if let Some(strategy) = opts.baseline_strategy {
+        let entries = history
+            .as_deref()
+            .context("a baseline strategy needs a history file")?;
+        history::baseline_from(entries, strategy, opts.baseline_window)
     } else {
         None
     };
 
+    if let Some(b) = baseline.as_ref().filter(|_| opts.run_rules) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        findings.extend(run_baseline_rules(b, &metrics, now, &opts.rule_thresholds));
+        findings.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+    if opts.run_rules {
+        rules::apply_min_confidence(
+            &mut findings,
+            &confidence,
+            &opts.min_confidence,
+            &opts.rule_thresholds,
+        );
+    }
+
     let baseline_comparison = baseline
         .as_ref()
-        .map(|b| report::compare_to_baseline(b, &metrics));
-
-    let budget = match (
-        opts.budget_ratio,
-        baseline.as_ref(),
-        baseline_comparison.as_ref(),
-    ) {
-        (Some(threshold), Some(_), Some(cmp)) => {
-            let pass = cmp.regression_ratio <= threshold;
-            Some(BudgetResult {
-                threshold_regression_ratio: threshold,
-                pass,
-                reason: if pass {
-                    "within regression budget".to_string()
-                } else {
-                    format!(
-                        "regression_ratio {:.3} exceeds threshold {:.3}",
-                        cmp.regression_ratio, threshold
-                    )
-                },
-            })
-        }
+        .map(|b| report::compare_to_baseline(b, &metrics, opts.baseline_metric));
+
+    let budget_ratio = opts
+        .budget_ratio
+        .or_else(|| baseline.as_ref().and_then(|b| b.budget_ratio));
+    let budget = match (&opts.budget_expression, budget_ratio, baseline.as_ref()) {
+        (Some(expr), _, _) => Some(report::evaluate_budget_expression(
+            expr,
+            &metrics,
+            baseline.as_ref(),
+        )),
+        (None, Some(threshold), Some(b)) => Some(report::evaluate_budget(
+            b.baseline_new_bytes,
+            metrics.new_bytes,
+            threshold,
+            opts.budget_tolerance_bytes,
+        )),
         _ => None,
     };
 
     let per_depot: Vec<DepotReport> = parsed
         .per_depot
         .iter()
-        .map(|d| {
-            let depot_parsed = parser::ParsedBuildOutput {
-                mode: parse_mode,
-                counters: d.counters.clone(),
-                offenders: d.offenders.clone(),
-                sources: vec![],
-                per_depot: vec![],
+        .zip(depot_metrics)
+        .map(|(d, (depot_parsed, depot_metrics, depot_confidence))| {
+            let class = opts.depot_classes.get(&d.depot_id).copied();
+            let depot_cfg = nearest_depot_config(d, depot_configs);
+            let thresholds = match depot_cfg {
+                Some(cfg) => cfg.overlay_thresholds(&opts.rule_thresholds),
+                None => opts.rule_thresholds.clone(),
             };
-            let (depot_metrics, depot_confidence) = compute_metrics(&depot_parsed);
+            let mut findings = Vec::new();
+            if opts.run_rules {
+                findings = run_rules(&depot_parsed, &depot_metrics, &thresholds, class);
+                rules::apply_min_confidence(
+                    &mut findings,
+                    &depot_confidence,
+                    &opts.min_confidence,
+                    &thresholds,
+                );
+            }
+            let depot_budget = depot_cfg
+                .and_then(|cfg| cfg.budget_ratio)
+                .or_else(|| opts.depot_budgets.get(&d.depot_id).copied());
+            let budget = depot_budget.and_then(|threshold| {
+                let base = *baseline.as_ref()?.depot_new_bytes.get(&d.depot_id)?;
+                Some(report::evaluate_budget(
+                    base,
+                    depot_metrics.new_bytes,
+                    threshold,
+                    opts.budget_tolerance_bytes,
+                ))
+            });
             DepotReport {
                 depot_id: d.depot_id.clone(),
                 metrics: depot_metrics,
                 confidence: depot_confidence.overall,
+                class,
+                findings,
+                budget,
+                offenders: d
+                    .offenders
+                    .iter()
+                    .take(report::DEPOT_TOP_OFFENDERS)
+                    .cloned()
+                    .collect(),
             }
         })
         .collect();
@@ -118,12 +482,71 @@ pub fn analyse_dir(input: &Path, opts: AnalyseOptions) -> anyhow::Result<Report>
         budget,
         opts.build_metadata,
     );
+    let head = report
+        .build_metadata
+        .as_ref()
+        .and_then(|m| m.sha.as_deref());
+    if let (Some(base), Some(head)) = (opts.base_sha.as_deref(), head) {
+        report.compare_range = Some(report::CompareRange::new(
+            base,
+            head,
+            opts.compare_url_template.as_deref(),
+        ));
+    }
+    report.efficiency_floor = opts
+        .min_delta_efficiency
+        .map(|floor| report::evaluate_efficiency_floor(report.metrics.delta_efficiency, floor));
+    if opts.raw_counters {
+        report.raw_counters = Some(report::RawCounters::from(&parsed.counters));
+    }
     report.inputs.sources = parsed.sources;
+    report.inputs.files_without_counters = parsed.files_without_counters;
+    report.metrics_extra = report::MetricsExtra::from_depots(&per_depot);
+    report.metrics_extra.unclamped_delta_efficiency = report.metrics.unclamped_delta_efficiency();
     report.per_depot = per_depot;
+    report.depots_omitted = depots_omitted;
+    let build_id = report
+        .build_metadata
+        .as_ref()
+        .and_then(|m| m.build_id.clone());
+    if let Some(ids) = build_id.and_then(|id| opts.accepted_findings.get(&id)) {
+        report.accept_findings(ids);
+    }
+    report.offender_stats = parsed.offender_tally.stats();
+    report.offenders = parsed
+        .offenders
+        .into_iter()
+        .take(report::TOP_OFFENDERS)
+        .collect();
+    if let Some(b) = baseline.as_ref().filter(|b| !b.offenders.is_empty()) {
+        report.offender_diff = report::diff_offenders(&b.offenders, &report.offenders);
+    }
+    if let Some(label) = &opts.input_root {
+        report.relabel_input_root(input, label);
+    }
+    if let Some(root) = &opts.path_root {
+        report.relativize_paths(root);
+    }
+    if let Some(max) = opts.max_offender_path_len {
+        report.truncate_offender_paths(max);
+    }
+    timings.rules = start.elapsed();
 
     Ok(report)
 }
 
+/// Per depot, whether it is among the `max` largest by new_bytes. Ties go to
+/// the depot listed first.
+fn largest_depots<T, C>(depots: &[(T, Metrics, C)], max: usize) -> Vec<bool> {
+    let mut order: Vec<usize> = (0..depots.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(depots[i].1.new_bytes));
+    let mut keep = vec![false; depots.len()];
+    for i in order.into_iter().take(max) {
+        keep[i] = true;
+    }
+    keep
+}
+
 pub fn compute_metrics(parsed: &parser::ParsedBuildOutput) -> (Metrics, report::ConfidenceSummary) {
     let mut new_bytes = parsed.counters.predicted_update_bytes;
     let mut changed_content_bytes = parsed.counters.changed_content_bytes;
@@ -188,7 +611,7 @@ pub fn compute_metrics(parsed: &parser::ParsedBuildOutput) -> (Metrics, report::
         new_bytes: new_conf,
         changed_content_bytes: changed_conf,
         delta_efficiency: ConfidenceLevel::Medium,
-        waste_ratio: ConfidenceLevel::Medium,
+        waste_ratio: confidence_overall(new_conf, changed_conf),
         overall: confidence_overall(new_conf, changed_conf),
     };
 
@@ -209,18 +632,19 @@ mod tests {
     use super::*;
     use crate::parser::{ParseMode, ParsedBuildOutput, SteamPipeCounters};
 
+    fn parsed_with(new_bytes: Option<u64>, changed: Option<u64>) -> ParsedBuildOutput {
+        ParsedBuildOutput::from_counters(
+            ParseMode::BestEffort,
+            SteamPipeCounters {
+                predicted_update_bytes: new_bytes,
+                changed_content_bytes: changed,
+            },
+        )
+    }
+
     #[test]
     fn metrics_handle_zero_new_bytes_with_positive_changed_bytes() {
-        let parsed = ParsedBuildOutput {
-            mode: ParseMode::BestEffort,
-            counters: SteamPipeCounters {
-                predicted_update_bytes: Some(0),
-                changed_content_bytes: Some(1024),
-            },
-            offenders: vec![],
-            sources: vec!["x.log".to_string()],
-            per_depot: vec![],
-        };
+        let parsed = parsed_with(Some(0), Some(1024));
 
         let (metrics, confidence) = compute_metrics(&parsed);
 
@@ -229,4 +653,29 @@ mod tests {
         assert!(metrics.delta_efficiency > 0.0);
         assert_eq!(confidence.new_bytes, ConfidenceLevel::Low);
     }
+
+    #[test]
+    fn fallback_branch_emits_fallback_confidence_finding() {
+        let parsed = parsed_with(Some(0), Some(1024));
+
+        let (metrics, _) = compute_metrics(&parsed);
+        let findings = run_rules(&parsed, &metrics, &RuleThresholds::default(), None);
+        let fallback = findings
+            .iter()
+            .find(|f| f.id == "FALLBACK_CONFIDENCE")
+            .expect("FALLBACK_CONFIDENCE finding");
+        assert_eq!(fallback.severity, crate::types::Severity::Low);
+        assert!(fallback.evidence[0].contains("predicted_update_bytes=0"));
+    }
+
+    #[test]
+    fn waste_ratio_confidence_follows_low_inputs() {
+        let parsed = parsed_with(None, None);
+
+        let (_, confidence) = compute_metrics(&parsed);
+
+        assert_eq!(confidence.new_bytes, ConfidenceLevel::Low);
+        assert_eq!(confidence.changed_content_bytes, ConfidenceLevel::Low);
+        assert_eq!(confidence.waste_ratio, ConfidenceLevel::Low);
+    }
 }
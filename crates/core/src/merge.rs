@@ -0,0 +1,241 @@
+use std::path::Path;
+
+use crate::{
+    report::{self, BudgetResult, ConfidenceSummary, Inputs, Report},
+    types::{FileOffender, Metrics},
+};
+
+/// How byte counters from several reports combine into one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetricMerge {
+    /// Add counters, for reports covering disjoint depots or platforms.
+    #[default]
+    Sum,
+    /// Keep the largest counter, for reports covering the same content.
+    Max,
+}
+
+/// Label used to tag a report's findings: the parent directory name for a
+/// file called `report.json`, the file stem otherwise.
+pub fn report_label(path: &Path) -> String {
+    let name = if path.file_name().and_then(|n| n.to_str()) == Some("report.json") {
+        path.parent().and_then(|p| p.file_name())
+    } else {
+        path.file_stem()
+    };
+    name.map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Combines labelled reports into one meta-report. Findings are tagged with
+/// their report's label, confidence is the worst seen per counter, and the
+/// budget fails if any report failed its budget, efficiency floor or a
+/// per-depot budget.
+pub fn merge_reports(reports: &[(String, Report)], strategy: MetricMerge) -> Report {
+    let combine = |f: fn(&Report) -> u64| -> u64 {
+        let values = reports.iter().map(|(_, r)| f(r));
+        match strategy {
+            MetricMerge::Sum => values.fold(0u64, u64::saturating_add),
+            MetricMerge::Max => values.max().unwrap_or(0),
+        }
+    };
+    let new_bytes = combine(|r| r.metrics.new_bytes);
+    let changed_content_bytes = combine(|r| r.metrics.changed_content_bytes);
+    let delta_efficiency = if new_bytes == 0 {
+        1.0
+    } else {
+        (changed_content_bytes as f64 / new_bytes as f64).clamp(0.0, 1.0)
+    };
+
+    // ConfidenceLevel orders High < Medium < Low, so the worst is the max.
+    let worst = |f: fn(&ConfidenceSummary) -> crate::types::ConfidenceLevel| {
+        reports
+            .iter()
+            .map(|(_, r)| f(&r.confidence))
+            .max()
+            .unwrap_or(crate::types::ConfidenceLevel::Low)
+    };
+    let confidence = ConfidenceSummary {
+        new_bytes: worst(|c| c.new_bytes),
+        changed_content_bytes: worst(|c| c.changed_content_bytes),
+        delta_efficiency: worst(|c| c.delta_efficiency),
+        waste_ratio: worst(|c| c.waste_ratio),
+        overall: worst(|c| c.overall),
+    };
+
+    let mut findings = Vec::new();
+    for (label, r) in reports {
+        for f in &r.findings {
+            let mut f = f.clone();
+            f.source_report = Some(label.clone());
+            findings.push(f);
+        }
+    }
+    findings.sort_by(|a, b| a.id.cmp(&b.id).then(a.source_report.cmp(&b.source_report)));
+
+    // Reports with any gate: the aggregate budget, the efficiency floor or a
+    // per-depot budget. A report fails when `budget_passed` does.
+    let gated: Vec<&(String, Report)> = reports
+        .iter()
+        .filter(|(_, r)| {
+            r.budget.is_some()
+                || r.efficiency_floor.is_some()
+                || r.per_depot.iter().any(|d| d.budget.is_some())
+        })
+        .collect();
+    let worst = reports
+        .iter()
+        .filter_map(|(_, r)| r.budget.as_ref())
+        .max_by(|a, b| {
+            let ratio = |b: &BudgetResult| b.regression_ratio.unwrap_or(f64::NEG_INFINITY);
+            ratio(a).total_cmp(&ratio(b))
+        });
+    let budget = (!gated.is_empty()).then(|| {
+        let failed: Vec<&str> = gated
+            .iter()
+            .filter(|(_, r)| !r.budget_passed())
+            .map(|(label, _)| label.as_str())
+            .collect();
+        BudgetResult {
+            regression_ratio: worst.and_then(|b| b.regression_ratio),
+            threshold_regression_ratio: worst.and_then(|b| b.threshold_regression_ratio),
+            pass: failed.is_empty(),
+            reason: if failed.is_empty() {
+                "all reports within regression budget".to_string()
+            } else {
+                format!(
+                    "{} of {} reports failed the budget: {}",
+                    failed.len(),
+                    gated.len(),
+                    failed.join(", ")
+                )
+            },
+            tolerance_bytes: None,
+            policy: None,
+            clauses: Vec::new(),
+        }
+    });
+
+    let mut parse_modes: Vec<&str> = reports
+        .iter()
+        .map(|(_, r)| r.inputs.parse_mode.as_str())
+        .collect();
+    parse_modes.dedup();
+    let mut offenders: Vec<FileOffender> = reports
+        .iter()
+        .flat_map(|(_, r)| r.offenders.iter().cloned())
+        .collect();
+    offenders.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    offenders.truncate(report::TOP_OFFENDERS);
+
+    Report {
+        report_version: report::REPORT_VERSION.to_string(),
+        inputs: Inputs {
+            input_path: reports
+                .iter()
+                .map(|(label, _)| label.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            parse_mode: match parse_modes.as_slice() {
+                [single] => single.to_string(),
+                _ => "MIXED".to_string(),
+            },
+            sources: reports
+                .iter()
+                .flat_map(|(_, r)| r.inputs.sources.iter().cloned())
+                .collect(),
+            files_without_counters: reports
+                .iter()
+                .map(|(_, r)| r.inputs.files_without_counters)
+                .sum(),
+        },
+        metrics: Metrics {
+            new_bytes,
+            changed_content_bytes,
+            delta_efficiency,
+            waste_ratio: (1.0 - delta_efficiency).clamp(0.0, 1.0),
+        },
+        confidence,
+        findings,
+        baseline_comparison: None,
+        budget,
+        build_metadata: None,
+        per_depot: Vec::new(),
+        offenders,
+        offender_diff: Vec::new(),
+        metrics_extra: Default::default(),
+        compare_range: None,
+        raw_counters: None,
+        efficiency_floor: None,
+        offender_stats: None,
+        depots_omitted: None,
+        strict_preview: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::ParseMode,
+        report::{evaluate_budget, DepotReport},
+        types::ConfidenceLevel,
+    };
+
+    fn report() -> Report {
+        let metrics = Metrics {
+            new_bytes: 300,
+            changed_content_bytes: 200,
+            delta_efficiency: 2.0 / 3.0,
+            waste_ratio: 1.0 / 3.0,
+        };
+        Report::new(
+            Path::new("x"),
+            ParseMode::BestEffort,
+            metrics.clone(),
+            ConfidenceSummary {
+                new_bytes: ConfidenceLevel::High,
+                changed_content_bytes: ConfidenceLevel::High,
+                delta_efficiency: ConfidenceLevel::High,
+                waste_ratio: ConfidenceLevel::High,
+                overall: ConfidenceLevel::High,
+            },
+            vec![],
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn depot_budget_failure_fails_the_merged_budget() {
+        let mut failing = report();
+        failing.per_depot = vec![DepotReport {
+            depot_id: "12345".to_string(),
+            metrics: failing.metrics.clone(),
+            confidence: ConfidenceLevel::High,
+            class: None,
+            findings: vec![],
+            budget: Some(evaluate_budget(100, 300, 1.25, None)),
+            offenders: vec![],
+        }];
+        assert!(failing.budget.is_none());
+
+        let reports = vec![
+            ("win".to_string(), report()),
+            ("linux".to_string(), failing),
+        ];
+        let merged = merge_reports(&reports, MetricMerge::Sum);
+        let budget = merged
+            .budget
+            .as_ref()
+            .expect("merged budget from the depot gate");
+        assert!(!budget.pass);
+        assert_eq!(budget.reason, "1 of 1 reports failed the budget: linux");
+        assert_eq!(budget.regression_ratio, None);
+        assert!(!merged.budget_passed());
+
+        let merged = merge_reports(&reports[..1], MetricMerge::Sum);
+        assert!(merged.budget.is_none());
+    }
+}
@@ -0,0 +1,35 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Text encoding used to decode scanned logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Honour a UTF-8 or UTF-16 byte order mark, otherwise assume UTF-8.
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl InputEncoding {
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            InputEncoding::Auto | InputEncoding::Utf8 => UTF_8,
+            InputEncoding::Utf16Le => UTF_16LE,
+            InputEncoding::Utf16Be => UTF_16BE,
+            InputEncoding::Windows1252 => WINDOWS_1252,
+        }
+    }
+}
+
+/// Decodes log bytes to text. Malformed sequences become U+FFFD rather than
+/// failing the whole log.
+pub fn decode_log(bytes: &[u8], encoding: InputEncoding) -> Cow<'_, str> {
+    match encoding {
+        InputEncoding::Auto => UTF_8.decode(bytes).0,
+        forced => forced.encoding().decode_with_bom_removal(bytes).0,
+    }
+}
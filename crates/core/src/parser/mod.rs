@@ -1,16 +1,26 @@
+mod encoding;
+mod sidecar;
 mod steampipe_log;
 
-use std::collections::HashMap;
-use std::{fs::File, io::BufReader, path::Path};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use walkdir::WalkDir;
 
+use crate::stats::OffenderTally;
 use crate::types::FileOffender;
 
-pub use steampipe_log::{parse_steampipe_log, SteamPipeCounters};
+pub use encoding::{decode_log, InputEncoding};
+pub use sidecar::load_sidecar;
+pub use steampipe_log::{
+    parse_steampipe_log, parse_steampipe_log_str, parse_steampipe_log_with, CounterMerge,
+    CounterPatterns, DigitSeparators, LogOptions, MatchCounts, ParsedSteamPipeLog,
+    SteamPipeCounters,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParseMode {
@@ -18,117 +28,709 @@ pub enum ParseMode {
     BestEffort,
 }
 
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    pub max_total_bytes_scanned: u64,
+    /// File name of a counters sidecar looked up next to each parsed log.
+    pub sidecar_name: String,
+    /// Group logs by depot id; disable to skip per-depot work entirely.
+    pub per_depot: bool,
+    pub log: LogOptions,
+    pub encoding: InputEncoding,
+    /// Only analyse logs from these depots. Logs without a depot id are
+    /// dropped when this is non-empty.
+    pub include_depots: Vec<String>,
+    /// Drop logs from these depots, counters and offenders alike.
+    pub exclude_depots: Vec<String>,
+    /// Treat each log without a depot id, typically one sitting at the
+    /// BuildOutput root, as a depot named by its file stem, and sum depot
+    /// counters into the aggregate instead of merging them.
+    pub root_logs_as_depots: bool,
+    /// Replaces the default `\d{5,}` depot id pattern; see
+    /// [`compile_depot_id_pattern`].
+    pub depot_id_pattern: Option<Regex>,
+    /// In strict mode, parse every log and report all that lack the required
+    /// counter in one error instead of stopping at the first.
+    pub strict_collect_all: bool,
+    /// Memory-map logs of at least this many bytes instead of reading them
+    /// into a buffer; `None` never maps.
+    pub mmap_min_bytes: Option<u64>,
+}
+
+impl ParseOptions {
+    fn includes_depot(&self, depot_id: Option<&str>) -> bool {
+        match depot_id {
+            Some(id) if self.exclude_depots.iter().any(|d| d == id) => false,
+            Some(id) if !self.include_depots.is_empty() => {
+                self.include_depots.iter().any(|d| d == id)
+            }
+            None => self.include_depots.is_empty(),
+            Some(_) => true,
+        }
+    }
+
+    /// Depot id from a log's file name or parent directory.
+    fn depot_id(&self, path: &Path) -> Option<String> {
+        extract_depot_id_with(path, self.depot_id_pattern.as_ref().unwrap_or(&RE_DEPOT_ID))
+    }
+
+    /// Depot a log is grouped under, if any.
+    fn depot_key(&self, depot_id: Option<String>, path: &Path) -> Option<String> {
+        if self.root_logs_as_depots {
+            depot_id.or_else(|| log_stem(path).map(str::to_string))
+        } else {
+            depot_id.filter(|_| self.per_depot)
+        }
+    }
+
+    /// Mode a single log is parsed in; collecting strict violations parses
+    /// best-effort and checks afterwards.
+    fn log_mode(&self, mode: ParseMode) -> ParseMode {
+        if self.strict_collect_all {
+            ParseMode::BestEffort
+        } else {
+            mode
+        }
+    }
+
+    fn accumulator(&self) -> LogAccumulator {
+        LogAccumulator {
+            counter_merge: self.log.counter_merge,
+            sum_depots: self.root_logs_as_depots,
+            keep_depots: self.per_depot,
+            ..LogAccumulator::default()
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            mode: ParseMode::BestEffort,
+            max_total_bytes_scanned: 50 * 1024 * 1024,
+            sidecar_name: "counters.json".to_string(),
+            per_depot: true,
+            log: LogOptions::default(),
+            encoding: InputEncoding::Auto,
+            include_depots: Vec::new(),
+            exclude_depots: Vec::new(),
+            root_logs_as_depots: false,
+            depot_id_pattern: None,
+            strict_collect_all: false,
+            mmap_min_bytes: None,
+        }
+    }
+}
+
+/// Diagnostics about a parse, for tooling that wants more than the report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Files found under the input, before any filtering.
+    pub files_seen: usize,
+    /// Files with a `.log` or `.txt` extension, gzipped or not.
+    pub files_matched: usize,
+    pub files_parsed: usize,
+    pub files_skipped: Vec<SkippedFile>,
+    pub bytes_scanned: u64,
+    pub matches: MatchCounts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Not a `.log`, `.txt`, `.log.gz` or `.txt.gz` file.
+    Extension,
+    /// Excluded by `include_depots` / `exclude_depots`.
+    DepotFilter,
+    /// `max_total_bytes_scanned` was reached.
+    ScanLimit,
+}
+
 #[derive(Debug, Clone)]
 pub struct DepotOutput {
     pub depot_id: String,
     pub counters: SteamPipeCounters,
+    /// The depot's largest offenders, at most [`RETAINED_OFFENDERS`].
     pub offenders: Vec<FileOffender>,
+    /// Count, total and percentiles over all of the depot's offenders.
+    pub offender_tally: OffenderTally,
+    /// Sum of the depot's offender sizes with each path counted once.
+    pub offender_bytes: u64,
+    /// Logs attributed to this depot.
+    pub sources: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ParsedBuildOutput {
     pub mode: ParseMode,
     pub counters: SteamPipeCounters,
+    /// The largest offenders, at most [`RETAINED_OFFENDERS`], merged by path.
     pub offenders: Vec<FileOffender>,
+    /// Count, total and percentiles over every offender line, including
+    /// those not retained in `offenders`.
+    pub offender_tally: OffenderTally,
+    /// Sum of every offender's size with each path counted once, at its
+    /// largest reported size; unlike `offender_tally`, a file reported by
+    /// several logs is not double counted.
+    pub offender_bytes: u64,
     pub sources: Vec<String>,
     pub per_depot: Vec<DepotOutput>,
+    pub files_without_counters: usize,
+}
+
+impl ParsedBuildOutput {
+    /// Output holding only `counters`: no offenders, sources or depots.
+    pub fn from_counters(mode: ParseMode, counters: SteamPipeCounters) -> Self {
+        Self {
+            mode,
+            counters,
+            offenders: vec![],
+            offender_tally: Default::default(),
+            offender_bytes: 0,
+            sources: vec![],
+            per_depot: vec![],
+            files_without_counters: 0,
+        }
+    }
 }
 
 static RE_DEPOT_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d{5,})").expect("valid regex"));
 
+/// `Some(gzipped)` for a `.log` or `.txt` file, optionally with a `.gz`
+/// suffix; `None` for anything else.
+fn log_kind(path: &Path) -> Option<bool> {
+    let is_log = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("log") || e.eq_ignore_ascii_case("txt"))
+    };
+    if is_log(path) {
+        return Some(false);
+    }
+    let gz = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("gz"));
+    (gz && is_log(Path::new(path.file_stem()?))).then_some(true)
+}
+
+/// File stem without the log extension, so `preview.log.gz` gives `preview`.
+fn log_stem(path: &Path) -> Option<&str> {
+    let stem = path.file_stem()?;
+    match log_kind(path) {
+        Some(true) => Path::new(stem).file_stem()?.to_str(),
+        _ => stem.to_str(),
+    }
+}
+
 pub fn extract_depot_id(path: &Path) -> Option<String> {
-    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-    if let Some(cap) = RE_DEPOT_ID.captures(stem) {
-        return Some(cap.get(1).unwrap().as_str().to_string());
+    extract_depot_id_with(path, &RE_DEPOT_ID)
+}
+
+/// Like [`extract_depot_id`], with the id taken from the first capture group
+/// of `pattern`.
+pub fn extract_depot_id_with(path: &Path, pattern: &Regex) -> Option<String> {
+    let stem = log_stem(path).unwrap_or("");
+    if let Some(m) = pattern.captures(stem).and_then(|cap| cap.get(1)) {
+        return Some(m.as_str().to_string());
     }
     if let Some(parent) = path.parent() {
         let dir_name = parent.file_name().and_then(|s| s.to_str()).unwrap_or("");
-        if let Some(cap) = RE_DEPOT_ID.captures(dir_name) {
-            return Some(cap.get(1).unwrap().as_str().to_string());
+        if let Some(m) = pattern.captures(dir_name).and_then(|cap| cap.get(1)) {
+            return Some(m.as_str().to_string());
         }
     }
     None
 }
 
+/// Compiles a `depot_id_pattern`, which must have a capture group for the id.
+pub fn compile_depot_id_pattern(src: &str) -> anyhow::Result<Regex> {
+    let re = Regex::new(src).with_context(|| format!("invalid depot_id_pattern {src:?}"))?;
+    if re.captures_len() < 2 {
+        anyhow::bail!("depot_id_pattern {src:?} has no capture group");
+    }
+    Ok(re)
+}
+
+/// Lines at the top of a log searched by [`depot_id_from_contents`].
+const DEPOT_HEADER_LINES: usize = 50;
+
+static RE_DEPOT_HEADER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\bdepot\s+(\d{5,})\b").expect("valid regex"));
+
+/// Depot id from a `Depot <id>` line in the log header, for logs whose path
+/// carries no id.
+pub fn depot_id_from_contents(text: &str) -> Option<String> {
+    text.lines()
+        .take(DEPOT_HEADER_LINES)
+        .find_map(|line| RE_DEPOT_HEADER.captures(line))
+        .map(|cap| cap[1].to_string())
+}
+
+/// True when `input` does not exist on disk but contains glob metacharacters.
+pub fn is_glob_pattern(input: &Path) -> bool {
+    !input.exists() && input.to_str().is_some_and(|s| s.contains(['*', '?', '[']))
+}
+
 pub fn parse_buildoutput_dir(
     input: &Path,
-    mode: ParseMode,
-    max_total_bytes_scanned: u64,
+    opts: &ParseOptions,
 ) -> anyhow::Result<ParsedBuildOutput> {
-    let mut counters = SteamPipeCounters::default();
-    let mut offenders: Vec<FileOffender> = Vec::new();
-    let mut sources: Vec<String> = Vec::new();
-    let mut depot_map: HashMap<String, (SteamPipeCounters, Vec<FileOffender>)> = HashMap::new();
+    parse_buildoutput_dir_stats(input, opts).map(|(parsed, _)| parsed)
+}
 
-    let mut scanned: u64 = 0;
+/// Like [`parse_buildoutput_dir`], also returning [`ParseStats`].
+pub fn parse_buildoutput_dir_stats(
+    input: &Path,
+    opts: &ParseOptions,
+) -> anyhow::Result<(ParsedBuildOutput, ParseStats)> {
+    parse_log_files(&dir_files(input)?, opts)
+}
 
+/// Every file below `input`, in walk order.
+pub(crate) fn dir_files(input: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
     for entry in WalkDir::new(input).follow_links(false) {
         let entry = entry?;
-        if !entry.file_type().is_file() {
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Parses the files matched by a glob pattern such as `logs/*.log`, in sorted order.
+pub fn parse_buildoutput_glob(
+    pattern: &str,
+    opts: &ParseOptions,
+) -> anyhow::Result<ParsedBuildOutput> {
+    parse_log_files(&glob_files(pattern, opts.mode)?, opts).map(|(parsed, _)| parsed)
+}
+
+/// Files matched by `pattern`, sorted. Strict mode rejects an empty match.
+pub(crate) fn glob_files(pattern: &str, mode: ParseMode) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in glob::glob(pattern).with_context(|| format!("invalid glob {pattern}"))? {
+        let path = entry?;
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    if files.is_empty() && mode == ParseMode::Strict {
+        anyhow::bail!("insufficient input: glob {pattern} matched no files");
+    }
+    Ok(files)
+}
+
+/// Source name recorded for a log read from stdin.
+pub const STDIN_SOURCE: &str = "<stdin>";
+
+/// Parses a single log read from `r`, such as stdin, recorded as `source`.
+/// Like a file on disk, a log over `max_total_bytes_scanned` is skipped.
+pub fn parse_buildoutput_reader<R: BufRead>(
+    r: &mut R,
+    source: &str,
+    opts: &ParseOptions,
+) -> anyhow::Result<ParsedBuildOutput> {
+    let mut bytes = Vec::new();
+    r.take(opts.max_total_bytes_scanned.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("read {source}"))?;
+    parse_logs(&[(source.to_string(), bytes)], opts)
+}
+
+/// Parses in-memory logs given as `(source name, contents)` pairs, e.g. fetched
+/// over HTTP. Depot ids are extracted from the source names.
+pub fn parse_logs(
+    logs: &[(String, Vec<u8>)],
+    opts: &ParseOptions,
+) -> anyhow::Result<ParsedBuildOutput> {
+    let mut acc = opts.accumulator();
+    let mut scanned: u64 = 0;
+    let mut violations = StrictViolations::default();
+
+    for (name, bytes) in logs {
+        let text = decode_log(bytes, opts.encoding);
+        let depot_id = opts
+            .depot_id(Path::new(name))
+            .or_else(|| depot_id_from_contents(&text));
+        if !opts.includes_depot(depot_id.as_deref()) {
             continue;
         }
 
-        let path = entry.path();
+        let len = bytes.len() as u64;
+        if scanned.saturating_add(len) > opts.max_total_bytes_scanned {
+            break;
+        }
+        scanned += len;
+        let parsed = parse_steampipe_log_with(
+            &mut std::io::Cursor::new(text.as_bytes()),
+            opts.log_mode(opts.mode),
+            &opts.log,
+        )
+        .with_context(|| format!("parse log {name}"))?;
+        violations.check(opts.mode, name, &parsed);
+        let depot = opts.depot_key(depot_id, Path::new(name));
+        acc.add(name.clone(), depot, parsed);
+    }
+
+    violations.into_result()?;
+    acc.finish(opts.mode)
+}
 
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_lowercase();
-        if !(ext == "log" || ext == "txt") {
+pub(crate) fn parse_log_files(
+    files: &[PathBuf],
+    opts: &ParseOptions,
+) -> anyhow::Result<(ParsedBuildOutput, ParseStats)> {
+    let mode = opts.mode;
+    let mut acc = opts.accumulator();
+    let mut stats = ParseStats {
+        files_seen: files.len(),
+        ..ParseStats::default()
+    };
+    let mut limit_reached = false;
+    let mut sidecar_dirs: HashSet<PathBuf> = HashSet::new();
+    let mut violations = StrictViolations::default();
+
+    for path in files {
+        let path = path.as_path();
+        let skip = |stats: &mut ParseStats, reason| {
+            stats.files_skipped.push(SkippedFile {
+                path: path.display().to_string(),
+                reason,
+            })
+        };
+
+        let Some(gzipped) = log_kind(path) else {
+            skip(&mut stats, SkipReason::Extension);
+            continue;
+        };
+        stats.files_matched += 1;
+
+        // Without an id in the name, filtering waits until the header is read.
+        let mut depot_id = opts.depot_id(path);
+        if depot_id.is_some() && !opts.includes_depot(depot_id.as_deref()) {
+            skip(&mut stats, SkipReason::DepotFilter);
             continue;
         }
 
         let meta = std::fs::metadata(path)?;
         let len = meta.len();
-        if scanned.saturating_add(len) > max_total_bytes_scanned {
-            break;
+        if limit_reached || stats.bytes_scanned.saturating_add(len) > opts.max_total_bytes_scanned {
+            // Once the cap is hit, every later file is skipped too.
+            limit_reached = true;
+            skip(&mut stats, SkipReason::ScanLimit);
+            continue;
+        }
+        stats.bytes_scanned += len;
+
+        // Compressed logs count their on-disk size against the scan limit.
+        let bytes = if gzipped {
+            LogBytes::gunzip(path)?
+        } else {
+            LogBytes::open(path, len, opts.mmap_min_bytes)?
+        };
+        let text = decode_log(&bytes, opts.encoding);
+        if depot_id.is_none() {
+            depot_id = depot_id_from_contents(&text);
+            if !opts.includes_depot(depot_id.as_deref()) {
+                skip(&mut stats, SkipReason::DepotFilter);
+                continue;
+            }
         }
-        scanned += len;
 
-        let f = File::open(path).with_context(|| format!("open {}", path.display()))?;
-        let mut reader = BufReader::new(f);
+        let sidecar = match path.parent() {
+            Some(dir) => load_sidecar(&dir.join(&opts.sidecar_name))?,
+            None => None,
+        };
+        // A sidecar supplying the required counter satisfies strict mode on its own.
+        let log_mode = if sidecar
+            .as_ref()
+            .is_some_and(|c| c.predicted_update_bytes.is_some())
+        {
+            ParseMode::BestEffort
+        } else {
+            mode
+        };
 
-        let parsed = parse_steampipe_log(&mut reader, mode)
-            .with_context(|| format!("parse log {}", path.display()))?;
+        let mut parsed = match bytes {
+            LogBytes::Mapped(_) => {
+                parse_steampipe_log_str(&text, opts.log_mode(log_mode), &opts.log)
+            }
+            LogBytes::Read(_) => {
+                parse_steampipe_log_with(&mut text.as_bytes(), opts.log_mode(log_mode), &opts.log)
+            }
+        }
+        .with_context(|| format!("parse log {}", path.display()))?;
+        if let Some(mut sidecar) = sidecar {
+            // Each directory's sidecar counts once: when logs are summed, later
+            // logs beside it take zero for the fields it supplies.
+            let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            if !sidecar_dirs.insert(dir) && opts.log.counter_merge == CounterMerge::Sum {
+                sidecar.predicted_update_bytes = sidecar.predicted_update_bytes.map(|_| 0);
+                sidecar.changed_content_bytes = sidecar.changed_content_bytes.map(|_| 0);
+            }
+            parsed.counters.merge(sidecar);
+        }
+        violations.check(log_mode, &path.display().to_string(), &parsed);
+        stats.files_parsed += 1;
+        stats.matches.add(parsed.matches);
+
+        acc.add(
+            path.display().to_string(),
+            opts.depot_key(depot_id, path),
+            parsed,
+        );
+    }
 
-        counters.merge(parsed.counters.clone());
-        offenders.extend(parsed.offenders.clone());
-        sources.push(path.display().to_string());
+    violations.into_result()?;
+    Ok((acc.finish(mode)?, stats))
+}
+
+/// A log's contents, memory-mapped or read into a buffer.
+enum LogBytes {
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+}
 
-        if let Some(depot_id) = extract_depot_id(path) {
-            let entry = depot_map
-                .entry(depot_id)
-                .or_insert_with(|| (SteamPipeCounters::default(), Vec::new()));
-            entry.0.merge(parsed.counters);
-            entry.1.extend(parsed.offenders);
+impl LogBytes {
+    /// Maps `path` when it is at least `mmap_min_bytes` long, falling back to
+    /// a plain read for smaller files or when mapping fails.
+    fn open(path: &Path, len: u64, mmap_min_bytes: Option<u64>) -> anyhow::Result<Self> {
+        if mmap_min_bytes.is_some_and(|min| len >= min && len > 0) {
+            let mapped = std::fs::File::open(path).and_then(|f| {
+                // SAFETY: the map is read-only and dropped once the log is
+                // parsed; build logs aren't rewritten while being analysed.
+                unsafe { memmap2::Mmap::map(&f) }
+            });
+            if let Ok(map) = mapped {
+                return Ok(LogBytes::Mapped(map));
+            }
         }
+        let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+        Ok(LogBytes::Read(bytes))
     }
 
-    offenders.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    fn gunzip(path: &Path) -> anyhow::Result<Self> {
+        let f = std::fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut bytes = Vec::new();
+        flate2::read::GzDecoder::new(f)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("decompress {}", path.display()))?;
+        Ok(LogBytes::Read(bytes))
+    }
+}
 
-    if mode == ParseMode::Strict && counters.predicted_update_bytes.is_none() {
+impl std::ops::Deref for LogBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            LogBytes::Mapped(map) => map,
+            LogBytes::Read(bytes) => bytes,
+        }
+    }
+}
+
+/// Logs that failed strict mode's counter check, collected for
+/// [`ParseOptions::strict_collect_all`].
+#[derive(Default)]
+struct StrictViolations {
+    sources: Vec<String>,
+}
+
+impl StrictViolations {
+    fn check(&mut self, mode: ParseMode, source: &str, parsed: &ParsedSteamPipeLog) {
+        if mode == ParseMode::Strict && parsed.counters.predicted_update_bytes.is_none() {
+            self.sources.push(source.to_string());
+        }
+    }
+
+    fn into_result(self) -> anyhow::Result<()> {
+        if self.sources.is_empty() {
+            return Ok(());
+        }
         anyhow::bail!(
-            "insufficient input: missing required counter predicted_update_bytes; \
-         run BestEffort mode or provide logs containing PREDICTED_UPDATE_BYTES=..."
-        );
+            "missing required counter PREDICTED_UPDATE_BYTES in {} logs:\n  {}",
+            self.sources.len(),
+            self.sources.join("\n  ")
+        )
     }
+}
+
+/// Offenders kept per aggregate or depot; enough for the report's top list.
+pub const RETAINED_OFFENDERS: usize = crate::report::TOP_OFFENDERS;
+
+/// The largest offenders seen so far, merged by path (largest size, all
+/// sources), in memory bounded by [`RETAINED_OFFENDERS`]. Sizes only grow,
+/// so a path pruned once can only return by growing past the kept ones.
+#[derive(Debug, Default)]
+struct TopOffenders {
+    by_path: HashMap<String, FileOffender>,
+    /// Sum over every path added, at its largest size. Kept across pruning;
+    /// a path pruned and later reported again is counted again.
+    total_bytes: u64,
+}
+
+impl TopOffenders {
+    fn add(&mut self, o: FileOffender) {
+        match self.by_path.get_mut(&o.path) {
+            Some(existing) => {
+                if o.bytes > existing.bytes {
+                    self.total_bytes += o.bytes - existing.bytes;
+                    existing.bytes = o.bytes;
+                    existing.percent = o.percent;
+                }
+                for s in o.sources {
+                    if !existing.sources.contains(&s) {
+                        existing.sources.push(s);
+                    }
+                }
+            }
+            None => {
+                self.total_bytes += o.bytes;
+                self.by_path.insert(o.path.clone(), o);
+            }
+        }
+        if self.by_path.len() > 2 * RETAINED_OFFENDERS {
+            let kept = sorted_top(std::mem::take(&mut self.by_path));
+            self.by_path = kept.into_iter().map(|o| (o.path.clone(), o)).collect();
+        }
+    }
+
+    /// Largest first, ties by path, at most [`RETAINED_OFFENDERS`], and the
+    /// path-deduplicated byte total.
+    fn into_sorted(self) -> (Vec<FileOffender>, u64) {
+        (sorted_top(self.by_path), self.total_bytes)
+    }
+}
+
+fn sorted_top(by_path: HashMap<String, FileOffender>) -> Vec<FileOffender> {
+    let mut out: Vec<FileOffender> = by_path.into_values().collect();
+    for o in &mut out {
+        o.sources.sort();
+    }
+    out.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    out.truncate(RETAINED_OFFENDERS);
+    out
+}
+
+#[derive(Default)]
+struct DepotAccumulator {
+    counters: SteamPipeCounters,
+    offenders: TopOffenders,
+    tally: OffenderTally,
+    sources: Vec<String>,
+}
 
-    let mut per_depot: Vec<DepotOutput> = depot_map
-        .into_iter()
-        .map(|(depot_id, (counters, offenders))| DepotOutput {
-            depot_id,
+#[derive(Default)]
+struct LogAccumulator {
+    counters: SteamPipeCounters,
+    offenders: TopOffenders,
+    tally: OffenderTally,
+    sources: Vec<String>,
+    depot_map: HashMap<String, DepotAccumulator>,
+    files_without_counters: usize,
+    counter_merge: CounterMerge,
+    /// Aggregate counters are the sum of the per-depot counters.
+    sum_depots: bool,
+    /// Report `per_depot`; depots may still be grouped for `sum_depots`.
+    keep_depots: bool,
+}
+
+impl LogAccumulator {
+    fn add(&mut self, source: String, depot_id: Option<String>, mut parsed: ParsedSteamPipeLog) {
+        for o in &mut parsed.offenders {
+            o.sources = vec![source.clone()];
+        }
+        if parsed.counters.predicted_update_bytes.is_none()
+            && parsed.counters.changed_content_bytes.is_none()
+        {
+            self.files_without_counters += 1;
+        }
+        self.counters
+            .merge_with(parsed.counters.clone(), self.counter_merge);
+        let mut depot = depot_id.map(|id| self.depot_map.entry(id).or_default());
+        if let Some(d) = &mut depot {
+            d.counters.merge_with(parsed.counters, self.counter_merge);
+            d.sources.push(source.clone());
+        }
+        for o in parsed.offenders {
+            self.tally.add(o.bytes);
+            if let Some(d) = &mut depot {
+                d.tally.add(o.bytes);
+                d.offenders.add(o.clone());
+            }
+            self.offenders.add(o);
+        }
+        self.sources.push(source);
+    }
+
+    fn finish(self, mode: ParseMode) -> anyhow::Result<ParsedBuildOutput> {
+        let LogAccumulator {
+            mut counters,
+            offenders,
+            tally,
+            sources,
+            depot_map,
+            files_without_counters,
+            sum_depots,
+            keep_depots,
+            ..
+        } = self;
+
+        let (offenders, offender_bytes) = offenders.into_sorted();
+
+        if sum_depots {
+            counters = SteamPipeCounters::default();
+            for d in depot_map.values() {
+                counters.merge_with(d.counters.clone(), CounterMerge::Sum);
+            }
+        }
+
+        if mode == ParseMode::Strict && counters.predicted_update_bytes.is_none() {
+            anyhow::bail!(
+                "insufficient input: missing required counter predicted_update_bytes; \
+             run BestEffort mode or provide logs containing PREDICTED_UPDATE_BYTES=..."
+            );
+        }
+
+        let mut per_depot: Vec<DepotOutput> = depot_map
+            .into_iter()
+            .map(|(depot_id, d)| {
+                let (offenders, offender_bytes) = d.offenders.into_sorted();
+                DepotOutput {
+                    depot_id,
+                    counters: d.counters,
+                    offenders,
+                    offender_tally: d.tally,
+                    offender_bytes,
+                    sources: d.sources,
+                }
+            })
+            .collect();
+        per_depot.sort_by(|a, b| a.depot_id.cmp(&b.depot_id));
+        if !keep_depots {
+            per_depot.clear();
+        }
+
+        Ok(ParsedBuildOutput {
+            mode,
             counters,
             offenders,
+            offender_tally: tally,
+            offender_bytes,
+            sources,
+            per_depot,
+            files_without_counters,
         })
-        .collect();
-    per_depot.sort_by(|a, b| a.depot_id.cmp(&b.depot_id));
-
-    Ok(ParsedBuildOutput {
-        mode,
-        counters,
-        offenders,
-        sources,
-        per_depot,
-    })
+    }
 }
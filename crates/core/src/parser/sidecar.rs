@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use super::SteamPipeCounters;
+
+#[derive(Debug, Deserialize)]
+struct SidecarCounters {
+    predicted_update_bytes: Option<u64>,
+    changed_content_bytes: Option<u64>,
+}
+
+/// Loads authoritative counters written by a build wrapper next to the log.
+/// Returns `None` when no sidecar exists.
+pub fn load_sidecar(path: &Path) -> anyhow::Result<Option<SteamPipeCounters>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    let sidecar: SidecarCounters =
+        serde_json::from_slice(&bytes).with_context(|| format!("parse {}", path.display()))?;
+    Ok(Some(SteamPipeCounters {
+        predicted_update_bytes: sidecar.predicted_update_bytes,
+        changed_content_bytes: sidecar.changed_content_bytes,
+    }))
+}
@@ -14,23 +14,183 @@ pub struct SteamPipeCounters {
     pub changed_content_bytes: Option<u64>,
 }
 
+/// How repeated counter values combine, within a log and across logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CounterMerge {
+    /// The last value seen wins.
+    #[default]
+    LastWins,
+    /// Values are added. Use this when a log prints one counter line per
+    /// depot, or when each log covers a different depot.
+    Sum,
+    /// The largest value seen wins.
+    Max,
+}
+
 impl SteamPipeCounters {
+    /// Merges with [`CounterMerge::LastWins`].
     pub fn merge(&mut self, other: SteamPipeCounters) {
-        if other.predicted_update_bytes.is_some() {
-            self.predicted_update_bytes = other.predicted_update_bytes;
+        self.merge_with(other, CounterMerge::LastWins);
+    }
+
+    pub fn merge_with(&mut self, other: SteamPipeCounters, strategy: CounterMerge) {
+        let combine = |mine: &mut Option<u64>, theirs: Option<u64>| {
+            let Some(v) = theirs else { return };
+            *mine = Some(match (strategy, *mine) {
+                (CounterMerge::Sum, Some(m)) => m.saturating_add(v),
+                (CounterMerge::Max, Some(m)) => m.max(v),
+                _ => v,
+            });
+        };
+        combine(
+            &mut self.predicted_update_bytes,
+            other.predicted_update_bytes,
+        );
+        combine(&mut self.changed_content_bytes, other.changed_content_bytes);
+    }
+}
+
+/// Which characters group digits in logged byte counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigitSeparators {
+    /// `_` in counters, `,` in the pretty form; `.` is a decimal point.
+    #[default]
+    Default,
+    /// European grouping: `.` and `_` separate thousands and `,` is a decimal
+    /// comma. `1.234.567` reads as 1234567; values with a fractional part are
+    /// ignored.
+    Eu,
+}
+
+impl DigitSeparators {
+    fn group_chars(self) -> &'static [char] {
+        match self {
+            DigitSeparators::Default => &['_', ','],
+            DigitSeparators::Eu => &['_', '.'],
         }
-        if other.changed_content_bytes.is_some() {
-            self.changed_content_bytes = other.changed_content_bytes;
+    }
+
+    fn parse(self, raw: &str) -> Option<u64> {
+        raw.replace(self.group_chars(), "").parse().ok()
+    }
+
+    /// A possibly fractional value such as `1.2` (`1,2` for [`Self::Eu`]).
+    fn parse_decimal(self, raw: &str) -> Option<f64> {
+        let raw = match self {
+            DigitSeparators::Default => raw.replace(',', ""),
+            DigitSeparators::Eu => raw.replace('.', "").replace(',', "."),
+        };
+        raw.parse().ok()
+    }
+
+    fn regexes(self) -> [&'static Regex; 3] {
+        match self {
+            DigitSeparators::Default => [&RE_KV, &RE_PRETTY_UPDATE, &RE_OFFENDER],
+            DigitSeparators::Eu => [&RE_KV_EU, &RE_PRETTY_UPDATE_EU, &RE_OFFENDER_EU],
         }
     }
 }
 
+/// Per-log parsing knobs beyond the parse mode.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub patterns: CounterPatterns,
+    pub counter_merge: CounterMerge,
+    pub digit_separators: DigitSeparators,
+}
+
+/// User-supplied counter regexes, tried before the built-in ones. The value is
+/// taken from a capture group named `bytes`, or the first group otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct CounterPatterns {
+    pub predicted_update_bytes: Vec<Regex>,
+    pub changed_content_bytes: Vec<Regex>,
+}
+
+impl CounterPatterns {
+    pub fn compile(predicted: &[String], changed: &[String]) -> anyhow::Result<Self> {
+        let compile_all = |key: &str, sources: &[String]| -> anyhow::Result<Vec<Regex>> {
+            sources
+                .iter()
+                .map(|src| {
+                    let re = Regex::new(src)
+                        .with_context(|| format!("invalid {key} pattern {src:?}"))?;
+                    if re.captures_len() < 2 {
+                        anyhow::bail!("{key} pattern {src:?} has no capture group");
+                    }
+                    Ok(re)
+                })
+                .collect()
+        };
+        Ok(Self {
+            predicted_update_bytes: compile_all("predicted_update_bytes", predicted)?,
+            changed_content_bytes: compile_all("changed_content_bytes", changed)?,
+        })
+    }
+}
+
+fn match_custom(patterns: &[Regex], line: &str, separators: DigitSeparators) -> Option<u64> {
+    patterns.iter().find_map(|re| {
+        let cap = re.captures(line)?;
+        let m = cap.name("bytes").or_else(|| cap.get(1))?;
+        match separators {
+            DigitSeparators::Default => m.as_str().replace(['_', ','], "").parse().ok(),
+            DigitSeparators::Eu => separators.parse(m.as_str()),
+        }
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedSteamPipeLog {
     pub counters: SteamPipeCounters,
     pub offenders: Vec<FileOffender>,
+    pub matches: MatchCounts,
+}
+
+/// Lines matched by each family of regexes while parsing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchCounts {
+    /// `KEY=value` counter lines.
+    pub counters: usize,
+    /// `Predicted update size: N bytes` (or `1.2 GiB`) lines.
+    pub pretty: usize,
+    pub offenders: usize,
+    /// Lines matched by a user `[patterns]` regex.
+    pub custom: usize,
 }
 
+impl MatchCounts {
+    pub fn add(&mut self, other: MatchCounts) {
+        self.counters += other.counters;
+        self.pretty += other.pretty;
+        self.offenders += other.offenders;
+        self.custom += other.custom;
+    }
+}
+
+static RE_OFFENDER_PERCENT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?i)\bTOP_OFFENDER\s*=\s*(?:"([^"]*)"|(.+?))\s*:\s*([0-9]+(?:[.,][0-9]+)?)\s*%\s*$"#,
+    )
+    .expect("valid regex")
+});
+// EU variants take `.` as a thousands separator only in strict three-digit
+// groups; a trailing `.5` or `,5` is captured separately so a decimal value is
+// rejected instead of being truncated.
+static RE_KV_EU: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(PREDICTED_UPDATE_BYTES|CHANGED_CONTENT_BYTES)\s*=\s*([0-9]{1,3}(?:\.[0-9]{3})+|[0-9][0-9_]*)\b([.,][0-9]+)?")
+        .expect("valid regex")
+});
+static RE_PRETTY_UPDATE_EU: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)predicted update size\s*:\s*([0-9]{1,3}(?:\.[0-9]{3})+|[0-9]+)\b([.,][0-9]+)?\s*bytes")
+        .expect("valid regex")
+});
+static RE_OFFENDER_EU: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\bTOP_OFFENDER\s*=\s*(?:"([^"]*)"|(.+?))\s*:\s*([0-9]{1,3}(?:\.[0-9]{3})+|[0-9][0-9_]*)\b([.,][0-9]+)?\s*$"#)
+        .expect("valid regex")
+});
 static RE_KV: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"(?i)\b(PREDICTED_UPDATE_BYTES|CHANGED_CONTENT_BYTES)\s*=\s*([0-9][0-9_]*)\b")
         .expect("valid regex")
@@ -40,17 +200,44 @@ static RE_PRETTY_UPDATE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"(?i)predicted update size\s*:\s*([0-9][0-9,]*)\s*bytes").expect("valid regex")
 });
 
+// `1.2 GiB` style sizes; `i` units are binary. The number is read by
+// `DigitSeparators::parse_decimal`, so both separator styles share it.
+static RE_PRETTY_SIZE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)predicted update size\s*:\s*([0-9][0-9.,]*)\s*([kmg])(i?)b\b")
+        .expect("valid regex")
+});
+
+/// Bytes in `value` of unit `prefix` (`k`, `m` or `g`), binary when `binary`.
+fn scaled_bytes(value: f64, prefix: &str, binary: bool) -> u64 {
+    let base: f64 = if binary { 1024.0 } else { 1000.0 };
+    let exp = match prefix.to_ascii_lowercase().as_str() {
+        "k" => 1,
+        "m" => 2,
+        _ => 3,
+    };
+    (value * base.powi(exp)).round() as u64
+}
+
+// The byte count is anchored to the end of the line so colons inside the path
+// (Windows drive letters, quoted names) stay part of the path.
 static RE_OFFENDER: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(?i)\bTOP_OFFENDER\s*=\s*(.+?)\s*:\s*([0-9][0-9_]*)\s*$").expect("valid regex")
+    Regex::new(r#"(?i)\bTOP_OFFENDER\s*=\s*(?:"([^"]*)"|(.+?))\s*:\s*([0-9][0-9_]*)\s*$"#)
+        .expect("valid regex")
 });
 
 pub fn parse_steampipe_log<R: BufRead>(
     r: &mut R,
     mode: ParseMode,
 ) -> anyhow::Result<ParsedSteamPipeLog> {
-    let mut counters = SteamPipeCounters::default();
-    let mut offenders: Vec<FileOffender> = Vec::new();
+    parse_steampipe_log_with(r, mode, &LogOptions::default())
+}
 
+pub fn parse_steampipe_log_with<R: BufRead>(
+    r: &mut R,
+    mode: ParseMode,
+    opts: &LogOptions,
+) -> anyhow::Result<ParsedSteamPipeLog> {
+    let mut parser = LineParser::new(opts);
     let mut line = String::new();
     loop {
         line.clear();
@@ -58,42 +245,167 @@ pub fn parse_steampipe_log<R: BufRead>(
         if n == 0 {
             break;
         }
+        parser.line(&line);
+    }
+    parser.finish(mode)
+}
+
+/// Like [`parse_steampipe_log_with`] over a log already in memory, such as a
+/// memory-mapped file, without copying each line. Gives the same result for
+/// the same text, with or without a trailing newline.
+pub fn parse_steampipe_log_str(
+    text: &str,
+    mode: ParseMode,
+    opts: &LogOptions,
+) -> anyhow::Result<ParsedSteamPipeLog> {
+    let mut parser = LineParser::new(opts);
+    for line in text.split_inclusive('\n') {
+        parser.line(line);
+    }
+    parser.finish(mode)
+}
+
+/// Parse state carried across the lines of one log.
+struct LineParser<'o> {
+    opts: &'o LogOptions,
+    seps: DigitSeparators,
+    re_kv: &'static Regex,
+    re_pretty: &'static Regex,
+    re_offender: &'static Regex,
+    counters: SteamPipeCounters,
+    /// First `1.2 GiB` style predicted size; used only when no exact byte
+    /// count turns up anywhere in the log.
+    pretty_size: Option<u64>,
+    offenders: Vec<FileOffender>,
+    matches: MatchCounts,
+}
 
-        if let Some(cap) = RE_KV.captures(&line) {
+impl<'o> LineParser<'o> {
+    fn new(opts: &'o LogOptions) -> Self {
+        let [re_kv, re_pretty, re_offender] = opts.digit_separators.regexes();
+        Self {
+            opts,
+            seps: opts.digit_separators,
+            re_kv,
+            re_pretty,
+            re_offender,
+            counters: SteamPipeCounters::default(),
+            pretty_size: None,
+            offenders: Vec::new(),
+            matches: MatchCounts::default(),
+        }
+    }
+
+    /// Feeds one line, trailing newline included or not.
+    fn line(&mut self, line: &str) {
+        let custom_predicted =
+            match_custom(&self.opts.patterns.predicted_update_bytes, line, self.seps);
+        let custom_changed =
+            match_custom(&self.opts.patterns.changed_content_bytes, line, self.seps);
+        if custom_predicted.is_some() || custom_changed.is_some() {
+            self.matches.custom += 1;
+        }
+        let mut line_counters = SteamPipeCounters {
+            predicted_update_bytes: custom_predicted,
+            changed_content_bytes: custom_changed,
+        };
+
+        if let Some(cap) = self.re_kv.captures(line).filter(|c| c.get(3).is_none()) {
+            self.matches.counters += 1;
             let key = cap.get(1).unwrap().as_str().to_ascii_uppercase();
-            let val = cap.get(2).unwrap().as_str().replace('_', "");
-            let num: u64 = val.parse().unwrap_or(0);
+            let num = self.seps.parse(cap.get(2).unwrap().as_str()).unwrap_or(0);
 
             match key.as_str() {
-                "PREDICTED_UPDATE_BYTES" => counters.predicted_update_bytes = Some(num),
-                "CHANGED_CONTENT_BYTES" => counters.changed_content_bytes = Some(num),
+                "PREDICTED_UPDATE_BYTES" if custom_predicted.is_none() => {
+                    line_counters.predicted_update_bytes = Some(num)
+                }
+                "CHANGED_CONTENT_BYTES" if custom_changed.is_none() => {
+                    line_counters.changed_content_bytes = Some(num)
+                }
                 _ => {}
             }
         }
+        self.counters
+            .merge_with(line_counters, self.opts.counter_merge);
 
-        if counters.predicted_update_bytes.is_none() {
-            if let Some(cap) = RE_PRETTY_UPDATE.captures(&line) {
-                let raw = cap.get(1).unwrap().as_str().replace(',', "");
-                if let Ok(num) = raw.parse::<u64>() {
-                    counters.predicted_update_bytes = Some(num);
+        if self.counters.predicted_update_bytes.is_none() {
+            if let Some(cap) = self.re_pretty.captures(line).filter(|c| c.get(2).is_none()) {
+                self.matches.pretty += 1;
+                if let Some(num) = self.seps.parse(cap.get(1).unwrap().as_str()) {
+                    self.counters.predicted_update_bytes = Some(num);
+                }
+            } else if let (None, Some(cap)) = (self.pretty_size, RE_PRETTY_SIZE.captures(line)) {
+                if let Some(value) = self.seps.parse_decimal(cap.get(1).unwrap().as_str()) {
+                    self.matches.pretty += 1;
+                    self.pretty_size = Some(scaled_bytes(
+                        value,
+                        cap.get(2).unwrap().as_str(),
+                        !cap.get(3).unwrap().as_str().is_empty(),
+                    ));
                 }
             }
         }
 
-        if let Some(cap) = RE_OFFENDER.captures(&line) {
-            let path = cap.get(1).unwrap().as_str().trim().to_string();
-            let raw = cap.get(2).unwrap().as_str().replace('_', "");
-            let bytes = raw.parse::<u64>().unwrap_or(0);
-            offenders.push(FileOffender { path, bytes });
+        if let Some(cap) = self
+            .re_offender
+            .captures(line)
+            .filter(|c| c.get(4).is_none())
+        {
+            self.matches.offenders += 1;
+            let path = cap
+                .get(1)
+                .or_else(|| cap.get(2))
+                .unwrap()
+                .as_str()
+                .trim()
+                .to_string();
+            let bytes = self.seps.parse(cap.get(3).unwrap().as_str()).unwrap_or(0);
+            self.offenders.push(FileOffender {
+                path,
+                bytes,
+                percent: None,
+                sources: vec![],
+            });
+        } else if let Some(cap) = RE_OFFENDER_PERCENT.captures(line) {
+            // A share never needs group separators, so `,` can only be a
+            // decimal comma here.
+            let pct = cap.get(3).unwrap().as_str().replace(',', ".");
+            if let Ok(pct) = pct.parse::<f64>() {
+                self.matches.offenders += 1;
+                let path = cap.get(1).or_else(|| cap.get(2)).unwrap().as_str().trim();
+                self.offenders.push(FileOffender {
+                    path: path.to_string(),
+                    bytes: 0,
+                    percent: Some(pct),
+                    sources: vec![],
+                });
+            }
         }
     }
 
-    if mode == ParseMode::Strict && counters.predicted_update_bytes.is_none() {
-        anyhow::bail!("missing required counter PREDICTED_UPDATE_BYTES");
-    }
+    fn finish(mut self, mode: ParseMode) -> anyhow::Result<ParsedSteamPipeLog> {
+        if self.counters.predicted_update_bytes.is_none() {
+            self.counters.predicted_update_bytes = self.pretty_size;
+        }
 
-    Ok(ParsedSteamPipeLog {
-        counters,
-        offenders,
-    })
+        // Percent offenders get their bytes once the total is known, whichever
+        // line it came on.
+        if let Some(total) = self.counters.predicted_update_bytes {
+            for o in &mut self.offenders {
+                if let Some(pct) = o.percent {
+                    o.bytes = (total as f64 * pct / 100.0).round() as u64;
+                }
+            }
+        }
+
+        if mode == ParseMode::Strict && self.counters.predicted_update_bytes.is_none() {
+            anyhow::bail!("missing required counter PREDICTED_UPDATE_BYTES");
+        }
+
+        Ok(ParsedSteamPipeLog {
+            counters: self.counters,
+            offenders: self.offenders,
+            matches: self.matches,
+        })
+    }
 }
@@ -4,10 +4,121 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     baseline::Baseline,
-    parser::ParseMode,
-    types::{ConfidenceLevel, Finding, Metrics, Severity},
+    parser::{ParseMode, SteamPipeCounters},
+    stats::OffenderStats,
+    types::{
+        human_bytes, truncate_path, BaselineMetric, ConfidenceLevel, DepotClass, FileOffender,
+        Finding, Metrics, Severity,
+    },
 };
 
+/// Number of largest offenders carried per depot.
+pub const DEPOT_TOP_OFFENDERS: usize = 5;
+
+/// Report schema versions `report.json` can be written as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FormatVersion {
+    /// The original schema, without fields added since.
+    V1_0_0,
+    #[default]
+    V1_1_0,
+}
+
+impl FormatVersion {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FormatVersion::V1_0_0 => "1.0.0",
+            FormatVersion::V1_1_0 => "1.1.0",
+        }
+    }
+}
+
+/// `report_version` of reports built by this crate.
+pub const REPORT_VERSION: &str = "1.1.0";
+
+/// Fields of each object in a 1.0.0 report.
+const V1_0_0_FIELDS: &[(&str, &[&str])] = &[
+    (
+        "",
+        &[
+            "report_version",
+            "inputs",
+            "metrics",
+            "confidence",
+            "findings",
+            "baseline_comparison",
+            "budget",
+            "build_metadata",
+            "per_depot",
+        ],
+    ),
+    ("inputs", &["input_path", "parse_mode", "sources"]),
+    (
+        "baseline_comparison",
+        &["baseline_new_bytes", "regression_ratio", "delta_new_bytes"],
+    ),
+    ("budget", &["threshold_regression_ratio", "pass", "reason"]),
+    ("per_depot", &["depot_id", "metrics", "confidence"]),
+    (
+        "findings",
+        &[
+            "id",
+            "severity",
+            "evidence",
+            "likely_cause",
+            "suggested_actions",
+        ],
+    ),
+];
+
+/// Rounds every non-integer number in `value` to `decimals` places, so
+/// serialized reports don't churn on float noise like `0.16200000000001`.
+/// Integers such as byte counts are left alone.
+pub fn round_floats(value: &mut serde_json::Value, decimals: u32) {
+    match value {
+        serde_json::Value::Number(n) if n.is_f64() => {
+            if let Some(x) = n.as_f64() {
+                let scale = 10f64.powi(decimals as i32);
+                if let Some(rounded) = serde_json::Number::from_f64((x * scale).round() / scale) {
+                    *n = rounded;
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for v in items {
+                round_floats(v, decimals);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for v in map.values_mut() {
+                round_floats(v, decimals);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn retain_fields(value: &mut serde_json::Value, fields: &[&str]) {
+    match value {
+        serde_json::Value::Object(map) => map.retain(|k, _| fields.contains(&k.as_str())),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                retain_fields(item, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// JSON pointers that differ between runs over identical inputs (a CI job id
+/// changes on every retry) and are ignored by [`Report::same_as_json`].
+pub const VOLATILE_FIELDS: &[&str] = &["/build_metadata/build_id"];
+
+pub const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Number of largest offenders carried in the report.
+pub const TOP_OFFENDERS: usize = 20;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Report {
     pub report_version: String,
@@ -19,8 +130,168 @@ pub struct Report {
     pub budget: Option<BudgetResult>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub build_metadata: Option<BuildMetadata>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub per_depot: Vec<DepotReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub offenders: Vec<FileOffender>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub offender_diff: Vec<OffenderChange>,
+    #[serde(default, skip_serializing_if = "MetricsExtra::is_empty")]
+    pub metrics_extra: MetricsExtra,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compare_range: Option<CompareRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_counters: Option<RawCounters>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub efficiency_floor: Option<EfficiencyFloorResult>,
+    /// Percentiles over all offenders, including those beyond the top N.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offender_stats: Option<OffenderStats>,
+    /// Depots left out of `per_depot` by `max_depots`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depots_omitted: Option<usize>,
+    /// Verdict strict mode would reach, recorded without gating on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_preview: Option<StrictPreview>,
+}
+
+/// What strict parsing plus the budget gates would conclude for the same
+/// input, so enforcement can be switched on knowing the outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrictPreview {
+    pub pass: bool,
+    pub reason: String,
+}
+
+impl StrictPreview {
+    /// Verdict for the result of analysing the input again in strict mode.
+    pub fn from_result(result: &anyhow::Result<Report>) -> Self {
+        let report = match result {
+            Ok(report) => report,
+            Err(e) => {
+                return Self {
+                    pass: false,
+                    reason: format!("strict parsing failed: {e:#}"),
+                }
+            }
+        };
+        let mut failed: Vec<String> = Vec::new();
+        if let Some(b) = report.budget.as_ref().filter(|b| !b.pass) {
+            failed.push(b.reason.clone());
+        }
+        if let Some(e) = report.efficiency_floor.as_ref().filter(|e| !e.pass) {
+            failed.push(e.reason.clone());
+        }
+        for d in &report.per_depot {
+            if let Some(b) = d.budget.as_ref().filter(|b| !b.pass) {
+                failed.push(format!("depot {}: {}", d.depot_id, b.reason));
+            }
+        }
+        if failed.is_empty() {
+            Self {
+                pass: true,
+                reason: "strict parsing and budget gates pass".to_string(),
+            }
+        } else {
+            Self {
+                pass: false,
+                reason: failed.join("; "),
+            }
+        }
+    }
+}
+
+/// Outcome of the `min_delta_efficiency` gate, which needs no baseline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EfficiencyFloorResult {
+    pub delta_efficiency: f64,
+    pub min_delta_efficiency: f64,
+    pub pass: bool,
+    pub reason: String,
+}
+
+pub fn evaluate_efficiency_floor(delta_efficiency: f64, floor: f64) -> EfficiencyFloorResult {
+    let pass = delta_efficiency >= floor;
+    let reason = if pass {
+        format!("delta_efficiency {delta_efficiency:.3} >= floor {floor:.3}")
+    } else {
+        format!("delta_efficiency {delta_efficiency:.3} < floor {floor:.3}")
+    };
+    EfficiencyFloorResult {
+        delta_efficiency,
+        min_delta_efficiency: floor,
+        pass,
+        reason,
+    }
+}
+
+/// Counters as parsed, before `compute_metrics` fills in missing values;
+/// absent counters serialize as `null`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawCounters {
+    pub predicted_update_bytes: Option<u64>,
+    pub changed_content_bytes: Option<u64>,
+}
+
+impl From<&SteamPipeCounters> for RawCounters {
+    fn from(c: &SteamPipeCounters) -> Self {
+        Self {
+            predicted_update_bytes: c.predicted_update_bytes,
+            changed_content_bytes: c.changed_content_bytes,
+        }
+    }
+}
+
+/// The commit range a report covers, from `base` to the build's `sha`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompareRange {
+    pub base: String,
+    pub head: String,
+    /// Diff link, from a template containing `{base}` and `{head}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl CompareRange {
+    pub fn new(base: &str, head: &str, url_template: Option<&str>) -> Self {
+        Self {
+            base: base.to_string(),
+            head: head.to_string(),
+            url: url_template.map(|t| t.replace("{base}", base).replace("{head}", head)),
+        }
+    }
+}
+
+/// Derived KPIs that summarise other parts of the report.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricsExtra {
+    /// `(depot_id, waste_ratio)` of the depot with the highest waste ratio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worst_depot_waste: Option<(String, f64)>,
+    /// See [`Metrics::unclamped_delta_efficiency`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unclamped_delta_efficiency: Option<f64>,
+}
+
+impl MetricsExtra {
+    pub fn is_empty(&self) -> bool {
+        self.worst_depot_waste.is_none() && self.unclamped_delta_efficiency.is_none()
+    }
+
+    /// Equal waste ratios resolve to the lowest depot id, regardless of the
+    /// order `depots` is in.
+    pub fn from_depots(depots: &[DepotReport]) -> Self {
+        let worst = depots.iter().min_by(|a, b| {
+            b.metrics
+                .waste_ratio
+                .total_cmp(&a.metrics.waste_ratio)
+                .then_with(|| depot_id_cmp(&a.depot_id, &b.depot_id))
+        });
+        Self {
+            worst_depot_waste: worst.map(|d| (d.depot_id.clone(), d.metrics.waste_ratio)),
+            unclamped_delta_efficiency: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +299,13 @@ pub struct Inputs {
     pub input_path: String,
     pub parse_mode: String,
     pub sources: Vec<String>,
+    /// Logs that passed the extension filter but yielded no counters.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub files_without_counters: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,14 +319,124 @@ pub struct ConfidenceSummary {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaselineComparison {
+    /// Metric `regression_ratio` is computed over.
+    #[serde(default)]
+    pub metric: BaselineMetric,
     pub baseline_new_bytes: u64,
     pub regression_ratio: f64,
     pub delta_new_bytes: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_changed_content_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_changed_content_bytes: Option<i64>,
+    /// `(current - baseline) / baseline * 100` over `metric`; `None` for a zero baseline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regression_percent: Option<f64>,
+    /// [`BaselineComparison::regression_percent`] in basis points, rounded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regression_bps: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BudgetResult {
-    pub threshold_regression_ratio: f64,
+    /// The budget always gates on new_bytes, independent of the comparison metric.
+    /// `None` when a [`BudgetExpression`] was evaluated without a baseline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regression_ratio: Option<f64>,
+    /// `None` when a [`BudgetExpression`] sets no regression_ratio maximum.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_regression_ratio: Option<f64>,
+    pub pass: bool,
+    pub reason: String,
+    /// Absolute growth in new_bytes that passes even above the threshold ratio.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance_bytes: Option<u64>,
+    /// Set when the verdict comes from a [`BudgetExpression`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<BudgetPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clauses: Vec<BudgetClauseResult>,
+}
+
+impl BudgetResult {
+    /// What tripped the gate: the failing clauses of a budget expression,
+    /// otherwise the exceeded regression_ratio threshold.
+    pub fn failure_detail(&self) -> String {
+        match self.threshold_regression_ratio {
+            Some(threshold) if self.policy.is_none() => {
+                format!("regression_ratio exceeded threshold {threshold}")
+            }
+            _ => self
+                .clauses
+                .iter()
+                .filter(|c| !c.pass)
+                .map(|c| c.reason.as_str())
+                .collect::<Vec<_>>()
+                .join("; "),
+        }
+    }
+}
+
+/// Compound budget from config: each clause is a failure condition on one
+/// metric, combined by `policy`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetExpression {
+    #[serde(default)]
+    pub policy: BudgetPolicy,
+    pub clauses: Vec<BudgetClause>,
+}
+
+/// `and` fails the budget only when every clause trips; `or` when any does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPolicy {
+    #[default]
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetMetric {
+    /// new_bytes against the baseline, as in the simple budget.
+    RegressionRatio,
+    NewBytes,
+    ChangedContentBytes,
+    DeltaEfficiency,
+    WasteRatio,
+}
+
+impl BudgetMetric {
+    fn as_str(self) -> &'static str {
+        match self {
+            BudgetMetric::RegressionRatio => "regression_ratio",
+            BudgetMetric::NewBytes => "new_bytes",
+            BudgetMetric::ChangedContentBytes => "changed_content_bytes",
+            BudgetMetric::DeltaEfficiency => "delta_efficiency",
+            BudgetMetric::WasteRatio => "waste_ratio",
+        }
+    }
+}
+
+/// Trips when `metric` is above `max` or below `min`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BudgetClause {
+    pub metric: BudgetMetric,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetClauseResult {
+    pub metric: BudgetMetric,
+    /// `None` for `regression_ratio` without a baseline; such a clause passes.
+    pub value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
     pub pass: bool,
     pub reason: String,
 }
@@ -68,6 +456,37 @@ pub struct DepotReport {
     pub depot_id: String,
     pub metrics: Metrics,
     pub confidence: ConfidenceLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<DepotClass>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    /// Set when the depot has its own budget and the baseline has data for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetResult>,
+    /// The depot's largest offenders, at most [`DEPOT_TOP_OFFENDERS`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub offenders: Vec<FileOffender>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OffenderStatus {
+    New,
+    Grown,
+    Shrunk,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OffenderChange {
+    pub path: String,
+    pub status: OffenderStatus,
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_bytes: Option<u64>,
+    /// Set when matched to a baseline offender by basename only (likely rename).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline_path: Option<String>,
 }
 
 impl BuildMetadata {
@@ -90,7 +509,7 @@ impl Report {
     ) -> Self {
         let build_metadata = build_metadata.filter(|m| !m.is_empty());
         Self {
-            report_version: "1.0.0".to_string(),
+            report_version: REPORT_VERSION.to_string(),
             inputs: Inputs {
                 input_path: input.display().to_string(),
                 parse_mode: match mode {
@@ -98,6 +517,7 @@ impl Report {
                     ParseMode::BestEffort => "BEST_EFFORT".to_string(),
                 },
                 sources: vec![],
+                files_without_counters: 0,
             },
             metrics,
             confidence,
@@ -106,12 +526,60 @@ impl Report {
             budget,
             build_metadata,
             per_depot: Vec::new(),
+            offenders: Vec::new(),
+            offender_diff: Vec::new(),
+            metrics_extra: MetricsExtra::default(),
+            compare_range: None,
+            raw_counters: None,
+            efficiency_floor: None,
+            offender_stats: None,
+            depots_omitted: None,
+            strict_preview: None,
+        }
+    }
+
+    /// False when the aggregate budget, the efficiency floor or any per-depot
+    /// budget failed.
+    pub fn budget_passed(&self) -> bool {
+        self.budget.as_ref().is_none_or(|b| b.pass)
+            && self.efficiency_floor.as_ref().is_none_or(|e| e.pass)
+            && self
+                .per_depot
+                .iter()
+                .all(|d| d.budget.as_ref().is_none_or(|b| b.pass))
+    }
+
+    /// `(reason, detail)` of each gate that makes [`Report::budget_passed`] false.
+    fn budget_failures(&self) -> Vec<(&str, String)> {
+        let mut failures = Vec::new();
+        if let Some(b) = self.budget.as_ref().filter(|b| !b.pass) {
+            failures.push((b.reason.as_str(), b.failure_detail()));
         }
+        if let Some(e) = self.efficiency_floor.as_ref().filter(|e| !e.pass) {
+            failures.push((
+                e.reason.as_str(),
+                "delta_efficiency below floor".to_string(),
+            ));
+        }
+        for d in &self.per_depot {
+            if let Some(b) = d.budget.as_ref().filter(|b| !b.pass) {
+                failures.push((
+                    b.reason.as_str(),
+                    format!("depot {}: {}", d.depot_id, b.failure_detail()),
+                ));
+            }
+        }
+        failures
     }
 
     pub fn to_markdown(&self) -> String {
+        self.to_markdown_with(&MarkdownOptions::default())
+    }
+
+    pub fn to_markdown_with(&self, opts: &MarkdownOptions) -> String {
+        let fold = opts.collapsible;
         let mut s = String::new();
-        s.push_str("# patchwaste report\n\n");
+        s.push_str(&format!("# {}\n\n", opts.title));
         s.push_str(&format!("- report_version: `{}`\n", self.report_version));
         s.push_str(&format!("- input_path: `{}`\n", self.inputs.input_path));
         s.push_str(&format!("- parse_mode: `{}`\n", self.inputs.parse_mode));
@@ -127,6 +595,15 @@ impl Report {
             "- delta_efficiency: `{:.3}`\n",
             self.metrics.delta_efficiency
         ));
+        if let Some(unclamped) = self
+            .metrics_extra
+            .unclamped_delta_efficiency
+            .filter(|&e| e > 1.0)
+        {
+            s.push_str(&format!(
+                "- unclamped_delta_efficiency: `{unclamped:.3}` (changed content exceeds new bytes)\n"
+            ));
+        }
         s.push_str(&format!(
             "- waste_ratio: `{:.3}`\n",
             self.metrics.waste_ratio
@@ -135,31 +612,77 @@ impl Report {
 
         if let Some(cmp) = &self.baseline_comparison {
             s.push_str("## Baseline comparison\n\n");
+            s.push_str(&format!("- metric: `{}`\n", cmp.metric.as_str()));
             s.push_str(&format!(
                 "- baseline_new_bytes: `{}`\n",
                 cmp.baseline_new_bytes
             ));
             s.push_str(&format!("- delta_new_bytes: `{}`\n", cmp.delta_new_bytes));
+            if let (Some(base), Some(delta)) = (
+                cmp.baseline_changed_content_bytes,
+                cmp.delta_changed_content_bytes,
+            ) {
+                s.push_str(&format!("- baseline_changed_content_bytes: `{}`\n", base));
+                s.push_str(&format!("- delta_changed_content_bytes: `{}`\n", delta));
+            }
             s.push_str(&format!(
                 "- regression_ratio: `{:.3}`\n",
                 cmp.regression_ratio
             ));
+            if let Some(pct) = cmp.regression_percent {
+                s.push_str(&format!("- regression_percent: `{:+.1}%`\n", pct));
+            }
+            if let Some(bps) = cmp.regression_bps.filter(|_| opts.regression_bps) {
+                s.push_str(&format!("- regression_bps: `{bps:+}`\n"));
+            }
             s.push('\n');
         }
 
         if let Some(b) = &self.budget {
             s.push_str("## Budget gate\n\n");
-            s.push_str(&format!(
-                "- threshold_regression_ratio: `{:.3}`\n",
-                b.threshold_regression_ratio
-            ));
+            if let Some(threshold) = b.threshold_regression_ratio {
+                s.push_str(&format!("- threshold_regression_ratio: `{threshold:.3}`\n"));
+            }
             s.push_str(&format!("- pass: `{}`\n", b.pass));
             s.push_str(&format!("- reason: `{}`\n", b.reason));
+            for c in &b.clauses {
+                let mark = if c.pass { "pass" } else { "fail" };
+                s.push_str(&format!("  - {mark}: {}\n", c.reason));
+            }
+            s.push('\n');
+        }
+
+        if let Some(e) = &self.efficiency_floor {
+            s.push_str("## Efficiency floor\n\n");
+            s.push_str(&format!(
+                "- min_delta_efficiency: `{:.3}`\n",
+                e.min_delta_efficiency
+            ));
+            s.push_str(&format!("- pass: `{}`\n", e.pass));
+            s.push_str(&format!("- reason: `{}`\n", e.reason));
+            s.push('\n');
+        }
+
+        if let Some(p) = &self.strict_preview {
+            s.push_str("## Strict preview\n\n");
+            s.push_str(&format!("- pass: `{}`\n", p.pass));
+            s.push_str(&format!("- reason: `{}`\n", p.reason));
+            s.push_str("- not gating: exit code follows the current settings\n");
             s.push('\n');
         }
 
         if !self.per_depot.is_empty() {
             s.push_str("## Per-depot metrics\n\n");
+            let high = self
+                .per_depot
+                .iter()
+                .filter(|d| d.confidence == ConfidenceLevel::High)
+                .count();
+            s.push_str(&format!(
+                "{high}/{} depots High confidence\n\n",
+                self.per_depot.len()
+            ));
+            details_open(&mut s, fold, &format!("{} depots", self.per_depot.len()));
             for d in &self.per_depot {
                 s.push_str(&format!("### Depot {}\n", d.depot_id));
                 s.push_str(&format!("- new_bytes: `{}`\n", d.metrics.new_bytes));
@@ -169,8 +692,71 @@ impl Report {
                 ));
                 s.push_str(&format!("- waste_ratio: `{:.3}`\n", d.metrics.waste_ratio));
                 s.push_str(&format!("- confidence: `{:?}`\n", d.confidence));
+                if let Some(class) = d.class {
+                    s.push_str(&format!("- class: `{:?}`\n", class));
+                }
+                if let Some(b) = &d.budget {
+                    s.push_str(&format!(
+                        "- budget: `{}` ({})\n",
+                        if b.pass { "PASS" } else { "FAIL" },
+                        b.reason
+                    ));
+                }
+                if !d.findings.is_empty() {
+                    let ids: Vec<&str> = d.findings.iter().map(|f| f.id.as_str()).collect();
+                    s.push_str(&format!("- findings: `{}`\n", ids.join("`, `")));
+                }
+                if !d.offenders.is_empty() {
+                    s.push_str("- top offenders:\n");
+                    for o in &d.offenders {
+                        s.push_str(&format!("  - `{}` ({} bytes)\n", o.path, o.bytes));
+                    }
+                }
                 s.push('\n');
             }
+            if let Some(n) = self.depots_omitted {
+                s.push_str(&format!("{n} smaller depots omitted (max_depots)\n\n"));
+            }
+            details_close(&mut s, fold);
+        }
+
+        if !self.offenders.is_empty() {
+            s.push_str("## Top offenders\n\n");
+            details_open(&mut s, fold, &format!("{} offenders", self.offenders.len()));
+            for o in &self.offenders {
+                s.push_str(&format!("- {} (`{}` bytes)\n", o.path, o.bytes));
+            }
+            if let Some(st) = &self.offender_stats {
+                s.push_str(&format!(
+                    "\n{} offenders in total; p50 `{}`, p90 `{}`, p99 `{}` bytes (estimated)\n",
+                    st.count, st.p50_bytes, st.p90_bytes, st.p99_bytes
+                ));
+            }
+            s.push('\n');
+            details_close(&mut s, fold);
+        }
+
+        if !self.offender_diff.is_empty() {
+            s.push_str("## Offender changes\n\n");
+            details_open(
+                &mut s,
+                fold,
+                &format!("{} changes", self.offender_diff.len()),
+            );
+            for c in &self.offender_diff {
+                match c.baseline_bytes {
+                    Some(base) => s.push_str(&format!(
+                        "- `{:?}` {} ({} -> {} bytes)\n",
+                        c.status, c.path, base, c.bytes
+                    )),
+                    None => s.push_str(&format!(
+                        "- `{:?}` {} ({} bytes)\n",
+                        c.status, c.path, c.bytes
+                    )),
+                }
+            }
+            s.push('\n');
+            details_close(&mut s, fold);
         }
 
         if let Some(meta) = &self.build_metadata {
@@ -184,6 +770,13 @@ impl Report {
             if let Some(build_id) = &meta.build_id {
                 s.push_str(&format!("- build_id: `{}`\n", build_id));
             }
+            if let Some(range) = &self.compare_range {
+                let text = format!("{}...{}", range.base, range.head);
+                match &range.url {
+                    Some(url) => s.push_str(&format!("- compare: [`{}`]({})\n", text, url)),
+                    None => s.push_str(&format!("- compare: `{}`\n", text)),
+                }
+            }
             s.push('\n');
         }
 
@@ -191,9 +784,31 @@ impl Report {
         if self.findings.is_empty() {
             s.push_str("- (none)\n");
         } else {
-            for f in &self.findings {
+            let overflow = fold && self.findings.len() > opts.top_findings;
+            for (i, f) in self.findings.iter().enumerate() {
+                if overflow && i == opts.top_findings {
+                    details_open(
+                        &mut s,
+                        true,
+                        &format!("{} more findings", self.findings.len() - i),
+                    );
+                }
                 s.push_str(&format!("### {}\n", f.id));
-                s.push_str(&format!("- severity: `{:?}`\n", f.severity));
+                if opts.severity_colour {
+                    s.push_str(&format!(
+                        "- severity: <span style=\"color:{}\">{:?}</span>\n",
+                        severity_html_colour(f.severity),
+                        f.severity
+                    ));
+                } else {
+                    s.push_str(&format!("- severity: `{:?}`\n", f.severity));
+                }
+                if f.accepted {
+                    s.push_str("- accepted: `true`\n");
+                }
+                if let Some(label) = &f.source_report {
+                    s.push_str(&format!("- source_report: `{}`\n", label));
+                }
                 s.push_str(&format!("- likely_cause: {}\n", f.likely_cause));
                 if !f.evidence.is_empty() {
                     s.push_str("- evidence:\n");
@@ -209,99 +824,932 @@ impl Report {
                 }
                 s.push('\n');
             }
+            details_close(&mut s, overflow);
         }
 
-        s
+        s
+    }
+
+    /// Highest severity among findings that weren't accepted.
+    pub fn worst_severity(&self) -> Option<Severity> {
+        self.findings
+            .iter()
+            .filter(|f| !f.accepted)
+            .map(|f| f.severity)
+            .max()
+    }
+
+    /// Marks findings with the given ids, aggregate and per depot, as
+    /// accepted and demotes them to `LOW`.
+    pub fn accept_findings(&mut self, ids: &[String]) {
+        let depot_findings = self.per_depot.iter_mut().flat_map(|d| &mut d.findings);
+        for f in self.findings.iter_mut().chain(depot_findings) {
+            if ids.contains(&f.id) {
+                f.severity = Severity::Low;
+                f.accepted = true;
+            }
+        }
+    }
+
+    /// Shortens offender paths for display. Call after any path-based matching
+    /// (such as the baseline offender diff), which needs the full paths.
+    pub fn truncate_offender_paths(&mut self, max: usize) {
+        let depot_offenders = self.per_depot.iter_mut().flat_map(|d| &mut d.offenders);
+        for o in self.offenders.iter_mut().chain(depot_offenders) {
+            o.path = truncate_path(&o.path, max);
+        }
+        for c in &mut self.offender_diff {
+            c.path = truncate_path(&c.path, max);
+            if let Some(p) = &mut c.baseline_path {
+                *p = truncate_path(p, max);
+            }
+        }
+    }
+
+    /// Rewrites the input path and log sources relative to `root` with `/`
+    /// separators, so reports from different checkouts compare equal. Paths
+    /// outside `root` are left unchanged.
+    pub fn relativize_paths(&mut self, root: &Path) {
+        self.inputs.input_path = relative_to(&self.inputs.input_path, root);
+        for s in &mut self.inputs.sources {
+            *s = relative_to(s, root);
+        }
+        let depot_offenders = self.per_depot.iter_mut().flat_map(|d| &mut d.offenders);
+        for o in self.offenders.iter_mut().chain(depot_offenders) {
+            for s in &mut o.sources {
+                *s = relative_to(s, root);
+            }
+        }
+    }
+
+    /// Records `label` as the input path, and as the prefix of log sources
+    /// found under `input`, in place of the directory actually scanned.
+    pub fn relabel_input_root(&mut self, input: &Path, label: &str) {
+        let relabel = |path: &mut String| {
+            *path = match relative_part(path, input).as_deref() {
+                Some(".") => label.to_string(),
+                Some(rel) => format!("{}/{rel}", label.trim_end_matches('/')),
+                None => return,
+            };
+        };
+        self.inputs.input_path = label.to_string();
+        for s in &mut self.inputs.sources {
+            relabel(s);
+        }
+        let depot_offenders = self.per_depot.iter_mut().flat_map(|d| &mut d.offenders);
+        for o in self.offenders.iter_mut().chain(depot_offenders) {
+            for s in &mut o.sources {
+                relabel(s);
+            }
+        }
+    }
+
+    /// Sorts collections whose order depends on map iteration so repeated runs
+    /// produce identical output.
+    pub fn canonicalize(&mut self) {
+        self.findings.sort_by(|a, b| a.id.cmp(&b.id));
+        self.per_depot
+            .sort_by(|a, b| depot_id_cmp(&a.depot_id, &b.depot_id));
+        for d in &mut self.per_depot {
+            d.findings.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+    }
+
+    /// Top offenders as CSV with a `path,bytes,sources` header; sources are
+    /// separated by `;`.
+    pub fn offenders_csv(&self) -> String {
+        let mut out = String::from("path,bytes,sources\n");
+        for o in &self.offenders {
+            out.push_str(&format!(
+                "{},{},{}\n",
+                csv_field(&o.path),
+                o.bytes,
+                csv_field(&o.sources.join(";"))
+            ));
+        }
+        out
+    }
+
+    /// Findings as CSV with an `id,severity,cause_category,likely_cause,evidence`
+    /// header; evidence lines are joined with `; `.
+    pub fn findings_csv(&self) -> String {
+        let mut out = String::from("id,severity,cause_category,likely_cause,evidence\n");
+        for f in &self.findings {
+            out.push_str(&format!(
+                "{},{:?},{},{},{}\n",
+                csv_field(&f.id),
+                f.severity,
+                f.cause_category(),
+                csv_field(&f.likely_cause),
+                csv_field(&f.evidence.join("; "))
+            ));
+        }
+        out
+    }
+
+    /// `KEY=value` lines a shell can `source`: `PATCHWASTE_<METRIC>` for the
+    /// totals and `PATCHWASTE_DEPOT_<n>_<METRIC>` per depot, numbered from 0.
+    /// Strings are single-quoted.
+    pub fn to_env(&self) -> String {
+        let mut out = String::new();
+        let mut metrics = |prefix: &str, m: &Metrics| {
+            out.push_str(&format!("{prefix}NEW_BYTES={}\n", m.new_bytes));
+            out.push_str(&format!(
+                "{prefix}CHANGED_CONTENT_BYTES={}\n",
+                m.changed_content_bytes
+            ));
+            out.push_str(&format!(
+                "{prefix}DELTA_EFFICIENCY={}\n",
+                m.delta_efficiency
+            ));
+            out.push_str(&format!("{prefix}WASTE_RATIO={}\n", m.waste_ratio));
+        };
+        metrics("PATCHWASTE_", &self.metrics);
+        for (i, d) in self.per_depot.iter().enumerate() {
+            metrics(&format!("PATCHWASTE_DEPOT_{i}_"), &d.metrics);
+        }
+
+        out.push_str(&format!(
+            "PATCHWASTE_CONFIDENCE={}\n",
+            shell_quote(&format!("{:?}", self.confidence.overall))
+        ));
+        out.push_str(&format!("PATCHWASTE_FINDINGS={}\n", self.findings.len()));
+        out.push_str(&format!(
+            "PATCHWASTE_BUDGET_PASSED={}\n",
+            self.budget_passed()
+        ));
+        out.push_str(&format!("PATCHWASTE_DEPOTS={}\n", self.per_depot.len()));
+        for (i, d) in self.per_depot.iter().enumerate() {
+            out.push_str(&format!(
+                "PATCHWASTE_DEPOT_{i}_ID={}\n",
+                shell_quote(&d.depot_id)
+            ));
+        }
+        out
+    }
+
+    /// `baseline_comparison` and `build_metadata` on their own, for
+    /// integrations that don't want the full report. `None` without a baseline.
+    pub fn comparison_json(&self) -> Option<serde_json::Value> {
+        let comparison = self.baseline_comparison.as_ref()?;
+        let mut value = serde_json::json!({ "baseline_comparison": comparison });
+        if let Some(meta) = &self.build_metadata {
+            value["build_metadata"] = serde_json::json!(meta);
+        }
+        Some(value)
+    }
+
+    /// Changes from this report (the base) to `other` (the head): metric
+    /// deltas and findings that appeared or disappeared, matched by id.
+    pub fn diff(&self, other: &Report) -> ReportDiff {
+        let (a, b) = (&self.metrics, &other.metrics);
+        let ids = |r: &Report| {
+            r.findings
+                .iter()
+                .map(|f| f.id.clone())
+                .collect::<std::collections::BTreeSet<_>>()
+        };
+        let (base_ids, head_ids) = (ids(self), ids(other));
+        ReportDiff {
+            metrics: vec![
+                MetricDelta::new("new_bytes", a.new_bytes as f64, b.new_bytes as f64, true),
+                MetricDelta::new(
+                    "changed_content_bytes",
+                    a.changed_content_bytes as f64,
+                    b.changed_content_bytes as f64,
+                    false,
+                ),
+                MetricDelta::new("waste_ratio", a.waste_ratio, b.waste_ratio, true),
+            ],
+            findings_added: head_ids.difference(&base_ids).cloned().collect(),
+            findings_removed: base_ids.difference(&head_ids).cloned().collect(),
+        }
+    }
+
+    /// The parts of this report [`Baseline::from_report_json`] reads: metrics,
+    /// offenders and per-depot new_bytes, plus `created_at` (Unix seconds)
+    /// when given.
+    pub fn to_baseline_json(&self, created_at: Option<u64>) -> serde_json::Value {
+        let per_depot: Vec<serde_json::Value> = self
+            .per_depot
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "depot_id": d.depot_id,
+                    "metrics": { "new_bytes": d.metrics.new_bytes },
+                })
+            })
+            .collect();
+        let mut value = serde_json::json!({
+            "metrics": {
+                "new_bytes": self.metrics.new_bytes,
+                "changed_content_bytes": self.metrics.changed_content_bytes,
+            },
+        });
+        if !self.offenders.is_empty() {
+            value["offenders"] = serde_json::json!(self.offenders);
+        }
+        if !per_depot.is_empty() {
+            value["per_depot"] = serde_json::Value::Array(per_depot);
+        }
+        if let Some(t) = created_at {
+            value["created_at"] = serde_json::json!(t);
+        }
+        value
+    }
+
+    /// Findings as a SARIF 2.1.0 log for code scanning UIs. Levels follow
+    /// severity (High `error`, Medium `warning`, Low `note`); offender paths
+    /// named in a finding's evidence become its locations.
+    pub fn to_sarif(&self) -> serde_json::Value {
+        let level = |s: Severity| match s {
+            Severity::High => "error",
+            Severity::Medium => "warning",
+            Severity::Low => "note",
+        };
+        let mut rules: Vec<serde_json::Value> = Vec::new();
+        let mut results: Vec<serde_json::Value> = Vec::new();
+        for f in &self.findings {
+            if !rules.iter().any(|r| r["id"] == f.id.as_str()) {
+                rules.push(serde_json::json!({
+                    "id": f.id,
+                    "shortDescription": { "text": f.likely_cause },
+                }));
+            }
+            let locations: Vec<serde_json::Value> = self
+                .offenders
+                .iter()
+                .filter(|o| f.evidence.iter().any(|e| e.contains(&o.path)))
+                .map(|o| {
+                    serde_json::json!({
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": o.path.replace('\\', "/") },
+                        },
+                    })
+                })
+                .collect();
+            let mut result = serde_json::json!({
+                "ruleId": f.id,
+                "level": level(f.severity),
+                "message": { "text": f.likely_cause },
+            });
+            if !f.evidence.is_empty() {
+                result["properties"] = serde_json::json!({ "evidence": f.evidence });
+            }
+            if !locations.is_empty() {
+                result["locations"] = serde_json::Value::Array(locations);
+            }
+            results.push(result);
+        }
+        serde_json::json!({
+            "$schema": SARIF_SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "patchwaste",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        })
+    }
+
+    /// Top offenders as newline-delimited JSON, one object per offender.
+    pub fn offenders_ndjson(&self) -> serde_json::Result<String> {
+        let mut out = String::new();
+        for o in &self.offenders {
+            out.push_str(&serde_json::to_string(&serde_json::json!({
+                "path": o.path,
+                "bytes": o.bytes,
+                "sources": o.sources,
+            }))?);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    /// Streams pretty JSON to `w` without buffering the whole document.
+    pub fn write_json<W: std::io::Write>(&self, w: W) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(w, self)
+    }
+
+    /// The report as JSON in the given schema version; fields newer than
+    /// `version` are dropped.
+    pub fn to_json_value(&self, version: FormatVersion) -> serde_json::Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        if version == FormatVersion::V1_0_0 {
+            // 1.0.0 requires a numeric threshold, which an expression without
+            // a regression_ratio clause can't supply.
+            if self
+                .budget
+                .as_ref()
+                .is_some_and(|b| b.threshold_regression_ratio.is_none())
+            {
+                value["budget"] = serde_json::Value::Null;
+            }
+            for (key, fields) in V1_0_0_FIELDS {
+                match *key {
+                    "" => retain_fields(&mut value, fields),
+                    key => {
+                        if let Some(v) = value.get_mut(key) {
+                            retain_fields(v, fields);
+                        }
+                    }
+                }
+            }
+        }
+        value["report_version"] = version.as_str().into();
+        Ok(value)
+    }
+
+    /// Whether `existing`, a previously written `report.json`, holds the same
+    /// report once both are canonicalized and [`VOLATILE_FIELDS`] are dropped.
+    /// Unparseable input never matches.
+    pub fn same_as_json(&self, existing: &[u8]) -> bool {
+        let comparable = |mut report: Report| {
+            report.canonicalize();
+            let mut value = serde_json::to_value(&report).ok()?;
+            for pointer in VOLATILE_FIELDS {
+                let (parent, key) = pointer.rsplit_once('/')?;
+                if let Some(obj) = value.pointer_mut(parent).and_then(|v| v.as_object_mut()) {
+                    obj.remove(key);
+                }
+            }
+            Some(value)
+        };
+        let Ok(previous) = serde_json::from_slice::<Report>(existing) else {
+            return false;
+        };
+        match (comparable(self.clone()), comparable(previous)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Takes the [`VOLATILE_FIELDS`] from `previous`, a report kept because
+    /// [`Report::same_as_json`] matched it, so every output rendered from
+    /// this report agrees with the kept `report.json`.
+    pub fn adopt_volatile_fields(&mut self, previous: &Report) {
+        if let (Some(meta), Some(prev)) = (&mut self.build_metadata, &previous.build_metadata) {
+            meta.build_id = prev.build_id.clone();
+        }
+    }
+
+    /// Pretty JSON with canonical collection order and lexicographically sorted
+    /// object keys (`serde_json::Value` maps are ordered), for committed reports.
+    pub fn to_canonical_json(&self) -> serde_json::Result<Vec<u8>> {
+        let mut report = self.clone();
+        report.canonicalize();
+        let value = serde_json::to_value(&report)?;
+        serde_json::to_vec_pretty(&value)
+    }
+
+    /// Compact GitHub-flavoured markdown for PR comments. Findings are folded
+    /// into a `<details>` block to stay well under comment size limits.
+    pub fn to_pr_comment(&self) -> String {
+        let (label, colour) = if self.budget_passed() {
+            ("PASS", "success")
+        } else {
+            ("FAIL", "critical")
+        };
+
+        let mut s = String::new();
+        s.push_str(&format!(
+            "![patchwaste: {label}](https://img.shields.io/badge/patchwaste-{label}-{colour})\n\n"
+        ));
+        if let Some(sha) = self.build_metadata.as_ref().and_then(|m| m.sha.as_ref()) {
+            s.push_str(&format!("Commit: `{}`\n\n", sha));
+        }
+
+        s.push_str("| metric | value |\n");
+        s.push_str("| --- | --- |\n");
+        s.push_str(&format!(
+            "| new_bytes | `{}` ({}) |\n",
+            self.metrics.new_bytes,
+            human_bytes(self.metrics.new_bytes)
+        ));
+        s.push_str(&format!(
+            "| changed_content_bytes | `{}` ({}) |\n",
+            self.metrics.changed_content_bytes,
+            human_bytes(self.metrics.changed_content_bytes)
+        ));
+        s.push_str(&format!(
+            "| waste_ratio | `{:.3}` |\n",
+            self.metrics.waste_ratio
+        ));
+        s.push_str(&format!(
+            "| delta_efficiency | `{:.3}` |\n",
+            self.metrics.delta_efficiency
+        ));
+        if let Some(cmp) = &self.baseline_comparison {
+            s.push_str(&format!(
+                "| regression_ratio | `{:.3}` |\n",
+                cmp.regression_ratio
+            ));
+        }
+        if let Some(b) = &self.budget {
+            match b.threshold_regression_ratio {
+                Some(threshold) => {
+                    s.push_str(&format!("| budget | `{threshold:.3}` ({}) |\n", b.reason))
+                }
+                None => s.push_str(&format!("| budget | {} |\n", b.reason)),
+            }
+        }
+        s.push('\n');
+
+        if self.findings.is_empty() {
+            s.push_str("No findings.\n");
+        } else {
+            s.push_str(&format!(
+                "<details>\n<summary>Findings ({})</summary>\n\n",
+                self.findings.len()
+            ));
+            for f in &self.findings {
+                s.push_str(&format!(
+                    "- **{:?}** `{}`: {}",
+                    f.severity, f.id, f.likely_cause
+                ));
+                if !f.evidence.is_empty() {
+                    s.push_str(&format!(" ({})", f.evidence.join("; ")));
+                }
+                s.push('\n');
+            }
+            s.push_str("\n</details>\n");
+        }
+
+        s
+    }
+
+    pub fn to_junit_xml(&self) -> String {
+        self.to_junit_xml_with(&JunitOptions::default())
+    }
+
+    pub fn to_junit_xml_with(&self, opts: &JunitOptions) -> String {
+        let mut x = String::new();
+        x.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+        let total = self.findings.len() + 1; // +1 for budget gate testcase
+        let failures: usize = self
+            .findings
+            .iter()
+            .filter(|f| f.severity == Severity::High)
+            .count()
+            + if self.budget_passed() { 0 } else { 1 };
+
+        x.push_str(&format!(
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            xml_escape(&opts.suite_name),
+            total,
+            failures
+        ));
+        let prefix = xml_escape(&opts.classname_prefix);
+
+        for f in &self.findings {
+            x.push_str(&format!(
+                "  <testcase name=\"{}\" classname=\"{}.findings\"",
+                xml_escape(&f.id),
+                prefix
+            ));
+            if f.severity == Severity::High {
+                x.push_str(">\n");
+                x.push_str(&format!(
+                    "    <failure message=\"{}\">{}</failure>\n",
+                    xml_escape(&f.likely_cause),
+                    xml_escape(&f.evidence.join("; "))
+                ));
+                x.push_str("  </testcase>\n");
+            } else {
+                x.push_str(" />\n");
+            }
+        }
+
+        // Budget gate testcase
+        x.push_str(&format!(
+            "  <testcase name=\"budget_gate\" classname=\"{}.budget\"",
+            prefix
+        ));
+        let failures = self.budget_failures();
+        if failures.is_empty() {
+            x.push_str(" />\n");
+        } else {
+            let (reasons, details): (Vec<&str>, Vec<String>) = failures.into_iter().unzip();
+            x.push_str(">\n");
+            x.push_str(&format!(
+                "    <failure message=\"{}\">{}</failure>\n",
+                xml_escape(&reasons.join("; ")),
+                xml_escape(&details.join("; "))
+            ));
+            x.push_str("  </testcase>\n");
+        }
+
+        x.push_str("</testsuite>\n");
+        x
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+    /// Fold per-depot, offender and overflow findings sections into `<details>` blocks.
+    pub collapsible: bool,
+    /// Findings shown before the remainder is folded, when `collapsible` is set.
+    pub top_findings: usize,
+    /// Wrap finding severities in coloured `<span>`s, using the CLI's colour scheme.
+    pub severity_colour: bool,
+    /// Top-level heading.
+    pub title: String,
+    /// Show the baseline regression in basis points.
+    pub regression_bps: bool,
+}
+
+/// Markdown heading used when no `--report-title` is given.
+pub const DEFAULT_REPORT_TITLE: &str = "patchwaste report";
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            collapsible: false,
+            top_findings: 5,
+            severity_colour: false,
+            title: DEFAULT_REPORT_TITLE.to_string(),
+            regression_bps: false,
+        }
+    }
+}
+
+fn severity_html_colour(sev: Severity) -> &'static str {
+    match sev {
+        Severity::High => "red",
+        Severity::Medium => "goldenrod",
+        Severity::Low => "gray",
+    }
+}
+
+/// Names used in [`Report::to_junit_xml_with`] output.
+#[derive(Debug, Clone)]
+pub struct JunitOptions {
+    /// `<testsuite name>`.
+    pub suite_name: String,
+    /// Prepended to the `findings` and `budget` testcase classnames.
+    pub classname_prefix: String,
+}
+
+impl Default for JunitOptions {
+    fn default() -> Self {
+        Self {
+            suite_name: "patchwaste".to_string(),
+            classname_prefix: "patchwaste".to_string(),
+        }
+    }
+}
+
+fn details_open(s: &mut String, enabled: bool, summary: &str) {
+    if enabled {
+        s.push_str(&format!("<details>\n<summary>{}</summary>\n\n", summary));
+    }
+}
+
+fn details_close(s: &mut String, enabled: bool) {
+    if enabled {
+        s.push_str("</details>\n\n");
+    }
+}
+
+fn relative_to(path: &str, root: &Path) -> String {
+    relative_part(path, root).unwrap_or_else(|| path.to_string())
+}
+
+/// `path` relative to `root` with `/` separators (`.` for `root` itself), or
+/// `None` when it lies outside `root`.
+fn relative_part(path: &str, root: &Path) -> Option<String> {
+    let p = Path::new(path);
+    let rel = p
+        .strip_prefix(root)
+        .map(Path::to_path_buf)
+        .ok()
+        .or_else(|| {
+            let p = p.canonicalize().ok()?;
+            let root = root.canonicalize().ok()?;
+            p.strip_prefix(root).map(Path::to_path_buf).ok()
+        });
+    let rel = rel?;
+    if rel.as_os_str().is_empty() {
+        return Some(".".to_string());
+    }
+    Some(
+        rel.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Numeric order for numeric depot ids, falling back to string order.
+fn depot_id_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y),
+        _ => a.cmp(b),
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// POSIX single-quoted string; embedded quotes become `'\''`.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn compare_to_baseline(
+    b: &Baseline,
+    metrics: &Metrics,
+    metric: BaselineMetric,
+) -> BaselineComparison {
+    let (baseline, current) = match metric {
+        BaselineMetric::NewBytes => (b.baseline_new_bytes, metrics.new_bytes),
+        BaselineMetric::ChangedContentBytes => (
+            b.baseline_changed_content_bytes,
+            metrics.changed_content_bytes,
+        ),
+    };
+    let changed = (metric == BaselineMetric::ChangedContentBytes).then_some((
+        b.baseline_changed_content_bytes,
+        metrics.changed_content_bytes as i64 - b.baseline_changed_content_bytes as i64,
+    ));
+
+    BaselineComparison {
+        metric,
+        baseline_new_bytes: b.baseline_new_bytes,
+        regression_ratio: regression_ratio(baseline, current),
+        delta_new_bytes: metrics.new_bytes as i64 - b.baseline_new_bytes as i64,
+        baseline_changed_content_bytes: changed.map(|(base, _)| base),
+        delta_changed_content_bytes: changed.map(|(_, delta)| delta),
+        regression_percent: regression_percent(baseline, current),
+        regression_bps: regression_bps(baseline, current),
+    }
+}
+
+/// Gates `current` new_bytes against `baseline`. Growth of at most
+/// `tolerance_bytes` passes even when the ratio exceeds `threshold`.
+pub fn evaluate_budget(
+    baseline: u64,
+    current: u64,
+    threshold: f64,
+    tolerance_bytes: Option<u64>,
+) -> BudgetResult {
+    let ratio = regression_ratio(baseline, current);
+    let within_ratio = ratio <= threshold;
+    let within_tolerance = tolerance_bytes.is_some_and(|t| current.saturating_sub(baseline) <= t);
+    let pass = within_ratio || within_tolerance;
+    BudgetResult {
+        regression_ratio: Some(ratio),
+        threshold_regression_ratio: Some(threshold),
+        pass,
+        reason: if within_ratio {
+            "within regression budget".to_string()
+        } else if within_tolerance {
+            format!(
+                "regression_ratio {:.3} exceeds threshold {:.3} but growth of {} bytes is within tolerance",
+                ratio,
+                threshold,
+                current.saturating_sub(baseline)
+            )
+        } else {
+            format!(
+                "regression_ratio {:.3} exceeds threshold {:.3}",
+                ratio, threshold
+            )
+        },
+        tolerance_bytes,
+        policy: None,
+        clauses: Vec::new(),
     }
+}
 
-    pub fn to_junit_xml(&self) -> String {
-        let mut x = String::new();
-        x.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+/// Evaluates every clause of `expr`; `regression_ratio` needs `baseline`.
+pub fn evaluate_budget_expression(
+    expr: &BudgetExpression,
+    metrics: &Metrics,
+    baseline: Option<&Baseline>,
+) -> BudgetResult {
+    let ratio = baseline.map(|b| regression_ratio(b.baseline_new_bytes, metrics.new_bytes));
+    let clauses: Vec<BudgetClauseResult> = expr
+        .clauses
+        .iter()
+        .map(|c| {
+            let value = match c.metric {
+                BudgetMetric::RegressionRatio => ratio,
+                BudgetMetric::NewBytes => Some(metrics.new_bytes as f64),
+                BudgetMetric::ChangedContentBytes => Some(metrics.changed_content_bytes as f64),
+                BudgetMetric::DeltaEfficiency => Some(metrics.delta_efficiency),
+                BudgetMetric::WasteRatio => Some(metrics.waste_ratio),
+            };
+            let name = c.metric.as_str();
+            let reason = match value {
+                None => format!("{name} needs a baseline"),
+                Some(v) => match (c.min.filter(|min| v < *min), c.max.filter(|max| v > *max)) {
+                    (Some(min), _) => format!("{name} {v:.3} < min {min:.3}"),
+                    (_, Some(max)) => format!("{name} {v:.3} > max {max:.3}"),
+                    _ => format!("{name} {v:.3} within limits"),
+                },
+            };
+            let tripped = value.is_some_and(|v| {
+                c.min.is_some_and(|min| v < min) || c.max.is_some_and(|max| v > max)
+            });
+            BudgetClauseResult {
+                metric: c.metric,
+                value,
+                min: c.min,
+                max: c.max,
+                pass: !tripped,
+                reason,
+            }
+        })
+        .collect();
 
-        let total = self.findings.len() + 1; // +1 for budget gate testcase
-        let failures: usize = self
-            .findings
+    let failed = clauses.iter().filter(|c| !c.pass).count();
+    let pass = match expr.policy {
+        BudgetPolicy::And => clauses.is_empty() || failed < clauses.len(),
+        BudgetPolicy::Or => failed == 0,
+    };
+    let policy = match expr.policy {
+        BudgetPolicy::And => "and",
+        BudgetPolicy::Or => "or",
+    };
+    BudgetResult {
+        regression_ratio: ratio,
+        threshold_regression_ratio: expr
+            .clauses
             .iter()
-            .filter(|f| f.severity == Severity::High)
-            .count()
-            + if self.budget.as_ref().is_some_and(|b| !b.pass) {
-                1
-            } else {
-                0
-            };
+            .find(|c| c.metric == BudgetMetric::RegressionRatio)
+            .and_then(|c| c.max),
+        pass,
+        reason: format!(
+            "{failed} of {} clauses failed ({policy} policy)",
+            clauses.len()
+        ),
+        tolerance_bytes: None,
+        policy: Some(expr.policy),
+        clauses,
+    }
+}
 
-        x.push_str(&format!(
-            "<testsuite name=\"patchwaste\" tests=\"{}\" failures=\"{}\">\n",
-            total, failures
-        ));
+/// `(current - baseline) / baseline * 10000`, rounded; `None` for a zero baseline.
+pub fn regression_bps(baseline: u64, current: u64) -> Option<i64> {
+    regression_percent(baseline, current).map(|pct| (pct * 100.0).round() as i64)
+}
 
-        for f in &self.findings {
-            x.push_str(&format!(
-                "  <testcase name=\"{}\" classname=\"patchwaste.findings\"",
-                xml_escape(&f.id)
-            ));
-            if f.severity == Severity::High {
-                x.push_str(">\n");
-                x.push_str(&format!(
-                    "    <failure message=\"{}\">{}</failure>\n",
-                    xml_escape(&f.likely_cause),
-                    xml_escape(&f.evidence.join("; "))
-                ));
-                x.push_str("  </testcase>\n");
-            } else {
-                x.push_str(" />\n");
-            }
+pub fn regression_percent(baseline: u64, current: u64) -> Option<f64> {
+    (baseline > 0).then(|| (current as f64 - baseline as f64) / baseline as f64 * 100.0)
+}
+
+fn normalize_offender_path(path: &str) -> String {
+    let p = path.replace('\\', "/").to_lowercase();
+    p.trim_start_matches("./").to_string()
+}
+
+fn offender_basename(path: &str) -> String {
+    let p = normalize_offender_path(path);
+    p.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+/// How one metric moved between two reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricDelta {
+    pub metric: String,
+    pub base: f64,
+    pub head: f64,
+    /// `(head - base) / base`; `None` when `base` is 0.
+    pub change: Option<f64>,
+    /// Whether a rise in this metric is a regression. changed_content_bytes
+    /// is informational: more changed content is not itself waste.
+    pub higher_is_worse: bool,
+}
+
+impl MetricDelta {
+    fn new(metric: &str, base: f64, head: f64, higher_is_worse: bool) -> Self {
+        Self {
+            metric: metric.to_string(),
+            base,
+            head,
+            change: (base != 0.0).then(|| (head - base) / base),
+            higher_is_worse,
         }
+    }
 
-        // Budget gate testcase
-        x.push_str("  <testcase name=\"budget_gate\" classname=\"patchwaste.budget\"");
-        match &self.budget {
-            Some(b) if !b.pass => {
-                x.push_str(">\n");
-                x.push_str(&format!(
-                    "    <failure message=\"{}\">{}</failure>\n",
-                    xml_escape(&b.reason),
-                    xml_escape(&format!(
-                        "regression_ratio exceeded threshold {}",
-                        b.threshold_regression_ratio
-                    ))
-                ));
-                x.push_str("  </testcase>\n");
+    /// Rose by more than `threshold`, a fraction of the base value. A rise
+    /// from 0 always counts.
+    pub fn regressed(&self, threshold: f64) -> bool {
+        self.higher_is_worse
+            && match self.change {
+                Some(change) => change > threshold,
+                None => self.head > 0.0,
             }
-            _ => {
-                x.push_str(" />\n");
-            }
-        }
+    }
+}
 
-        x.push_str("</testsuite>\n");
-        x
+/// What moved from one report to another; see [`Report::diff`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportDiff {
+    /// new_bytes, changed_content_bytes and waste_ratio, in that order.
+    pub metrics: Vec<MetricDelta>,
+    /// Finding ids only in the head report, sorted.
+    pub findings_added: Vec<String>,
+    /// Finding ids only in the base report, sorted.
+    pub findings_removed: Vec<String>,
+}
+
+impl ReportDiff {
+    /// Metrics that regressed by more than `threshold`.
+    pub fn regressions(&self, threshold: f64) -> Vec<&MetricDelta> {
+        self.metrics
+            .iter()
+            .filter(|m| m.regressed(threshold))
+            .collect()
     }
 }
 
-fn xml_escape(s: &str) -> String {
-    s.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&apos;")
+/// Classifies each current offender against the baseline's offenders, matching
+/// by normalized path first and falling back to the basename for renames.
+pub fn diff_offenders(baseline: &[FileOffender], current: &[FileOffender]) -> Vec<OffenderChange> {
+    let mut used = vec![false; baseline.len()];
+    let mut matches: Vec<Option<usize>> = current
+        .iter()
+        .map(|c| {
+            let key = normalize_offender_path(&c.path);
+            let idx = baseline
+                .iter()
+                .position(|b| normalize_offender_path(&b.path) == key);
+            if let Some(i) = idx {
+                used[i] = true;
+            }
+            idx
+        })
+        .collect();
+
+    for (c, m) in current.iter().zip(matches.iter_mut()) {
+        if m.is_some() {
+            continue;
+        }
+        let name = offender_basename(&c.path);
+        if let Some(i) =
+            (0..baseline.len()).find(|&i| !used[i] && offender_basename(&baseline[i].path) == name)
+        {
+            used[i] = true;
+            *m = Some(i);
+        }
+    }
+
+    current
+        .iter()
+        .zip(matches)
+        .map(|(c, m)| {
+            let base = m.map(|i| &baseline[i]);
+            let status = match base {
+                None => OffenderStatus::New,
+                Some(b) if c.bytes > b.bytes => OffenderStatus::Grown,
+                Some(b) if c.bytes < b.bytes => OffenderStatus::Shrunk,
+                Some(_) => OffenderStatus::Unchanged,
+            };
+            OffenderChange {
+                path: c.path.clone(),
+                status,
+                bytes: c.bytes,
+                baseline_bytes: base.map(|b| b.bytes),
+                baseline_path: base
+                    .filter(|b| {
+                        normalize_offender_path(&b.path) != normalize_offender_path(&c.path)
+                    })
+                    .map(|b| b.path.clone()),
+            }
+        })
+        .collect()
 }
 
-pub fn compare_to_baseline(b: &Baseline, metrics: &Metrics) -> BaselineComparison {
-    let baseline = b.baseline_new_bytes;
-    let regression_ratio = if baseline == 0 {
-        if metrics.new_bytes == 0 {
+pub fn regression_ratio(baseline: u64, current: u64) -> f64 {
+    if baseline == 0 {
+        if current == 0 {
             1.0
         } else {
             f64::INFINITY
         }
     } else {
-        metrics.new_bytes as f64 / baseline as f64
-    };
-
-    BaselineComparison {
-        baseline_new_bytes: baseline,
-        regression_ratio,
-        delta_new_bytes: metrics.new_bytes as i64 - baseline as i64,
+        current as f64 / baseline as f64
     }
 }
 
@@ -310,6 +1758,19 @@ mod tests {
     use super::*;
     use crate::types::{ConfidenceLevel, Finding, Metrics, Severity};
 
+    #[test]
+    fn write_json_round_trips() {
+        let report = sample_report();
+        let mut buf = Vec::new();
+        report.write_json(&mut buf).unwrap();
+
+        let parsed: Report = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&report).unwrap()
+        );
+    }
+
     #[test]
     fn markdown_includes_sections_and_findings() {
         let report = Report {
@@ -318,6 +1779,7 @@ mod tests {
                 input_path: "x".to_string(),
                 parse_mode: "STRICT".to_string(),
                 sources: vec!["a.log".to_string()],
+                files_without_counters: 0,
             },
             metrics: Metrics {
                 new_bytes: 10,
@@ -338,19 +1800,39 @@ mod tests {
                 evidence: vec!["e".to_string()],
                 likely_cause: "c".to_string(),
                 suggested_actions: vec!["a".to_string()],
+                source_report: None,
+                accepted: false,
             }],
             baseline_comparison: Some(BaselineComparison {
+                metric: BaselineMetric::NewBytes,
                 baseline_new_bytes: 1,
                 regression_ratio: 10.0,
                 delta_new_bytes: 9,
+                baseline_changed_content_bytes: None,
+                delta_changed_content_bytes: None,
+                regression_percent: Some(900.0),
+                regression_bps: Some(90_000),
             }),
             budget: Some(BudgetResult {
-                threshold_regression_ratio: 1.0,
+                regression_ratio: Some(10.0),
+                threshold_regression_ratio: Some(1.0),
                 pass: false,
                 reason: "nope".to_string(),
+                tolerance_bytes: None,
+                policy: None,
+                clauses: Vec::new(),
             }),
             build_metadata: None,
             per_depot: Vec::new(),
+            offenders: Vec::new(),
+            offender_diff: Vec::new(),
+            metrics_extra: MetricsExtra::default(),
+            compare_range: None,
+            raw_counters: None,
+            efficiency_floor: None,
+            offender_stats: None,
+            depots_omitted: None,
+            strict_preview: None,
         };
 
         let md = report.to_markdown();
@@ -439,6 +1921,7 @@ mod tests {
                 input_path: "x".to_string(),
                 parse_mode: "BEST_EFFORT".to_string(),
                 sources: vec![],
+                files_without_counters: 0,
             },
             metrics: Metrics {
                 new_bytes: 10,
@@ -460,6 +1943,8 @@ mod tests {
                     evidence: vec!["waste_ratio=0.500".to_string()],
                     likely_cause: "churn".to_string(),
                     suggested_actions: vec![],
+                    source_report: None,
+                    accepted: false,
                 },
                 Finding {
                     id: "LOW_SEV".to_string(),
@@ -467,16 +1952,31 @@ mod tests {
                     evidence: vec![],
                     likely_cause: "minor".to_string(),
                     suggested_actions: vec![],
+                    source_report: None,
+                    accepted: false,
                 },
             ],
             baseline_comparison: None,
             budget: Some(BudgetResult {
-                threshold_regression_ratio: 1.0,
+                regression_ratio: Some(10.0),
+                threshold_regression_ratio: Some(1.0),
                 pass: false,
                 reason: "exceeded".to_string(),
+                tolerance_bytes: None,
+                policy: None,
+                clauses: Vec::new(),
             }),
             build_metadata: None,
             per_depot: Vec::new(),
+            offenders: Vec::new(),
+            offender_diff: Vec::new(),
+            metrics_extra: MetricsExtra::default(),
+            compare_range: None,
+            raw_counters: None,
+            efficiency_floor: None,
+            offender_stats: None,
+            depots_omitted: None,
+            strict_preview: None,
         };
 
         let xml = report.to_junit_xml();
@@ -489,6 +1989,501 @@ mod tests {
         assert!(xml.contains("budget_gate"));
     }
 
+    #[test]
+    fn pr_comment_folds_findings_and_shows_verdict() {
+        let mut report = Report::new(
+            Path::new("x"),
+            ParseMode::BestEffort,
+            Metrics {
+                new_bytes: 10,
+                changed_content_bytes: 5,
+                delta_efficiency: 0.5,
+                waste_ratio: 0.5,
+            },
+            ConfidenceSummary {
+                new_bytes: ConfidenceLevel::High,
+                changed_content_bytes: ConfidenceLevel::High,
+                delta_efficiency: ConfidenceLevel::Medium,
+                waste_ratio: ConfidenceLevel::Medium,
+                overall: ConfidenceLevel::High,
+            },
+            vec![Finding {
+                id: "HIGH_WASTE_RATIO".to_string(),
+                severity: Severity::High,
+                evidence: vec!["waste_ratio=0.500".to_string()],
+                likely_cause: "churn".to_string(),
+                suggested_actions: vec![],
+                source_report: None,
+                accepted: false,
+            }],
+            None,
+            Some(BudgetResult {
+                regression_ratio: Some(2.0),
+                threshold_regression_ratio: Some(1.25),
+                pass: false,
+                reason: "exceeded".to_string(),
+                tolerance_bytes: None,
+                policy: None,
+                clauses: Vec::new(),
+            }),
+            Some(BuildMetadata {
+                sha: Some("abc123".to_string()),
+                branch: None,
+                build_id: None,
+            }),
+        );
+
+        let comment = report.to_pr_comment();
+        assert!(comment.contains("<details>"));
+        assert!(comment.contains("HIGH_WASTE_RATIO"));
+        assert!(comment.contains("patchwaste-FAIL"));
+        assert!(comment.contains("abc123"));
+
+        report.budget = None;
+        assert!(report.to_pr_comment().contains("patchwaste-PASS"));
+    }
+
+    #[test]
+    fn depot_budget_and_floor_failures_fail_the_gate_outputs() {
+        let mut report = sample_report();
+        let mut d = depot("12345");
+        d.budget = Some(evaluate_budget(100, 300, 1.25, None));
+        report.per_depot = vec![d];
+
+        assert!(report.to_pr_comment().contains("patchwaste-FAIL"));
+        let xml = report.to_junit_xml();
+        assert!(xml.contains("failures=\"1\""));
+        assert!(xml.contains("depot 12345: regression_ratio exceeded threshold 1.25"));
+
+        report.per_depot.clear();
+        report.efficiency_floor = Some(evaluate_efficiency_floor(0.5, 0.8));
+        assert!(report.to_pr_comment().contains("patchwaste-FAIL"));
+        assert!(report
+            .to_junit_xml()
+            .contains("<failure message=\"delta_efficiency 0.500 &lt; floor 0.800\">"));
+
+        report.efficiency_floor = None;
+        assert!(report.to_pr_comment().contains("patchwaste-PASS"));
+        assert!(report.to_junit_xml().contains("failures=\"0\""));
+    }
+
+    fn sample_report() -> Report {
+        Report::new(
+            Path::new("x"),
+            ParseMode::BestEffort,
+            Metrics {
+                new_bytes: 10,
+                changed_content_bytes: 5,
+                delta_efficiency: 0.5,
+                waste_ratio: 0.5,
+            },
+            ConfidenceSummary {
+                new_bytes: ConfidenceLevel::High,
+                changed_content_bytes: ConfidenceLevel::High,
+                delta_efficiency: ConfidenceLevel::Medium,
+                waste_ratio: ConfidenceLevel::Medium,
+                overall: ConfidenceLevel::High,
+            },
+            vec![],
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn depot(id: &str) -> DepotReport {
+        DepotReport {
+            depot_id: id.to_string(),
+            metrics: Metrics {
+                new_bytes: 10,
+                changed_content_bytes: 5,
+                delta_efficiency: 0.5,
+                waste_ratio: 0.5,
+            },
+            confidence: ConfidenceLevel::High,
+            class: None,
+            findings: vec![],
+            budget: None,
+            offenders: vec![],
+        }
+    }
+
+    #[test]
+    fn canonical_json_sorts_depots_and_keys() {
+        let mut report = sample_report();
+        report.per_depot = vec![
+            depot("67890"),
+            depot("12345"),
+            depot("10"),
+            depot("23456"),
+            depot("9"),
+        ];
+
+        let json = String::from_utf8(report.to_canonical_json().unwrap()).unwrap();
+        let pos = |id: &str| json.find(&format!("\"depot_id\": \"{id}\"")).unwrap();
+        let order: Vec<usize> = ["9", "10", "12345", "23456", "67890"]
+            .iter()
+            .map(|id| pos(id))
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{order:?}");
+        assert!(
+            json.find("\"baseline_comparison\"").unwrap()
+                < json.find("\"report_version\"").unwrap()
+        );
+    }
+
+    #[test]
+    fn same_as_json_ignores_order_and_volatile_fields() {
+        let mut report = sample_report();
+        report.per_depot = vec![depot("12345"), depot("23456")];
+        report.build_metadata = Some(BuildMetadata {
+            sha: Some("abc".to_string()),
+            branch: None,
+            build_id: Some("1".to_string()),
+        });
+        let mut previous = report.clone();
+        previous.per_depot.reverse();
+        previous.build_metadata.as_mut().unwrap().build_id = Some("2".to_string());
+        let json = serde_json::to_vec_pretty(&previous).unwrap();
+        assert!(report.same_as_json(&json));
+        let mut adopted = report.clone();
+        adopted.adopt_volatile_fields(&previous);
+        assert_eq!(
+            adopted.build_metadata.as_ref().unwrap().build_id.as_deref(),
+            Some("2")
+        );
+
+        previous.build_metadata.as_mut().unwrap().sha = Some("def".to_string());
+        let json = serde_json::to_vec_pretty(&previous).unwrap();
+        assert!(!report.same_as_json(&json));
+        assert!(!report.same_as_json(b"not json"));
+    }
+
+    #[test]
+    fn diff_reports_metric_deltas_and_finding_changes() {
+        let finding = |id: &str| Finding {
+            id: id.to_string(),
+            severity: Severity::Medium,
+            evidence: vec![],
+            likely_cause: String::new(),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        };
+        let mut base = sample_report();
+        base.findings = vec![finding("LARGE_TOP_OFFENDER"), finding("STALE_BASELINE")];
+        let mut head = sample_report();
+        head.metrics.new_bytes = 15;
+        head.metrics.changed_content_bytes = 3;
+        head.metrics.waste_ratio = 0.8;
+        head.findings = vec![finding("HIGH_WASTE_RATIO"), finding("LARGE_TOP_OFFENDER")];
+
+        let diff = base.diff(&head);
+        let names: Vec<_> = diff.metrics.iter().map(|m| m.metric.as_str()).collect();
+        assert_eq!(names, ["new_bytes", "changed_content_bytes", "waste_ratio"]);
+        assert_eq!(diff.metrics[0].change, Some(0.5));
+        assert_eq!(diff.findings_added, ["HIGH_WASTE_RATIO"]);
+        assert_eq!(diff.findings_removed, ["STALE_BASELINE"]);
+
+        // new_bytes +50% and waste_ratio +60%; the drop in changed content
+        // never counts.
+        let regressed = |t: f64| -> Vec<String> {
+            diff.regressions(t)
+                .iter()
+                .map(|m| m.metric.clone())
+                .collect()
+        };
+        assert_eq!(regressed(0.25), ["new_bytes", "waste_ratio"]);
+        assert_eq!(regressed(0.55), ["waste_ratio"]);
+        assert!(regressed(1.0).is_empty());
+        assert!(head.diff(&head).regressions(0.0).is_empty());
+    }
+
+    #[test]
+    fn baseline_json_round_trips_through_from_report_json() {
+        let mut report = sample_report();
+        report.offenders = vec![FileOffender {
+            path: "Game.pak".to_string(),
+            bytes: 7,
+            percent: None,
+            sources: vec![],
+        }];
+
+        let json = serde_json::to_vec(&report.to_baseline_json(Some(1_700_000_000))).unwrap();
+        let baseline = Baseline::from_report_json(&json).unwrap();
+        assert_eq!(baseline.baseline_new_bytes, 10);
+        assert_eq!(baseline.baseline_changed_content_bytes, 5);
+        assert_eq!(baseline.offenders[0].path, "Game.pak");
+        assert_eq!(baseline.created_at, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn sarif_maps_severity_to_level_and_offenders_to_locations() {
+        let mut report = sample_report();
+        report.offenders = vec![FileOffender {
+            path: "Content\\Paks\\Game.pak".to_string(),
+            bytes: 900,
+            percent: None,
+            sources: vec![],
+        }];
+        let finding = |id: &str, severity, evidence: &str| Finding {
+            id: id.to_string(),
+            severity,
+            evidence: vec![evidence.to_string()],
+            likely_cause: format!("{id} cause"),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        };
+        report.findings = vec![
+            finding("HIGH_WASTE_RATIO", Severity::High, "waste_ratio=0.900"),
+            finding(
+                "LARGE_TOP_OFFENDER",
+                Severity::Medium,
+                "Content\\Paks\\Game.pak (900 bytes)",
+            ),
+        ];
+
+        let sarif = report.to_sarif();
+        assert_eq!(sarif["$schema"], SARIF_SCHEMA);
+        assert_eq!(sarif["version"], "2.1.0");
+        let results = &sarif["runs"][0]["results"];
+        assert_eq!(results[0]["ruleId"], "HIGH_WASTE_RATIO");
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["message"]["text"], "HIGH_WASTE_RATIO cause");
+        assert!(results[0].get("locations").is_none());
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "Content/Paks/Game.pak"
+        );
+    }
+
+    #[test]
+    fn offender_diff_classifies_grown_new_and_renamed() {
+        let off = |path: &str, bytes: u64| FileOffender {
+            path: path.to_string(),
+            bytes,
+            percent: None,
+            sources: vec![],
+        };
+        let baseline = vec![off("Content/A.pak", 100), off("old/Level.pak", 50)];
+        let current = vec![
+            off("content\\a.pak", 200),
+            off("new/Level.pak", 50),
+            off("Content/B.pak", 10),
+        ];
+
+        let diff = diff_offenders(&baseline, &current);
+        assert_eq!(diff[0].status, OffenderStatus::Grown);
+        assert_eq!(diff[0].baseline_bytes, Some(100));
+        assert_eq!(diff[1].status, OffenderStatus::Unchanged);
+        assert_eq!(diff[1].baseline_path.as_deref(), Some("old/Level.pak"));
+        assert_eq!(diff[2].status, OffenderStatus::New);
+        assert_eq!(diff[2].baseline_bytes, None);
+    }
+
+    #[test]
+    fn worst_severity_picks_highest_finding() {
+        let finding = |severity| Finding {
+            id: "X".to_string(),
+            severity,
+            evidence: vec![],
+            likely_cause: "c".to_string(),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        };
+        let mut report = sample_report();
+        assert_eq!(report.worst_severity(), None);
+
+        report.findings = vec![
+            finding(Severity::Low),
+            finding(Severity::High),
+            finding(Severity::Medium),
+        ];
+        assert_eq!(report.worst_severity(), Some(Severity::High));
+        assert!(Severity::Low < Severity::Medium && Severity::Medium < Severity::High);
+        assert!(Severity::Medium.order() > Severity::Low.order());
+    }
+
+    #[test]
+    fn collapsible_markdown_wraps_per_depot_section() {
+        let mut report = sample_report();
+        report.per_depot = vec![depot("12345"), depot("67890")];
+
+        let flat = report.to_markdown();
+        assert!(!flat.contains("<details>"));
+
+        let md = report.to_markdown_with(&MarkdownOptions {
+            collapsible: true,
+            ..MarkdownOptions::default()
+        });
+        let section = md.find("## Per-depot metrics").unwrap();
+        let details = md[section..].find("<details>").unwrap() + section;
+        let depot = md.find("### Depot 12345").unwrap();
+        let close = md[depot..].find("</details>").unwrap() + depot;
+        assert!(section < details && details < depot && depot < close);
+        assert!(md.contains("<summary>2 depots</summary>"));
+    }
+
+    #[test]
+    fn per_depot_markdown_rolls_up_high_confidence_depots() {
+        let mut report = sample_report();
+        let mut weak = depot("23456");
+        weak.confidence = ConfidenceLevel::Low;
+        let mut medium = depot("34567");
+        medium.confidence = ConfidenceLevel::Medium;
+        report.per_depot = vec![depot("12345"), weak, medium, depot("45678"), depot("56789")];
+
+        let md = report.to_markdown();
+        let section = md.find("## Per-depot metrics").unwrap();
+        let rollup = md.find("3/5 depots High confidence").unwrap();
+        assert!(section < rollup && rollup < md.find("### Depot 12345").unwrap());
+    }
+
+    #[test]
+    fn severity_colour_wraps_high_finding_in_red_span() {
+        let mut report = sample_report();
+        report.findings = vec![Finding {
+            id: "X".to_string(),
+            severity: Severity::High,
+            evidence: vec![],
+            likely_cause: "c".to_string(),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        }];
+
+        assert!(report.to_markdown().contains("- severity: `High`\n"));
+
+        let md = report.to_markdown_with(&MarkdownOptions {
+            severity_colour: true,
+            ..MarkdownOptions::default()
+        });
+        assert!(md.contains("- severity: <span style=\"color:red\">High</span>\n"));
+    }
+
+    #[test]
+    fn junit_options_set_suite_name_and_classname_prefix() {
+        let mut report = sample_report();
+        report.findings = vec![Finding {
+            id: "X".to_string(),
+            severity: Severity::Low,
+            evidence: vec![],
+            likely_cause: "c".to_string(),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        }];
+
+        let default = report.to_junit_xml();
+        assert!(default.contains("<testsuite name=\"patchwaste\""));
+        assert!(default.contains("classname=\"patchwaste.findings\""));
+
+        let xml = report.to_junit_xml_with(&JunitOptions {
+            suite_name: "patch size".to_string(),
+            classname_prefix: "ci.win64".to_string(),
+        });
+        assert!(xml.contains("<testsuite name=\"patch size\""));
+        assert!(xml.contains("<testcase name=\"X\" classname=\"ci.win64.findings\""));
+        assert!(xml.contains("<testcase name=\"budget_gate\" classname=\"ci.win64.budget\""));
+    }
+
+    #[test]
+    fn findings_csv_has_a_row_per_finding() {
+        let finding = |id: &str, severity| Finding {
+            id: id.to_string(),
+            severity,
+            evidence: vec!["waste_ratio=0.9".to_string(), "a, b".to_string()],
+            likely_cause: "c".to_string(),
+            suggested_actions: vec![],
+            source_report: None,
+            accepted: false,
+        };
+        let mut report = sample_report();
+        report.findings = vec![
+            finding("HIGH_WASTE_RATIO", Severity::High),
+            finding("SUSTAINED_GROWTH", Severity::Low),
+        ];
+
+        let csv = report.findings_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "id,severity,cause_category,likely_cause,evidence");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "HIGH_WASTE_RATIO,High,churn,c,\"waste_ratio=0.9; a, b\""
+        );
+        assert!(lines[2].starts_with("SUSTAINED_GROWTH,Low,trend,"));
+    }
+
+    #[test]
+    fn worst_depot_waste_tie_goes_to_lowest_depot_id() {
+        let (a, b) = (depot("67890"), depot("123456"));
+        assert_eq!(a.metrics.waste_ratio, b.metrics.waste_ratio);
+
+        for depots in [vec![a.clone(), b.clone()], vec![b, a]] {
+            let extra = MetricsExtra::from_depots(&depots);
+            assert_eq!(extra.worst_depot_waste, Some(("67890".to_string(), 0.5)));
+        }
+    }
+
+    #[test]
+    fn format_version_1_0_0_drops_newer_fields() {
+        let mut report = sample_report();
+        report.offenders = vec![FileOffender {
+            path: "a.pak".to_string(),
+            bytes: 1,
+            percent: None,
+            sources: vec![],
+        }];
+        report.per_depot = vec![depot("12345")];
+
+        let latest = report.to_json_value(FormatVersion::V1_1_0).unwrap();
+        assert_eq!(latest["report_version"], "1.1.0");
+        assert!(latest.get("offenders").is_some());
+
+        let v1 = report.to_json_value(FormatVersion::V1_0_0).unwrap();
+        assert_eq!(v1["report_version"], "1.0.0");
+        assert!(v1.get("offenders").is_none());
+        assert!(v1["per_depot"][0].get("depot_id").is_some());
+        assert!(v1["per_depot"][0].get("budget").is_none());
+    }
+
+    #[test]
+    fn format_version_1_0_0_drops_budget_without_ratio_threshold() {
+        let mut report = sample_report();
+        let expr = BudgetExpression {
+            policy: BudgetPolicy::Or,
+            clauses: vec![BudgetClause {
+                metric: BudgetMetric::WasteRatio,
+                min: None,
+                max: Some(0.1),
+            }],
+        };
+        let budget = evaluate_budget_expression(&expr, &report.metrics, None);
+        assert_eq!(budget.regression_ratio, None, "no baseline, no ratio");
+        report.budget = Some(budget);
+
+        let latest = report.to_json_value(FormatVersion::V1_1_0).unwrap();
+        assert_eq!(latest["budget"]["pass"], false);
+        let v1 = report.to_json_value(FormatVersion::V1_0_0).unwrap();
+        assert!(v1["budget"].is_null());
+
+        let expr = BudgetExpression {
+            policy: BudgetPolicy::And,
+            clauses: vec![BudgetClause {
+                metric: BudgetMetric::RegressionRatio,
+                min: None,
+                max: Some(1.25),
+            }],
+        };
+        report.budget = Some(evaluate_budget_expression(&expr, &report.metrics, None));
+        let v1 = report.to_json_value(FormatVersion::V1_0_0).unwrap();
+        assert_eq!(v1["budget"]["threshold_regression_ratio"], 1.25);
+    }
+
     #[test]
     fn xml_escape_handles_special_chars() {
         assert_eq!(xml_escape("<test>&\"'"), "&lt;test&gt;&amp;&quot;&apos;");
@@ -498,6 +2493,11 @@ mod tests {
     fn baseline_comparison_infinite_when_baseline_zero() {
         let b = Baseline {
             baseline_new_bytes: 0,
+            baseline_changed_content_bytes: 0,
+            offenders: vec![],
+            depot_new_bytes: Default::default(),
+            budget_ratio: None,
+            created_at: None,
         };
         let m = Metrics {
             new_bytes: 10,
@@ -505,7 +2505,70 @@ mod tests {
             delta_efficiency: 1.0,
             waste_ratio: 0.0,
         };
-        let cmp = compare_to_baseline(&b, &m);
+        let cmp = compare_to_baseline(&b, &m, BaselineMetric::NewBytes);
         assert!(cmp.regression_ratio.is_infinite());
+        assert_eq!(cmp.regression_percent, None);
+    }
+
+    #[test]
+    fn regression_percent_is_signed_percentage() {
+        assert_eq!(regression_percent(1000, 10_000), Some(900.0));
+        assert_eq!(
+            format!("{:+.1}", regression_percent(1000, 10_000).unwrap()),
+            "+900.0"
+        );
+        assert_eq!(regression_percent(1000, 500), Some(-50.0));
+        assert_eq!(regression_percent(0, 10), None);
+    }
+
+    #[test]
+    fn round_floats_limits_decimals_but_keeps_integers() {
+        let mut report = sample_report();
+        report.metrics.waste_ratio = 0.16200000000001;
+        report.metrics.delta_efficiency = 2.0 / 3.0;
+        report.metrics.new_bytes = 123_456_789;
+
+        let mut value = serde_json::to_value(&report).unwrap();
+        round_floats(&mut value, 3);
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(json.contains("\"waste_ratio\":0.162"), "{json}");
+        assert!(json.contains("\"delta_efficiency\":0.667"), "{json}");
+        assert!(json.contains("\"new_bytes\":123456789"), "{json}");
+
+        let waste = value["metrics"]["waste_ratio"].to_string();
+        let decimals = waste.split_once('.').map_or(0, |(_, frac)| frac.len());
+        assert!(decimals <= 3, "{waste}");
+        assert_eq!(report.metrics.waste_ratio, 0.16200000000001);
+    }
+
+    #[test]
+    fn regression_bps_renders_in_markdown_when_enabled() {
+        assert_eq!(regression_bps(1000, 1250), Some(2500));
+        assert_eq!(regression_bps(1000, 750), Some(-2500));
+        assert_eq!(regression_bps(0, 10), None);
+
+        let mut report = sample_report();
+        report.baseline_comparison = Some(compare_to_baseline(
+            &Baseline {
+                baseline_new_bytes: 8,
+                baseline_changed_content_bytes: 0,
+                offenders: vec![],
+                depot_new_bytes: Default::default(),
+                budget_ratio: None,
+                created_at: None,
+            },
+            &report.metrics,
+            BaselineMetric::NewBytes,
+        ));
+        assert_eq!(
+            report.baseline_comparison.as_ref().unwrap().regression_bps,
+            Some(2500)
+        );
+        assert!(!report.to_markdown().contains("regression_bps"));
+        let md = report.to_markdown_with(&MarkdownOptions {
+            regression_bps: true,
+            ..MarkdownOptions::default()
+        });
+        assert!(md.contains("- regression_bps: `+2500`"));
     }
 }
@@ -1,16 +1,165 @@
+use serde::Deserialize;
+
 use crate::{
-    parser::ParsedBuildOutput,
+    baseline::Baseline,
+    parser::{ParseMode, ParsedBuildOutput, SteamPipeCounters},
+    report::ConfidenceSummary,
+    types::ConfidenceLevel,
     types::Metrics,
-    types::{Finding, Severity},
+    types::{DepotClass, Finding, Severity, WasteBand, WasteBands},
 };
 
-pub fn run_rules(parsed: &ParsedBuildOutput, metrics: &Metrics) -> Vec<Finding> {
+#[derive(Debug, Clone)]
+pub struct RuleThresholds {
+    pub waste_ratio: f64,
+    pub code_waste_ratio: Option<f64>,
+    pub content_waste_ratio: Option<f64>,
+    /// Consecutive increasing samples (history plus current run) for `SUSTAINED_GROWTH`.
+    pub sustained_growth_samples: usize,
+    /// When set, `HIGH_WASTE_RATIO` is Low in the amber band and High in the red
+    /// band. Depots with a class-specific threshold keep the single cut-off.
+    pub waste_bands: Option<WasteBands>,
+    /// Share of logs without counters at which `UNRECOGNIZED_LOG_FORMAT` fires.
+    pub unrecognized_log_ratio: f64,
+    /// Multiple of `new_bytes` the offender byte sum may reach before
+    /// `OFFENDER_SUM_ANOMALY` fires. Offender sizes are whole-file sizes, so
+    /// the default only trips on orders-of-magnitude (unit) mismatches.
+    pub offender_sum_factor: f64,
+    /// Plausible `changed_content_bytes / new_bytes` range; outside it
+    /// `COUNTER_DISPROPORTION` fires.
+    pub counter_ratio_bounds: (f64, f64),
+    /// When set, `LARGE_TOP_OFFENDER` fires once the top offender reaches this
+    /// share of `changed_content_bytes`, instead of the absolute 100 MiB cut-off.
+    pub top_offender_changed_share: Option<f64>,
+    /// Multiple of the second largest offender past which `LARGE_TOP_OFFENDER`
+    /// escalates to High: one outsized file, rather than one of many similar.
+    pub top_offender_outlier_factor: f64,
+    /// Age in days past which a baseline with `created_at` is `STALE_BASELINE`.
+    pub max_baseline_age_days: u64,
+    /// Growth in changed_content_bytes over the baseline, as a fraction, at
+    /// which `EFFICIENT_LARGE_CHANGE` fires while new_bytes stays flat.
+    pub efficient_change_growth: f64,
+}
+
+/// new_bytes within this fraction of the baseline counts as flat.
+const FLAT_NEW_BYTES_TOLERANCE: f64 = 0.01;
+
+impl Default for RuleThresholds {
+    fn default() -> Self {
+        Self {
+            waste_ratio: 0.50,
+            code_waste_ratio: None,
+            content_waste_ratio: None,
+            sustained_growth_samples: 3,
+            unrecognized_log_ratio: 0.5,
+            offender_sum_factor: 100.0,
+            counter_ratio_bounds: (0.01, 100.0),
+            top_offender_changed_share: None,
+            top_offender_outlier_factor: 5.0,
+            max_baseline_age_days: 90,
+            efficient_change_growth: 0.5,
+            waste_bands: None,
+        }
+    }
+}
+
+impl RuleThresholds {
+    /// Waste ratio at which `HIGH_WASTE_RATIO` fires, falling back to the
+    /// general threshold when the class has no override.
+    pub fn waste_ratio_for(&self, class: Option<DepotClass>) -> f64 {
+        match class {
+            Some(DepotClass::Code) => self.code_waste_ratio.unwrap_or(self.waste_ratio),
+            Some(DepotClass::Content) => self.content_waste_ratio.unwrap_or(self.waste_ratio),
+            None => self.waste_ratio,
+        }
+    }
+
+    /// Severity of `HIGH_WASTE_RATIO` for `ratio`, or `None` when it should not fire.
+    pub fn waste_severity(&self, ratio: f64, class: Option<DepotClass>) -> Option<Severity> {
+        let class_override = match class {
+            Some(DepotClass::Code) => self.code_waste_ratio,
+            Some(DepotClass::Content) => self.content_waste_ratio,
+            None => None,
+        };
+        match (self.waste_bands, class_override) {
+            (Some(bands), None) => match bands.band(ratio) {
+                WasteBand::Green => None,
+                WasteBand::Amber => Some(Severity::Low),
+                WasteBand::Red => Some(Severity::High),
+            },
+            _ => (ratio >= self.waste_ratio_for(class)).then_some(Severity::High),
+        }
+    }
+}
+
+/// Minimum confidence a metric needs before rules depending on it are trusted.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MinConfidence {
+    pub new_bytes: Option<ConfidenceLevel>,
+    pub changed_content_bytes: Option<ConfidenceLevel>,
+    pub action: BelowConfidence,
+}
+
+/// What happens to a finding whose input metrics are below the minimum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BelowConfidence {
+    #[default]
+    Suppress,
+    /// Keep the finding at Low severity.
+    Downgrade,
+}
+
+/// Metrics each rule's verdict depends on, as `(new_bytes, changed_content_bytes)`.
+fn rule_inputs(id: &str, thresholds: &RuleThresholds) -> (bool, bool) {
+    match id {
+        "HIGH_WASTE_RATIO" | "COUNTER_DISPROPORTION" | "EFFICIENT_LARGE_CHANGE" => (true, true),
+        "OFFENDER_SUM_ANOMALY" | "SUSTAINED_GROWTH" => (true, false),
+        "LARGE_TOP_OFFENDER" => (false, thresholds.top_offender_changed_share.is_some()),
+        _ => (false, false),
+    }
+}
+
+/// Suppresses or downgrades findings whose input metrics are less confident
+/// than `min` allows.
+pub fn apply_min_confidence(
+    findings: &mut Vec<Finding>,
+    confidence: &ConfidenceSummary,
+    min: &MinConfidence,
+    thresholds: &RuleThresholds,
+) {
+    // ConfidenceLevel orders High < Medium < Low, so "below" is `>`.
+    let below =
+        |level: ConfidenceLevel, min: Option<ConfidenceLevel>| min.is_some_and(|m| level > m);
+    let untrusted = |f: &Finding| {
+        let (uses_new, uses_changed) = rule_inputs(&f.id, thresholds);
+        (uses_new && below(confidence.new_bytes, min.new_bytes))
+            || (uses_changed && below(confidence.changed_content_bytes, min.changed_content_bytes))
+    };
+    match min.action {
+        BelowConfidence::Suppress => findings.retain(|f| !untrusted(f)),
+        BelowConfidence::Downgrade => {
+            for f in findings.iter_mut().filter(|f| untrusted(f)) {
+                f.severity = Severity::Low;
+            }
+        }
+    }
+}
+
+pub fn run_rules(
+    parsed: &ParsedBuildOutput,
+    metrics: &Metrics,
+    thresholds: &RuleThresholds,
+    class: Option<DepotClass>,
+) -> Vec<Finding> {
     let mut findings: Vec<Finding> = Vec::new();
 
-    if metrics.waste_ratio >= 0.50 && metrics.new_bytes > 0 {
+    let waste_severity = thresholds.waste_severity(metrics.waste_ratio, class);
+    if let (Some(severity), true) = (waste_severity, metrics.new_bytes > 0) {
         findings.push(Finding {
             id: "HIGH_WASTE_RATIO".to_string(),
-            severity: Severity::High,
+            severity,
             evidence: vec![format!("waste_ratio={:.3}", metrics.waste_ratio)],
             likely_cause: "Large packed file churn or content reorder causing many new chunks"
                 .to_string(),
@@ -19,25 +168,309 @@ pub fn run_rules(parsed: &ParsedBuildOutput, metrics: &Metrics) -> Vec<Finding>
                 "Split packs by level/realm to localize churn".to_string(),
                 "Align pack layout to stable boundaries (e.g., 1MB) where applicable".to_string(),
             ],
+            source_report: None,
+            accepted: false,
         });
     }
 
     if let Some(off) = parsed.offenders.first() {
-        if off.bytes >= 100 * 1024 * 1024 {
+        let (large, mut evidence) = match thresholds.top_offender_changed_share {
+            Some(share) => {
+                let changed = metrics.changed_content_bytes;
+                let actual = off.bytes as f64 / changed.max(1) as f64;
+                (
+                    changed > 0 && actual >= share,
+                    vec![format!(
+                        "{:.1}% of changed_content_bytes (limit {:.1}%)",
+                        actual * 100.0,
+                        share * 100.0
+                    )],
+                )
+            }
+            None => (off.bytes >= 100 * 1024 * 1024, vec![]),
+        };
+        if large {
+            evidence.insert(0, format!("{} ({} bytes)", off.path, off.bytes));
+            let mut severity = Severity::Medium;
+            if let Some(next) = parsed.offenders.get(1).filter(|n| n.bytes > 0) {
+                let factor = off.bytes as f64 / next.bytes as f64;
+                if factor > thresholds.top_offender_outlier_factor {
+                    severity = Severity::High;
+                    evidence.push(format!(
+                        "{:.1}x the next largest offender {} (limit {:.1}x)",
+                        factor, next.path, thresholds.top_offender_outlier_factor
+                    ));
+                }
+            }
             findings.push(Finding {
                 id: "LARGE_TOP_OFFENDER".to_string(),
-                severity: Severity::Medium,
-                evidence: vec![format!("{} ({} bytes)", off.path, off.bytes)],
+                severity,
+                evidence,
                 likely_cause: "A large file dominates predicted update size".to_string(),
                 suggested_actions: vec![
                     "If this is a pack file, consider splitting into multiple packs".to_string(),
                     "Ensure build process does not rewrite the whole file for small changes"
                         .to_string(),
                 ],
+                source_report: None,
+                accepted: false,
             });
         }
     }
 
+    let offender_sum = parsed.offender_bytes;
+    if metrics.new_bytes > 0
+        && offender_sum as f64 > metrics.new_bytes as f64 * thresholds.offender_sum_factor
+    {
+        findings.push(Finding {
+            id: "OFFENDER_SUM_ANOMALY".to_string(),
+            severity: Severity::Low,
+            evidence: vec![format!(
+                "offender bytes sum to {} vs new_bytes={} (limit {:.1}x)",
+                offender_sum, metrics.new_bytes, thresholds.offender_sum_factor
+            )],
+            likely_cause: "Offender sizes and counters disagree, likely a parsing or unit mismatch"
+                .to_string(),
+            suggested_actions: vec![
+                "Check that offender sizes and counters in the logs use the same units".to_string(),
+                "Raise offender_sum_factor in patchwaste.toml if offenders are reported as full file sizes".to_string(),
+            ],
+            source_report: None,
+            accepted: false,
+        });
+    }
+
+    let (min_ratio, max_ratio) = thresholds.counter_ratio_bounds;
+    if metrics.new_bytes > 0 && metrics.changed_content_bytes > 0 {
+        let ratio = metrics.changed_content_bytes as f64 / metrics.new_bytes as f64;
+        if ratio < min_ratio || ratio > max_ratio {
+            findings.push(Finding {
+                id: "COUNTER_DISPROPORTION".to_string(),
+                severity: Severity::Low,
+                evidence: vec![format!(
+                    "changed_content_bytes/new_bytes={:.4} outside [{}, {}]",
+                    ratio, min_ratio, max_ratio
+                )],
+                likely_cause: "Counters differ by orders of magnitude, suggesting a parsing mismatch or an unusual build".to_string(),
+                suggested_actions: vec![
+                    "Verify both counters against the raw SteamPipe output".to_string(),
+                    "Check custom [patterns] capture the intended values".to_string(),
+                ],
+                source_report: None,
+                accepted: false,
+            });
+        }
+    }
+
+    let files = parsed.sources.len();
+    let unrecognized = parsed.files_without_counters;
+    if unrecognized > 0
+        && files > 0
+        && unrecognized as f64 / files as f64 >= thresholds.unrecognized_log_ratio
+    {
+        findings.push(Finding {
+            id: "UNRECOGNIZED_LOG_FORMAT".to_string(),
+            severity: Severity::Medium,
+            evidence: vec![format!(
+                "{unrecognized} of {files} logs contained no counters"
+            )],
+            likely_cause: "Logs do not match the expected SteamPipe counter format".to_string(),
+            suggested_actions: vec![
+                "Check that BuildOutput contains SteamPipe preview logs".to_string(),
+                "Add a [patterns] entry in patchwaste.toml for custom log formats".to_string(),
+            ],
+            source_report: None,
+            accepted: false,
+        });
+    }
+
+    let counters = &parsed.counters;
+    if !parsed.offenders.is_empty()
+        && counters.predicted_update_bytes.is_none()
+        && counters.changed_content_bytes.is_none()
+    {
+        findings.push(Finding {
+            id: "OFFENDERS_WITHOUT_COUNTERS".to_string(),
+            severity: Severity::Medium,
+            evidence: vec![format!(
+                "{} offenders parsed but no predicted_update_bytes or changed_content_bytes",
+                parsed.offender_tally.count()
+            )],
+            likely_cause: "Something shipped but the counter lines were not recognised"
+                .to_string(),
+            suggested_actions: vec![
+                "Compare the counter lines in the logs with PREDICTED_UPDATE_BYTES=... / CHANGED_CONTENT_BYTES=...".to_string(),
+                "Add a [patterns] entry in patchwaste.toml for custom counter formats".to_string(),
+            ],
+            source_report: None,
+            accepted: false,
+        });
+    }
+
+    if let (ParseMode::BestEffort, Some(evidence)) = (parsed.mode, counter_fallback(counters)) {
+        findings.push(Finding {
+            id: "FALLBACK_CONFIDENCE".to_string(),
+            severity: Severity::Low,
+            evidence: vec![evidence],
+            likely_cause: "A counter was filled in from the other one, so its confidence label is synthesized rather than parsed".to_string(),
+            suggested_actions: vec![
+                "Make sure the logs print both PREDICTED_UPDATE_BYTES and CHANGED_CONTENT_BYTES".to_string(),
+                "Treat waste_ratio as a lower bound until both counters are parsed".to_string(),
+            ],
+            source_report: None,
+            accepted: false,
+        });
+    }
+
     findings.sort_by(|a, b| a.id.cmp(&b.id));
     findings
 }
+
+/// Describes the fallback [`crate::compute_metrics`] takes for `counters`,
+/// if any: a counter that was missing, or zero beside real changes, and got
+/// the other counter's value.
+fn counter_fallback(counters: &SteamPipeCounters) -> Option<String> {
+    match (counters.predicted_update_bytes, counters.changed_content_bytes) {
+        (Some(0), Some(cb)) if cb > 0 => Some(format!(
+            "predicted_update_bytes=0 with changed_content_bytes={cb}; new_bytes taken from changed_content_bytes"
+        )),
+        (Some(nb), None) => Some(format!(
+            "changed_content_bytes missing; taken from predicted_update_bytes={nb}"
+        )),
+        (None, Some(cb)) => Some(format!(
+            "predicted_update_bytes missing; new_bytes taken from changed_content_bytes={cb}"
+        )),
+        _ => None,
+    }
+}
+
+/// Rules over the aggregate parse only. `WOULD_FAIL_STRICT` fires in best-effort
+/// mode on the condition strict mode bails on, so the gap is visible before
+/// `strict = true` is enforced.
+pub fn run_strict_preview_rules(parsed: &ParsedBuildOutput) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+
+    if parsed.mode == ParseMode::BestEffort && parsed.counters.predicted_update_bytes.is_none() {
+        findings.push(Finding {
+            id: "WOULD_FAIL_STRICT".to_string(),
+            severity: Severity::Low,
+            evidence: vec!["missing required counter predicted_update_bytes".to_string()],
+            likely_cause: "Strict mode would reject this input".to_string(),
+            suggested_actions: vec![
+                "Make sure the SteamPipe preview logs print PREDICTED_UPDATE_BYTES=...".to_string(),
+                "Provide the counter through a sidecar before enabling strict mode".to_string(),
+            ],
+            source_report: None,
+            accepted: false,
+        });
+    }
+
+    findings
+}
+
+/// Rules over the baseline and how the current metrics compare to it. `now`
+/// is Unix seconds; a baseline without `created_at` is never stale.
+pub fn run_baseline_rules(
+    baseline: &Baseline,
+    metrics: &Metrics,
+    now: u64,
+    thresholds: &RuleThresholds,
+) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+
+    // Needs both counters in the baseline.
+    let (base_new, base_changed) = (
+        baseline.baseline_new_bytes,
+        baseline.baseline_changed_content_bytes,
+    );
+    if base_new > 0 && base_changed > 0 {
+        let new_drift = (metrics.new_bytes as f64 - base_new as f64).abs() / base_new as f64;
+        let changed_growth =
+            (metrics.changed_content_bytes as f64 - base_changed as f64) / base_changed as f64;
+        if new_drift <= FLAT_NEW_BYTES_TOLERANCE
+            && changed_growth > thresholds.efficient_change_growth
+        {
+            findings.push(Finding {
+                id: "EFFICIENT_LARGE_CHANGE".to_string(),
+                severity: Severity::Low,
+                evidence: vec![
+                    format!(
+                        "changed_content_bytes grew {:.1}% ({} -> {}, limit {:.1}%)",
+                        changed_growth * 100.0,
+                        base_changed,
+                        metrics.changed_content_bytes,
+                        thresholds.efficient_change_growth * 100.0
+                    ),
+                    format!(
+                        "new_bytes stayed flat ({} -> {})",
+                        base_new, metrics.new_bytes
+                    ),
+                ],
+                likely_cause: "The delta is efficient, but the build changed more content than the baseline did".to_string(),
+                suggested_actions: vec![
+                    "Check that the extra changed content is intended".to_string(),
+                    "Raise efficient_change_growth in patchwaste.toml if large content changes are routine".to_string(),
+                ],
+                source_report: None,
+                accepted: false,
+            });
+        }
+    }
+
+    if let Some(created) = baseline.created_at {
+        let age_days = now.saturating_sub(created) / 86_400;
+        if age_days > thresholds.max_baseline_age_days {
+            findings.push(Finding {
+                id: "STALE_BASELINE".to_string(),
+                severity: Severity::Low,
+                evidence: vec![format!(
+                    "baseline is {} days old (limit {})",
+                    age_days, thresholds.max_baseline_age_days
+                )],
+                likely_cause: "The baseline predates many content changes, so the budget compares against an outdated build".to_string(),
+                suggested_actions: vec![
+                    "Refresh the baseline from a recent known-good build".to_string(),
+                    "Raise max_baseline_age_days in patchwaste.toml if old baselines are intended".to_string(),
+                ],
+                source_report: None,
+                accepted: false,
+            });
+        }
+    }
+
+    findings
+}
+
+/// Rules over the run history. `new_bytes_series` is oldest first and ends with
+/// the current run.
+pub fn run_trend_rules(new_bytes_series: &[u64], thresholds: &RuleThresholds) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+
+    let n = thresholds.sustained_growth_samples;
+    if n >= 2 && new_bytes_series.len() >= n {
+        let tail = &new_bytes_series[new_bytes_series.len() - n..];
+        if tail.windows(2).all(|w| w[1] > w[0]) {
+            findings.push(Finding {
+                id: "SUSTAINED_GROWTH".to_string(),
+                severity: Severity::Low,
+                evidence: vec![format!(
+                    "new_bytes over last {} runs: {}",
+                    n,
+                    tail.iter()
+                        .map(|b| b.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" -> ")
+                )],
+                likely_cause: "Patch size has grown on every recent build".to_string(),
+                suggested_actions: vec![
+                    "Review recent content additions for unintended churn".to_string(),
+                    "Re-baseline deliberately if the growth is expected".to_string(),
+                ],
+                source_report: None,
+                accepted: false,
+            });
+        }
+    }
+
+    findings
+}
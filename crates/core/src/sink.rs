@@ -0,0 +1,162 @@
+//! Destinations for report artifacts. The CLI writes into `--out` through
+//! [`FileSink`]; embedders can implement [`OutputSink`] to keep artifacts in
+//! memory or hand them to another store.
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+/// Renders into a writer; used by [`OutputSink::write_with`].
+pub type Render<'a> = &'a mut dyn FnMut(&mut dyn Write) -> std::io::Result<()>;
+
+pub trait OutputSink {
+    /// Stores `bytes` as the artifact `name`, e.g. `report.json`.
+    fn write(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()>;
+
+    /// Stores whatever `render` writes as `name`. Buffers and calls
+    /// [`OutputSink::write`] unless the sink can stream.
+    fn write_with(&self, name: &str, render: Render<'_>) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        render(&mut buf).with_context(|| format!("render {name}"))?;
+        self.write(name, &buf)
+    }
+}
+
+/// Writes each artifact to a file of that name in `dir`. Each file is written
+/// to a temporary sibling first and renamed into place, so a reader never
+/// sees a truncated artifact.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    dir: PathBuf,
+    skip_unchanged: bool,
+}
+
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            skip_unchanged: false,
+        }
+    }
+
+    /// Leaves a file untouched, mtime included, when it already holds exactly
+    /// the bytes being written.
+    pub fn skip_unchanged(mut self, skip: bool) -> Self {
+        self.skip_unchanged = skip;
+        self
+    }
+
+    /// Where the artifact `name` is written.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// Temporary sibling of `name`; in the same directory so the rename
+    /// stays on one filesystem.
+    fn temp_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!(".{name}.{}.tmp", std::process::id()))
+    }
+
+    /// Runs `fill` against the temp file, then renames it over `name`. The
+    /// temp file is removed if anything fails.
+    fn replace(
+        &self,
+        name: &str,
+        fill: impl FnOnce(&mut std::io::BufWriter<std::fs::File>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let path = self.path(name);
+        let tmp = self.temp_path(name);
+        let result = (|| {
+            let f =
+                std::fs::File::create(&tmp).with_context(|| format!("create {}", tmp.display()))?;
+            let mut w = std::io::BufWriter::new(f);
+            fill(&mut w)?;
+            let f = w
+                .into_inner()
+                .map_err(|e| e.into_error())
+                .with_context(|| format!("write {}", path.display()))?;
+            f.sync_all()
+                .with_context(|| format!("write {}", path.display()))?;
+            std::fs::rename(&tmp, &path)
+                .with_context(|| format!("rename {} to {}", tmp.display(), path.display()))
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        result
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let path = self.path(name);
+        if self.skip_unchanged && std::fs::read(&path).is_ok_and(|old| old == bytes) {
+            return Ok(());
+        }
+        self.replace(name, |w| {
+            w.write_all(bytes)
+                .with_context(|| format!("write {}", path.display()))
+        })
+    }
+
+    fn write_with(&self, name: &str, render: Render<'_>) -> anyhow::Result<()> {
+        if self.skip_unchanged {
+            // Comparing needs the whole artifact anyway.
+            let mut buf = Vec::new();
+            render(&mut buf).with_context(|| format!("render {name}"))?;
+            return self.write(name, &buf);
+        }
+        let path = self.path(name);
+        self.replace(name, |w| {
+            render(w).with_context(|| format!("write {}", path.display()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_replace_the_file_without_leaving_temp_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileSink::new(dir.path());
+        std::fs::write(sink.path("report.json"), b"{\"trunc").unwrap();
+
+        sink.write("report.json", b"{\"ok\":true}").unwrap();
+        sink.write_with("report.md", &mut |w| w.write_all(b"# report\n"))
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(sink.path("report.json")).unwrap(),
+            b"{\"ok\":true}"
+        );
+        assert_eq!(
+            std::fs::read(sink.path("report.md")).unwrap(),
+            b"# report\n"
+        );
+        let mut names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["report.json", "report.md"]);
+    }
+
+    #[test]
+    fn failed_render_keeps_the_previous_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileSink::new(dir.path());
+        sink.write("report.json", b"old").unwrap();
+
+        let err = sink.write_with("report.json", &mut |w| {
+            w.write_all(b"partial")?;
+            Err(std::io::Error::other("boom"))
+        });
+
+        assert!(err.is_err());
+        assert_eq!(std::fs::read(sink.path("report.json")).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
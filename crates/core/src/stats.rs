@@ -0,0 +1,223 @@
+//! Streaming statistics over values that are seen once and not retained.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::FileOffender;
+
+/// P² quantile estimator (Jain & Chlamtac, 1985): tracks one quantile in
+/// constant memory using five markers. Exact for the first five values.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    /// Estimator for quantile `p` in `[0, 1]`.
+    pub fn new(p: f64) -> Self {
+        let p = p.clamp(0.0, 1.0);
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn add(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let q = &mut self.heights;
+        let k = if x < q[0] {
+            q[0] = x;
+            0
+        } else if x >= q[4] {
+            q[4] = x;
+            3
+        } else {
+            (0..4).find(|&i| x < q[i + 1]).unwrap_or(3)
+        };
+        for n in &mut self.positions[k + 1..] {
+            *n += 1.0;
+        }
+        for (np, dn) in self.desired.iter_mut().zip(self.increments) {
+            *np += dn;
+        }
+
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            let n = &self.positions;
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = self.parabolic(i, d);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, d)
+                    };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let j = if d > 0.0 { i + 1 } else { i - 1 };
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+    }
+
+    /// Current estimate, or `None` before any value was added.
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..=5 => {
+                let mut seen = self.heights[..self.count].to_vec();
+                seen.sort_by(f64::total_cmp);
+                Some(exact_quantile(&seen, self.p))
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+/// Linearly interpolated quantile of sorted `values`.
+pub fn exact_quantile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// Count, byte total and percentile estimates over offenders fed one at a
+/// time, in constant memory. Each offender line counts once, so a path
+/// listed by two logs is counted twice.
+#[derive(Debug, Clone)]
+pub struct OffenderTally {
+    count: usize,
+    bytes: u64,
+    estimators: [P2Quantile; 3],
+}
+
+impl Default for OffenderTally {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            bytes: 0,
+            estimators: [0.5, 0.9, 0.99].map(P2Quantile::new),
+        }
+    }
+}
+
+impl OffenderTally {
+    pub fn add(&mut self, bytes: u64) {
+        self.count += 1;
+        self.bytes = self.bytes.saturating_add(bytes);
+        for e in &mut self.estimators {
+            e.add(bytes as f64);
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Sum of all offender bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Percentiles so far; `None` without offenders.
+    pub fn stats(&self) -> Option<OffenderStats> {
+        let [p50, p90, p99] = self
+            .estimators
+            .each_ref()
+            .map(|e| e.estimate().unwrap_or(0.0).round() as u64);
+        (self.count > 0).then_some(OffenderStats {
+            count: self.count,
+            p50_bytes: p50,
+            p90_bytes: p90,
+            p99_bytes: p99,
+        })
+    }
+}
+
+/// Size distribution over every offender, not just the reported top N.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OffenderStats {
+    pub count: usize,
+    pub p50_bytes: u64,
+    pub p90_bytes: u64,
+    pub p99_bytes: u64,
+}
+
+impl OffenderStats {
+    /// Estimates the percentiles in one pass; `None` without offenders.
+    pub fn from_offenders<'a>(
+        offenders: impl IntoIterator<Item = &'a FileOffender>,
+    ) -> Option<Self> {
+        let mut tally = OffenderTally::default();
+        for o in offenders {
+            tally.add(o.bytes);
+        }
+        tally.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p2_tracks_exact_percentiles_on_a_uniform_distribution() {
+        // 1..=10_000 in a fixed scrambled order (7919 is coprime to 10_000).
+        let values: Vec<f64> = (0..10_000u64)
+            .map(|i| ((i * 7919) % 10_000 + 1) as f64)
+            .collect();
+        let mut sorted = values.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        for p in [0.5, 0.9, 0.99] {
+            let mut est = P2Quantile::new(p);
+            for v in &values {
+                est.add(*v);
+            }
+            let exact = exact_quantile(&sorted, p);
+            let estimate = est.estimate().unwrap();
+            assert!(
+                (estimate - exact).abs() / exact < 0.02,
+                "p={p} estimate={estimate} exact={exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn p2_is_exact_for_few_values() {
+        let mut est = P2Quantile::new(0.5);
+        assert_eq!(est.estimate(), None);
+        for v in [5.0, 1.0, 3.0] {
+            est.add(v);
+        }
+        assert_eq!(est.estimate(), Some(3.0));
+    }
+}
@@ -3,8 +3,11 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ConfidenceLevel {
+    #[serde(alias = "high")]
     High,
+    #[serde(alias = "medium")]
     Medium,
+    #[serde(alias = "low")]
     Low,
 }
 
@@ -16,10 +19,40 @@ pub struct Metrics {
     pub waste_ratio: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Metrics {
+    /// `changed_content_bytes / new_bytes` without the `[0, 1]` clamp applied
+    /// to `delta_efficiency`, which `waste_ratio` is derived from. Above 1 when
+    /// more content changed than the patch ships, amplification the clamped
+    /// value hides. `None` when new_bytes is 0.
+    pub fn unclamped_delta_efficiency(&self) -> Option<f64> {
+        (self.new_bytes > 0).then(|| self.changed_content_bytes as f64 / self.new_bytes as f64)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FileOffender {
     pub path: String,
+    /// Derived from `percent` and predicted_update_bytes when the log gave a
+    /// share rather than a size.
     pub bytes: u64,
+    /// Share of the update the log reported, e.g. `12.5` for `12.5%`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    /// Logs that reported this path.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+}
+
+/// Shortens `path` to at most `max` characters, keeping the tail (file name)
+/// behind a leading ellipsis.
+pub fn truncate_path(path: &str, max: usize) -> String {
+    let len = path.chars().count();
+    if len <= max {
+        return path.to_string();
+    }
+    let keep = max.saturating_sub(1);
+    let tail: String = path.chars().skip(len - keep).collect();
+    format!("\u{2026}{tail}")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,9 +62,40 @@ pub struct Finding {
     pub evidence: Vec<String>,
     pub likely_cause: String,
     pub suggested_actions: Vec<String>,
+    /// Label of the report this finding came from, set by `merge`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_report: Option<String>,
+    /// Reviewed and accepted for this build via `[accepted]`; demoted to
+    /// `LOW` and not counted as a failure.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub accepted: bool,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl Finding {
+    /// Coarse grouping of the finding's likely cause, for triage.
+    pub fn cause_category(&self) -> &'static str {
+        match self.id.as_str() {
+            "HIGH_WASTE_RATIO" => "churn",
+            "LARGE_TOP_OFFENDER" => "layout",
+            "SUSTAINED_GROWTH" | "EFFICIENT_LARGE_CHANGE" => "trend",
+            "UNRECOGNIZED_LOG_FORMAT"
+            | "OFFENDER_SUM_ANOMALY"
+            | "COUNTER_DISPROPORTION"
+            | "WOULD_FAIL_STRICT"
+            | "OFFENDERS_WITHOUT_COUNTERS"
+            | "FALLBACK_CONFIDENCE"
+            | "STALE_BASELINE" => "input",
+            _ => "other",
+        }
+    }
+}
+
+/// Ordered `Low < Medium < High`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Severity {
     Low,
@@ -39,8 +103,112 @@ pub enum Severity {
     High,
 }
 
+impl Severity {
+    /// Numeric rank, higher is more severe.
+    pub fn order(self) -> u8 {
+        match self {
+            Severity::Low => 0,
+            Severity::Medium => 1,
+            Severity::High => 2,
+        }
+    }
+}
+
+/// Metric a baseline comparison is keyed on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BaselineMetric {
+    #[default]
+    NewBytes,
+    ChangedContentBytes,
+}
+
+impl BaselineMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BaselineMetric::NewBytes => "new_bytes",
+            BaselineMetric::ChangedContentBytes => "changed_content_bytes",
+        }
+    }
+}
+
+/// Green/amber/red cut-offs for the waste ratio. A ratio below `green_max` is
+/// green, below `amber_max` amber, and red otherwise.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct WasteBands {
+    pub green_max: f64,
+    pub amber_max: f64,
+}
+
+impl Default for WasteBands {
+    fn default() -> Self {
+        Self {
+            green_max: 0.3,
+            amber_max: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasteBand {
+    Green,
+    Amber,
+    Red,
+}
+
+impl WasteBands {
+    pub fn band(&self, ratio: f64) -> WasteBand {
+        if ratio < self.green_max {
+            WasteBand::Green
+        } else if ratio < self.amber_max {
+            WasteBand::Amber
+        } else {
+            WasteBand::Red
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DepotClass {
+    Code,
+    Content,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DepotMetrics {
     pub depot_id: String,
     pub metrics: Metrics,
 }
+
+/// `n` in binary units with one decimal, e.g. `1.5 KiB`; below 1 KiB as `<n> B`.
+pub fn human_bytes(n: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if n < 1024 {
+        return format!("{n} B");
+    }
+    let mut value = n as f64 / 1024.0;
+    let mut unit = 0;
+    // Promote values that would print as 1024.0 of the smaller unit.
+    while value >= 1023.95 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn human_bytes_boundaries() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1024), "1.0 KiB");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(1_048_575), "1.0 MiB");
+        assert_eq!(human_bytes(1_048_576), "1.0 MiB");
+        assert_eq!(human_bytes(u64::MAX), "16.0 EiB");
+    }
+}
@@ -4,17 +4,19 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use patchwaste_core::{analyse_dir, AnalyseOptions};
+use patchwaste_core::{analyse_dir, analyse_logs, AnalyseOptions};
 
 #[test]
 fn analyse_fixture_produces_stable_report_json() {
-    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let input = root.join("fixtures/synthetic_case_01/BuildOutput");
     let opts = AnalyseOptions {
         strict: false,
+        path_root: Some(root.clone()),
         ..AnalyseOptions::default()
     };
 
-    let report = analyse_dir(input, opts).expect("analyse_dir ok");
+    let report = analyse_dir(&input, opts).expect("analyse_dir ok");
 
     assert_eq!(report.metrics.new_bytes, 12_345_678);
     assert_eq!(report.metrics.changed_content_bytes, 2_000_000);
@@ -27,6 +29,33 @@ fn analyse_fixture_produces_stable_report_json() {
     insta::assert_json_snapshot!(report);
 }
 
+#[test]
+fn gzipped_logs_parse_like_plain_ones() {
+    use patchwaste_core::parser::{parse_buildoutput_dir_stats, ParseOptions};
+
+    let plain = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let gz = Path::new("../../fixtures/synthetic_case_gz/BuildOutput");
+    let (expected, _) = parse_buildoutput_dir_stats(plain, &ParseOptions::default()).unwrap();
+    let (parsed, stats) = parse_buildoutput_dir_stats(gz, &ParseOptions::default()).unwrap();
+
+    assert_eq!(parsed.counters.predicted_update_bytes, Some(12_345_678));
+    assert_eq!(
+        parsed.counters.predicted_update_bytes,
+        expected.counters.predicted_update_bytes
+    );
+    assert_eq!(
+        parsed.counters.changed_content_bytes,
+        expected.counters.changed_content_bytes
+    );
+    assert_eq!(parsed.offenders[0].path, expected.offenders[0].path);
+    assert_eq!(stats.files_matched, 1);
+    // The scan budget is charged the compressed size.
+    let on_disk = fs::metadata(gz.join("steampipe_preview.log.gz"))
+        .unwrap()
+        .len();
+    assert_eq!(stats.bytes_scanned, on_disk);
+}
+
 #[test]
 fn strict_mode_requires_required_counter() {
     let input = Path::new("../../fixtures/synthetic_case_missing_required/BuildOutput");
@@ -43,6 +72,255 @@ fn strict_mode_requires_required_counter() {
     );
 }
 
+#[test]
+fn accepted_findings_apply_only_to_their_build() {
+    use patchwaste_core::config::Config;
+    use patchwaste_core::report::{BuildMetadata, JunitOptions};
+    use patchwaste_core::types::Severity;
+
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let cfg: Config =
+        toml::from_str("[accepted]\n\"build-42\" = [\"HIGH_WASTE_RATIO\"]\n").unwrap();
+    let analyse = |build_id: &str| {
+        let opts = AnalyseOptions {
+            build_metadata: Some(BuildMetadata {
+                sha: None,
+                branch: None,
+                build_id: Some(build_id.to_string()),
+            }),
+            accepted_findings: cfg.accepted.clone(),
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(input, opts).unwrap()
+    };
+    let failures = |report: &patchwaste_core::report::Report| {
+        report
+            .to_junit_xml_with(&JunitOptions::default())
+            .matches("<failure ")
+            .count()
+    };
+
+    let other = analyse("build-41");
+    let waste = other
+        .findings
+        .iter()
+        .find(|f| f.id == "HIGH_WASTE_RATIO")
+        .unwrap();
+    assert_eq!(waste.severity, Severity::High);
+    assert!(!waste.accepted);
+    assert_eq!(other.worst_severity(), Some(Severity::High));
+
+    let accepted = analyse("build-42");
+    let waste = accepted
+        .findings
+        .iter()
+        .find(|f| f.id == "HIGH_WASTE_RATIO")
+        .unwrap();
+    assert_eq!(waste.severity, Severity::Low);
+    assert!(waste.accepted);
+    assert_ne!(accepted.worst_severity(), Some(Severity::High));
+    assert_eq!(failures(&accepted), failures(&other) - 1);
+}
+
+#[test]
+fn strict_collect_all_names_every_log_missing_the_counter() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.log"), "CHANGED_CONTENT_BYTES=10\n").unwrap();
+    fs::write(dir.path().join("b.log"), "no counters\n").unwrap();
+    fs::write(dir.path().join("ok.log"), "PREDICTED_UPDATE_BYTES=100\n").unwrap();
+
+    let analyse = |collect_all| {
+        let opts = AnalyseOptions {
+            strict: true,
+            strict_collect_all: collect_all,
+            ..AnalyseOptions::default()
+        };
+        format!("{:#}", analyse_dir(dir.path(), opts).unwrap_err())
+    };
+
+    let msg = analyse(true);
+    assert!(msg.contains("in 2 logs"), "{msg}");
+    assert!(msg.contains("a.log") && msg.contains("b.log"), "{msg}");
+    assert!(!msg.contains("ok.log"), "{msg}");
+
+    let msg = analyse(false);
+    assert!(!(msg.contains("a.log") && msg.contains("b.log")), "{msg}");
+}
+
+#[test]
+fn best_effort_flags_input_strict_mode_would_reject() {
+    use patchwaste_core::types::Severity;
+
+    let input = Path::new("../../fixtures/synthetic_case_missing_required/BuildOutput");
+    let report = analyse_dir(input, AnalyseOptions::default()).expect("best-effort analyse");
+    let finding = report
+        .findings
+        .iter()
+        .find(|f| f.id == "WOULD_FAIL_STRICT")
+        .expect("WOULD_FAIL_STRICT finding");
+    assert_eq!(finding.severity, Severity::Low);
+
+    let ok = analyse_dir(
+        Path::new("../../fixtures/synthetic_case_01/BuildOutput"),
+        AnalyseOptions::default(),
+    )
+    .unwrap();
+    assert!(ok.findings.iter().all(|f| f.id != "WOULD_FAIL_STRICT"));
+}
+
+#[test]
+fn disabling_rules_keeps_metrics_and_drops_findings() {
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let full = analyse_dir(input, AnalyseOptions::default()).unwrap();
+    assert!(!full.findings.is_empty());
+
+    let opts = AnalyseOptions {
+        run_rules: false,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).unwrap();
+    assert!(report.findings.is_empty());
+    assert!(report.per_depot.iter().all(|d| d.findings.is_empty()));
+    assert_eq!(report.metrics.new_bytes, full.metrics.new_bytes);
+    assert_eq!(report.metrics.waste_ratio, full.metrics.waste_ratio);
+}
+
+#[test]
+fn baseline_embedded_budget_gates_when_no_other_budget_is_set() {
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let baseline = r#"{"metrics":{"new_bytes":1000},"budget_ratio":1.25}"#;
+
+    let opts = AnalyseOptions {
+        baseline_inline: Some(baseline.to_string()),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).unwrap();
+    let budget = report.budget.as_ref().expect("budget from baseline");
+    assert_eq!(budget.threshold_regression_ratio, Some(1.25));
+    assert!(!budget.pass);
+
+    // An explicit budget wins over the embedded one.
+    let opts = AnalyseOptions {
+        baseline_inline: Some(baseline.to_string()),
+        budget_ratio: Some(1e9),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).unwrap();
+    assert!(report.budget.as_ref().unwrap().pass);
+}
+
+#[test]
+fn budget_expression_combines_clauses_by_policy() {
+    use patchwaste_core::config::Config;
+    use patchwaste_core::report::{BudgetClause, BudgetExpression, BudgetMetric, BudgetPolicy};
+
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let cfg: Config = toml::from_str(
+        r#"
+[budget_expression]
+policy = "and"
+clauses = [
+  { metric = "regression_ratio", max = 1.25 },
+  { metric = "waste_ratio", max = 1.0 },
+]
+"#,
+    )
+    .unwrap();
+    let mut expr = cfg.budget_expression.expect("budget_expression parsed");
+    let analyse = |expr| {
+        let opts = AnalyseOptions {
+            baseline_inline: Some(r#"{"new_bytes":1000}"#.to_string()),
+            budget_expression: Some(expr),
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(input, opts).unwrap().budget.unwrap()
+    };
+
+    let budget = analyse(expr.clone());
+    assert!(!budget.clauses[0].pass, "regression clause trips");
+    assert!(budget.clauses[1].pass, "waste clause holds");
+    assert!(budget.pass, "and: fails only when every clause trips");
+    assert_eq!(budget.policy, Some(BudgetPolicy::And));
+    assert_eq!(budget.threshold_regression_ratio, Some(1.25));
+
+    expr.policy = BudgetPolicy::Or;
+    let budget = analyse(expr);
+    assert!(!budget.pass, "or: fails when any clause trips");
+    assert_eq!(budget.reason, "1 of 2 clauses failed (or policy)");
+
+    // Without a regression_ratio clause there is no threshold to report.
+    let budget = analyse(BudgetExpression {
+        policy: BudgetPolicy::Or,
+        clauses: vec![BudgetClause {
+            metric: BudgetMetric::WasteRatio,
+            min: None,
+            max: Some(0.1),
+        }],
+    });
+    assert!(!budget.pass);
+    assert_eq!(budget.threshold_regression_ratio, None);
+    assert!(budget.failure_detail().starts_with("waste_ratio"));
+}
+
+#[test]
+fn flat_new_bytes_with_much_more_changed_content_is_noted() {
+    use patchwaste_core::types::Severity;
+
+    // The fixture has new_bytes 12_345_678 and changed_content_bytes 2_000_000.
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let analyse = |baseline: &str| {
+        let opts = AnalyseOptions {
+            baseline_inline: Some(baseline.to_string()),
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(input, opts)
+            .unwrap()
+            .findings
+            .into_iter()
+            .find(|f| f.id == "EFFICIENT_LARGE_CHANGE")
+    };
+
+    let finding = analyse(r#"{"new_bytes":12345678,"changed_content_bytes":1000000}"#)
+        .expect("EFFICIENT_LARGE_CHANGE finding");
+    assert_eq!(finding.severity, Severity::Low);
+    assert!(finding.evidence[0].contains("grew 100.0%"));
+
+    // Changed content grew only 25%, or new_bytes grew too, or the baseline
+    // lacks changed_content_bytes.
+    assert!(analyse(r#"{"new_bytes":12345678,"changed_content_bytes":1600000}"#).is_none());
+    assert!(analyse(r#"{"new_bytes":6000000,"changed_content_bytes":1000000}"#).is_none());
+    assert!(analyse(r#"{"new_bytes":12345678}"#).is_none());
+}
+
+#[test]
+fn old_baseline_is_flagged_stale() {
+    use patchwaste_core::types::Severity;
+
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let analyse = |baseline: &str| {
+        let opts = AnalyseOptions {
+            baseline_inline: Some(baseline.to_string()),
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(input, opts)
+            .unwrap()
+            .findings
+            .into_iter()
+            .find(|f| f.id == "STALE_BASELINE")
+    };
+
+    let stale = analyse(r#"{"new_bytes":1000,"created_at":"2020-01-01T00:00:00Z"}"#)
+        .expect("STALE_BASELINE finding");
+    assert_eq!(stale.severity, Severity::Low);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(analyse(&format!(r#"{{"new_bytes":1000,"created_at":{now}}}"#)).is_none());
+    assert!(analyse(r#"{"new_bytes":1000}"#).is_none());
+}
+
 #[test]
 fn baseline_comparison_and_budget_gate_are_computed() {
     let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
@@ -73,6 +351,44 @@ fn baseline_comparison_and_budget_gate_are_computed() {
     let _ = fs::remove_file(baseline_path);
 }
 
+#[test]
+fn baseline_comparison_metric_is_independent_of_budget_metric() {
+    use patchwaste_core::types::BaselineMetric;
+
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let baseline_path =
+        std::env::temp_dir().join(format!("patchwaste-core-baseline-metric-{nonce}.json"));
+    fs::write(
+        &baseline_path,
+        r#"{"metrics":{"new_bytes":1000,"changed_content_bytes":1000000}}"#,
+    )
+    .unwrap();
+
+    let opts = AnalyseOptions {
+        baseline_path: Some(baseline_path.clone()),
+        budget_ratio: Some(1.25),
+        baseline_metric: BaselineMetric::ChangedContentBytes,
+        ..AnalyseOptions::default()
+    };
+
+    let report = analyse_dir(input, opts).expect("analyse_dir with baseline");
+    let cmp = report.baseline_comparison.as_ref().unwrap();
+    let budget = report.budget.as_ref().unwrap();
+
+    assert_eq!(cmp.metric, BaselineMetric::ChangedContentBytes);
+    assert_eq!(cmp.delta_changed_content_bytes, Some(1_000_000));
+    assert!((cmp.regression_ratio - 2.0).abs() < 1e-9);
+    // The gate still evaluates new_bytes: 12_345_678 / 1000.
+    assert!(budget.regression_ratio.unwrap() > 12_000.0);
+    assert!(!budget.pass);
+
+    let _ = fs::remove_file(baseline_path);
+}
+
 #[test]
 fn automation_dummy_fixture_is_parseable() {
     let input = Path::new("../../fixtures/automation_dummy/BuildOutput");
@@ -109,6 +425,219 @@ fn multi_depot_fixture_produces_per_depot_metrics() {
     assert!(report.metrics.new_bytes > 0);
 }
 
+#[test]
+fn excluded_depot_is_dropped_from_aggregate_and_offenders() {
+    let input = Path::new("../../fixtures/multi_depot/BuildOutput");
+    let opts = AnalyseOptions {
+        exclude_depots: vec!["67890".to_string()],
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).unwrap();
+
+    assert_eq!(report.per_depot.len(), 1);
+    assert_eq!(report.per_depot[0].depot_id, "12345");
+    assert_eq!(report.metrics.new_bytes, 5_000_000);
+    assert_eq!(report.metrics.changed_content_bytes, 3_000_000);
+    assert!(report.offenders.iter().all(|o| o.path != "DepotB.pak"));
+
+    let opts = AnalyseOptions {
+        include_depots: vec!["67890".to_string()],
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).unwrap();
+    assert_eq!(report.metrics.new_bytes, 8_000_000);
+}
+
+#[test]
+fn depot_classes_apply_class_specific_waste_thresholds() {
+    use patchwaste_core::rules::RuleThresholds;
+    use patchwaste_core::types::DepotClass;
+
+    let input = Path::new("../../fixtures/multi_depot/BuildOutput");
+    let opts = AnalyseOptions {
+        rule_thresholds: RuleThresholds {
+            code_waste_ratio: Some(0.30),
+            content_waste_ratio: Some(0.90),
+            ..RuleThresholds::default()
+        },
+        depot_classes: [
+            ("12345".to_string(), DepotClass::Code),
+            ("67890".to_string(), DepotClass::Content),
+        ]
+        .into_iter()
+        .collect(),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).expect("analyse multi_depot");
+
+    let fired = |id: &str| {
+        report
+            .per_depot
+            .iter()
+            .find(|d| d.depot_id == id)
+            .expect("depot present")
+            .findings
+            .iter()
+            .any(|f| f.id == "HIGH_WASTE_RATIO")
+    };
+    // 12345 wastes 0.4 against a 0.3 code threshold; 67890 wastes 0.875 against 0.9.
+    assert!(fired("12345"));
+    assert!(!fired("67890"));
+}
+
+#[test]
+fn sidecar_counters_override_log_parsed_values() {
+    use patchwaste_core::types::ConfidenceLevel;
+
+    let input = Path::new("../../fixtures/sidecar_override/BuildOutput");
+    let report = analyse_dir(input, AnalyseOptions::default()).expect("analyse sidecar fixture");
+
+    assert_eq!(report.metrics.new_bytes, 9_000_000);
+    // The sidecar omits changed bytes, so the log value stands.
+    assert_eq!(report.metrics.changed_content_bytes, 500);
+    assert_eq!(report.confidence.new_bytes, ConfidenceLevel::High);
+}
+
+#[test]
+fn summed_logs_count_a_shared_sidecar_once() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["a.log", "b.log"] {
+        std::fs::write(
+            dir.path().join(name),
+            "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=500\n",
+        )
+        .unwrap();
+    }
+    std::fs::write(
+        dir.path().join("counters.json"),
+        r#"{"predicted_update_bytes": 9000000}"#,
+    )
+    .unwrap();
+
+    let opts = AnalyseOptions {
+        counter_merge: patchwaste_core::parser::CounterMerge::Sum,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).expect("analyse summed logs");
+
+    assert_eq!(report.metrics.new_bytes, 9_000_000);
+    // Changed bytes come from the logs, so both are summed.
+    assert_eq!(report.metrics.changed_content_bytes, 1000);
+}
+
+#[test]
+fn long_offender_paths_are_truncated_after_matching() {
+    let dir = tempfile::tempdir().unwrap();
+    let prefix_a = "a/".repeat(60);
+    let prefix_b = "b/".repeat(60);
+    fs::write(
+        dir.path().join("steampipe_preview.log"),
+        format!(
+            "PREDICTED_UPDATE_BYTES=1000\nTOP_OFFENDER={prefix_a}Shared.pak:900\nTOP_OFFENDER={prefix_b}Shared.pak:100\n"
+        ),
+    )
+    .unwrap();
+    let baseline_path = dir.path().join("baseline.json");
+    fs::write(
+        &baseline_path,
+        format!(
+            r#"{{"metrics":{{"new_bytes":1000}},"offenders":[{{"path":"{prefix_a}Shared.pak","bytes":500}}]}}"#
+        ),
+    )
+    .unwrap();
+
+    let opts = AnalyseOptions {
+        baseline_path: Some(baseline_path),
+        max_offender_path_len: Some(24),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).expect("analyse long paths");
+
+    // Both truncate to the same display path, but matching used the full paths.
+    use patchwaste_core::report::OffenderStatus;
+    assert_eq!(report.offender_diff[0].status, OffenderStatus::Grown);
+    assert_eq!(report.offender_diff[1].status, OffenderStatus::New);
+    assert_eq!(report.offenders[0].bytes, 900);
+    assert!(report
+        .offenders
+        .iter()
+        .all(|o| o.path.chars().count() <= 24));
+
+    let md = report.to_markdown();
+    assert!(md.contains("\u{2026}/b/b/b/b/b/b/Shared.pak"));
+    assert!(!md.contains(&prefix_a));
+}
+
+#[test]
+fn glob_input_merges_matching_logs() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.log"), "PREDICTED_UPDATE_BYTES=4000\n").unwrap();
+    fs::write(dir.path().join("b.log"), "CHANGED_CONTENT_BYTES=1000\n").unwrap();
+    fs::write(dir.path().join("ignored.txt"), "PREDICTED_UPDATE_BYTES=9\n").unwrap();
+
+    let pattern = dir.path().join("*.log");
+    let report = analyse_dir(&pattern, AnalyseOptions::default()).expect("analyse glob");
+
+    assert_eq!(report.inputs.sources.len(), 2);
+    assert_eq!(report.metrics.new_bytes, 4000);
+    assert_eq!(report.metrics.changed_content_bytes, 1000);
+
+    let empty = dir.path().join("*.missing");
+    let opts = AnalyseOptions {
+        strict: true,
+        ..AnalyseOptions::default()
+    };
+    assert!(analyse_dir(&empty, opts).is_err());
+}
+
+#[test]
+fn per_depot_can_be_disabled_without_changing_aggregate() {
+    let input = Path::new("../../fixtures/multi_depot/BuildOutput");
+    let full = analyse_dir(input, AnalyseOptions::default()).expect("analyse multi_depot");
+    let opts = AnalyseOptions {
+        per_depot: false,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).expect("analyse without per-depot");
+
+    assert!(report.per_depot.is_empty());
+    assert_eq!(report.metrics.new_bytes, full.metrics.new_bytes);
+    assert_eq!(
+        report.metrics.changed_content_bytes,
+        full.metrics.changed_content_bytes
+    );
+}
+
+#[test]
+fn monotonic_history_raises_sustained_growth() {
+    use patchwaste_core::types::Severity;
+
+    let dir = tempfile::tempdir().unwrap();
+    let history_path = dir.path().join("history.ndjson");
+    fs::write(
+        &history_path,
+        "{\"new_bytes\":9000000,\"changed_content_bytes\":1}\n\
+         {\"metrics\":{\"new_bytes\":10000000,\"changed_content_bytes\":1}}\n\
+         {\"new_bytes\":11000000,\"changed_content_bytes\":1}\n",
+    )
+    .unwrap();
+
+    let input = Path::new("../../fixtures/synthetic_case_01/BuildOutput");
+    let opts = AnalyseOptions {
+        history_path: Some(history_path),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(input, opts).expect("analyse with history");
+
+    let finding = report
+        .findings
+        .iter()
+        .find(|f| f.id == "SUSTAINED_GROWTH")
+        .expect("sustained growth finding");
+    assert_eq!(finding.severity, Severity::Low);
+    assert!(finding.evidence[0].contains("11000000 -> 12345678"));
+}
+
 #[test]
 fn extract_depot_id_from_filename() {
     use patchwaste_core::parser::extract_depot_id;
@@ -123,3 +652,654 @@ fn extract_depot_id_from_filename() {
         Some("99999".to_string())
     );
 }
+
+#[test]
+fn unrecognized_logs_are_counted_and_flagged() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("gibberish.log"),
+        "lorem ipsum\nno counters here\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+
+    assert_eq!(report.inputs.files_without_counters, 1);
+    assert!(report
+        .findings
+        .iter()
+        .any(|f| f.id == "UNRECOGNIZED_LOG_FORMAT"));
+}
+
+#[test]
+fn offenders_without_counters_are_flagged() {
+    use patchwaste_core::types::Severity;
+
+    let logs = vec![(
+        "preview.log".to_string(),
+        b"Building depot\nTOP_OFFENDER=Content/Paks/A.pak:5000\n".to_vec(),
+    )];
+    let report = analyse_logs("mem", &logs, AnalyseOptions::default()).unwrap();
+    let finding = report
+        .findings
+        .iter()
+        .find(|f| f.id == "OFFENDERS_WITHOUT_COUNTERS")
+        .expect("OFFENDERS_WITHOUT_COUNTERS finding");
+    assert_eq!(finding.severity, Severity::Medium);
+
+    let logs = vec![(
+        "preview.log".to_string(),
+        b"PREDICTED_UPDATE_BYTES=6000\nTOP_OFFENDER=Content/Paks/A.pak:5000\n".to_vec(),
+    )];
+    let report = analyse_logs("mem", &logs, AnalyseOptions::default()).unwrap();
+    assert!(report
+        .findings
+        .iter()
+        .all(|f| f.id != "OFFENDERS_WITHOUT_COUNTERS"));
+}
+
+#[test]
+fn offenders_far_above_new_bytes_flag_sum_anomaly() {
+    use patchwaste_core::rules::RuleThresholds;
+    use patchwaste_core::types::Severity;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=10\nCHANGED_CONTENT_BYTES=8\n\
+         TOP_OFFENDER=A.pak:1500\nTOP_OFFENDER=B.pak:1500\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    let anomaly = report
+        .findings
+        .iter()
+        .find(|f| f.id == "OFFENDER_SUM_ANOMALY")
+        .expect("anomaly finding");
+    assert_eq!(anomaly.severity, Severity::Low);
+
+    let opts = AnalyseOptions {
+        rule_thresholds: RuleThresholds {
+            offender_sum_factor: 400.0,
+            ..RuleThresholds::default()
+        },
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).unwrap();
+    assert!(!report
+        .findings
+        .iter()
+        .any(|f| f.id == "OFFENDER_SUM_ANOMALY"));
+}
+
+#[test]
+fn offender_sum_covers_offenders_beyond_the_retained_top_list() {
+    let mut log = String::from("PREDICTED_UPDATE_BYTES=100\nCHANGED_CONTENT_BYTES=80\n");
+    for i in 0..200 {
+        log.push_str(&format!("TOP_OFFENDER=file_{i:03}.pak:100\n"));
+    }
+    let logs = vec![("preview.log".to_string(), log.into_bytes())];
+
+    let report = analyse_logs("mem", &logs, AnalyseOptions::default()).unwrap();
+    let anomaly = report
+        .findings
+        .iter()
+        .find(|f| f.id == "OFFENDER_SUM_ANOMALY")
+        .expect("anomaly over all 200 offenders");
+    assert!(anomaly.evidence[0].contains("sum to 20000"));
+}
+
+#[test]
+fn offender_sum_counts_a_path_reported_by_several_logs_once() {
+    use patchwaste_core::rules::RuleThresholds;
+
+    let logs = vec![
+        (
+            "a.log".to_string(),
+            b"PREDICTED_UPDATE_BYTES=1000\nTOP_OFFENDER=A.pak:1500\n".to_vec(),
+        ),
+        ("b.log".to_string(), b"TOP_OFFENDER=A.pak:1500\n".to_vec()),
+    ];
+    let opts = AnalyseOptions {
+        rule_thresholds: RuleThresholds {
+            offender_sum_factor: 2.0,
+            ..RuleThresholds::default()
+        },
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_logs("mem", &logs, opts).unwrap();
+    assert!(report
+        .findings
+        .iter()
+        .all(|f| f.id != "OFFENDER_SUM_ANOMALY"));
+}
+
+#[test]
+fn top_offender_share_of_changed_content_flags_large_offender() {
+    use patchwaste_core::rules::RuleThresholds;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1200\nCHANGED_CONTENT_BYTES=1000\nTOP_OFFENDER=Level1.pak:600\n",
+    )
+    .unwrap();
+
+    let large = |share: Option<f64>| {
+        let opts = AnalyseOptions {
+            rule_thresholds: RuleThresholds {
+                top_offender_changed_share: share,
+                ..RuleThresholds::default()
+            },
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(dir.path(), opts)
+            .unwrap()
+            .findings
+            .into_iter()
+            .find(|f| f.id == "LARGE_TOP_OFFENDER")
+    };
+
+    // 600 bytes is nowhere near the absolute cut-off...
+    assert!(large(None).is_none());
+    // ...but it is 60% of the changed content.
+    let finding = large(Some(0.5)).expect("LARGE_TOP_OFFENDER finding");
+    assert!(finding.evidence.iter().any(|e| e.contains("60.0%")));
+    assert!(large(Some(0.75)).is_none());
+}
+
+#[test]
+fn outlier_top_offender_escalates_large_top_offender() {
+    use patchwaste_core::rules::RuleThresholds;
+    use patchwaste_core::types::Severity;
+
+    let severity = |offenders: &str| {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("preview.log"),
+            format!("PREDICTED_UPDATE_BYTES=2000\nCHANGED_CONTENT_BYTES=1000\n{offenders}"),
+        )
+        .unwrap();
+        let opts = AnalyseOptions {
+            rule_thresholds: RuleThresholds {
+                top_offender_changed_share: Some(0.1),
+                ..RuleThresholds::default()
+            },
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(dir.path(), opts)
+            .unwrap()
+            .findings
+            .into_iter()
+            .find(|f| f.id == "LARGE_TOP_OFFENDER")
+            .expect("LARGE_TOP_OFFENDER finding")
+    };
+
+    let outlier = severity(
+        "TOP_OFFENDER=Level1.pak:500\nTOP_OFFENDER=Level2.pak:50\nTOP_OFFENDER=Level3.pak:40\n",
+    );
+    assert_eq!(outlier.severity, Severity::High);
+    assert!(outlier.evidence.iter().any(|e| e.contains("10.0x")));
+
+    let uniform = severity(
+        "TOP_OFFENDER=Level1.pak:200\nTOP_OFFENDER=Level2.pak:190\nTOP_OFFENDER=Level3.pak:180\n",
+    );
+    assert_eq!(uniform.severity, Severity::Medium);
+}
+
+#[test]
+fn unclamped_delta_efficiency_shows_amplification() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=2000\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    assert_eq!(report.metrics.delta_efficiency, 1.0);
+    assert_eq!(report.metrics.waste_ratio, 0.0);
+    assert_eq!(report.metrics_extra.unclamped_delta_efficiency, Some(2.0));
+    assert!(report
+        .to_markdown()
+        .contains("- unclamped_delta_efficiency: `2.000`"));
+}
+
+#[test]
+fn counters_a_thousandfold_apart_flag_disproportion() {
+    use patchwaste_core::types::Severity;
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=1000000\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    let finding = report
+        .findings
+        .iter()
+        .find(|f| f.id == "COUNTER_DISPROPORTION")
+        .expect("disproportion finding");
+    assert_eq!(finding.severity, Severity::Low);
+}
+
+#[test]
+fn raw_counters_keep_absent_counters_as_null() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1000\n",
+    )
+    .unwrap();
+
+    let opts = AnalyseOptions {
+        raw_counters: true,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).unwrap();
+    let json = serde_json::to_value(&report).unwrap();
+
+    assert_eq!(json["raw_counters"]["predicted_update_bytes"], 1000);
+    assert!(json["raw_counters"]["changed_content_bytes"].is_null());
+    assert!(json["raw_counters"]
+        .as_object()
+        .unwrap()
+        .contains_key("changed_content_bytes"));
+    assert_eq!(json["metrics"]["changed_content_bytes"], 1000);
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    assert!(report.raw_counters.is_none());
+}
+
+#[test]
+fn efficiency_floor_fails_below_and_passes_above() {
+    let floor = |changed: u64| {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("preview.log"),
+            format!("PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES={changed}\n"),
+        )
+        .unwrap();
+        let opts = AnalyseOptions {
+            min_delta_efficiency: Some(0.5),
+            ..AnalyseOptions::default()
+        };
+        analyse_dir(dir.path(), opts).unwrap()
+    };
+
+    let low = floor(300);
+    assert!(!low.efficiency_floor.as_ref().unwrap().pass);
+    assert!(low.budget.is_none());
+    assert!(!low.budget_passed());
+
+    let high = floor(700);
+    assert!(high.efficiency_floor.as_ref().unwrap().pass);
+    assert!(high.budget_passed());
+}
+
+#[test]
+fn root_logs_as_depots_sum_into_the_aggregate() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, new, changed) in [("win", 100, 50), ("linux", 200, 100), ("mac", 300, 150)] {
+        fs::write(
+            dir.path().join(format!("{name}.log")),
+            format!("PREDICTED_UPDATE_BYTES={new}\nCHANGED_CONTENT_BYTES={changed}\n"),
+        )
+        .unwrap();
+    }
+
+    // Default merging keeps only one log's counters.
+    let merged = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    assert!(merged.metrics.new_bytes < 600);
+    assert!(merged.per_depot.is_empty());
+
+    let opts = AnalyseOptions {
+        root_logs_as_depots: true,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).unwrap();
+    assert_eq!(report.metrics.new_bytes, 600);
+    assert_eq!(report.metrics.changed_content_bytes, 300);
+    let ids: Vec<&str> = report
+        .per_depot
+        .iter()
+        .map(|d| d.depot_id.as_str())
+        .collect();
+    assert_eq!(ids, ["linux", "mac", "win"]);
+}
+
+#[test]
+fn max_depots_keeps_the_largest_depots() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..40u64 {
+        // Sizes are shuffled so the largest depots are not the last listed.
+        let new = (i * 17) % 40 + 1;
+        fs::write(
+            dir.path().join(format!("depot_{}.log", 20000 + i)),
+            format!("PREDICTED_UPDATE_BYTES={new}\nCHANGED_CONTENT_BYTES=1\n"),
+        )
+        .unwrap();
+    }
+
+    let opts = AnalyseOptions {
+        max_depots: Some(3),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).unwrap();
+    let mut sizes: Vec<u64> = report
+        .per_depot
+        .iter()
+        .map(|d| d.metrics.new_bytes)
+        .collect();
+    sizes.sort();
+    assert_eq!(sizes, [38, 39, 40]);
+    assert_eq!(report.depots_omitted, Some(37));
+    assert!(report.to_markdown().contains("37 smaller depots omitted"));
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+    assert_eq!(report.per_depot.len(), 40);
+    assert_eq!(report.depots_omitted, None);
+}
+
+#[test]
+fn depot_sections_list_their_own_top_offenders() {
+    let report = analyse_dir(
+        Path::new("../../fixtures/multi_depot/BuildOutput"),
+        AnalyseOptions::default(),
+    )
+    .unwrap();
+
+    let depot = report
+        .per_depot
+        .iter()
+        .find(|d| d.depot_id == "67890")
+        .unwrap();
+    assert_eq!(depot.offenders[0].path, "DepotB.pak");
+
+    let md = report.to_markdown();
+    let section = md.find("### Depot 67890").unwrap();
+    let rest = &md[section..];
+    let end = rest.find("\n\n").unwrap();
+    assert!(rest[..end].contains("`DepotB.pak` (500000000 bytes)"));
+    assert!(!rest[..end].contains("DepotA.pak"));
+}
+
+#[test]
+fn offender_reported_by_two_logs_lists_both_sources() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("a.log"),
+        "PREDICTED_UPDATE_BYTES=100\nTOP_OFFENDER=Shared.pak:50\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("b.log"),
+        "CHANGED_CONTENT_BYTES=80\nTOP_OFFENDER=Shared.pak:60\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+
+    assert_eq!(report.offenders.len(), 1);
+    let shared = &report.offenders[0];
+    assert_eq!(shared.bytes, 60);
+    assert_eq!(shared.sources.len(), 2);
+    assert!(shared.sources.iter().any(|s| s.ends_with("a.log")));
+    assert!(shared.sources.iter().any(|s| s.ends_with("b.log")));
+
+    let csv = report.offenders_csv();
+    let row = csv.lines().nth(1).unwrap();
+    assert!(row.starts_with("Shared.pak,60,"));
+    assert!(row.contains("a.log;") && row.ends_with("b.log"));
+}
+
+#[test]
+fn path_root_makes_sources_relative() {
+    let root = fs::canonicalize(Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")).unwrap();
+    let input = root.join("fixtures/synthetic_case_01/BuildOutput");
+    assert!(input.is_absolute());
+
+    let opts = AnalyseOptions {
+        path_root: Some(root.clone()),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(&input, opts).unwrap();
+
+    let root_str = root.display().to_string();
+    assert_eq!(
+        report.inputs.input_path,
+        "fixtures/synthetic_case_01/BuildOutput"
+    );
+    assert!(!report.inputs.sources.is_empty());
+    for s in report
+        .inputs
+        .sources
+        .iter()
+        .chain(report.offenders.iter().flat_map(|o| o.sources.iter()))
+    {
+        assert!(!s.starts_with(&root_str), "{s} still absolute");
+        assert!(!Path::new(s).is_absolute());
+    }
+}
+
+#[test]
+fn depot_budgets_pass_within_tolerance_and_fail_beyond() {
+    let dir = tempfile::tempdir().unwrap();
+    let baseline = dir.path().join("baseline.json");
+    fs::write(
+        &baseline,
+        r#"{"metrics":{"new_bytes":8000000},"per_depot":[
+            {"depot_id":"12345","metrics":{"new_bytes":4999000}},
+            {"depot_id":"67890","metrics":{"new_bytes":7980000}}
+        ]}"#,
+    )
+    .unwrap();
+
+    let opts = AnalyseOptions {
+        baseline_path: Some(baseline),
+        budget_tolerance_bytes: Some(10_000),
+        depot_budgets: [("12345".to_string(), 1.0), ("67890".to_string(), 1.0)]
+            .into_iter()
+            .collect(),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(Path::new("../../fixtures/multi_depot/BuildOutput"), opts).unwrap();
+
+    let budget = |id: &str| {
+        report
+            .per_depot
+            .iter()
+            .find(|d| d.depot_id == id)
+            .and_then(|d| d.budget.clone())
+            .unwrap()
+    };
+    // +1_000 bytes: over the ratio, within tolerance.
+    assert!(budget("12345").pass);
+    assert!(budget("12345").regression_ratio.unwrap() > 1.0);
+    // +20_000 bytes: beyond tolerance.
+    assert!(!budget("67890").pass);
+    assert!(!report.budget_passed());
+}
+
+#[test]
+fn generically_named_log_takes_depot_id_from_header() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("steampipe.log"),
+        "[SteamPipe Preview]\nBuilding Depot 12345\nPREDICTED_UPDATE_BYTES=1000\n\
+         CHANGED_CONTENT_BYTES=400\n",
+    )
+    .unwrap();
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+
+    assert_eq!(report.per_depot.len(), 1);
+    assert_eq!(report.per_depot[0].depot_id, "12345");
+    assert_eq!(report.per_depot[0].metrics.new_bytes, 1000);
+
+    let excluded = AnalyseOptions {
+        exclude_depots: vec!["12345".to_string()],
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), excluded).unwrap();
+    assert!(report.per_depot.is_empty());
+}
+
+#[test]
+fn worst_depot_waste_names_the_highest_ratio_depot() {
+    let dir = tempfile::tempdir().unwrap();
+    for (id, changed) in [("10001", 900), ("10002", 200), ("10003", 600)] {
+        fs::write(
+            dir.path().join(format!("steampipe_preview_{id}.log")),
+            format!("PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES={changed}\n"),
+        )
+        .unwrap();
+    }
+
+    let report = analyse_dir(dir.path(), AnalyseOptions::default()).unwrap();
+
+    assert_eq!(report.per_depot.len(), 3);
+    let (depot, ratio) = report.metrics_extra.worst_depot_waste.clone().unwrap();
+    assert_eq!(depot, "10002");
+    assert!((ratio - 0.8).abs() < 1e-9);
+}
+
+#[test]
+fn parse_stats_count_fixture_files_and_matches() {
+    use patchwaste_core::parser::{parse_buildoutput_dir_stats, ParseOptions, SkipReason};
+
+    let input = Path::new("../../fixtures/sidecar_override/BuildOutput");
+    let (_, stats) = parse_buildoutput_dir_stats(input, &ParseOptions::default()).unwrap();
+
+    assert_eq!(stats.files_seen, 2);
+    assert_eq!(stats.files_matched, 1);
+    assert_eq!(stats.files_parsed, 1);
+    assert_eq!(stats.files_skipped.len(), 1);
+    assert!(stats.files_skipped[0].path.ends_with("counters.json"));
+    assert_eq!(stats.files_skipped[0].reason, SkipReason::Extension);
+    assert_eq!(
+        stats.bytes_scanned,
+        fs::metadata(input.join("steampipe_preview.log"))
+            .unwrap()
+            .len()
+    );
+    assert_eq!(stats.matches.counters, 2);
+    assert_eq!(stats.matches.offenders, 0);
+}
+
+#[test]
+fn min_confidence_suppresses_waste_rule_on_low_changed_bytes() {
+    use patchwaste_core::rules::MinConfidence;
+    use patchwaste_core::types::ConfidenceLevel;
+
+    let logs = vec![(
+        "preview.log".to_string(),
+        b"PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=0\n".to_vec(),
+    )];
+
+    let report = analyse_logs("mem", &logs, AnalyseOptions::default()).unwrap();
+    assert_eq!(
+        report.confidence.changed_content_bytes,
+        ConfidenceLevel::Low
+    );
+    assert!(report.findings.iter().any(|f| f.id == "HIGH_WASTE_RATIO"));
+
+    let opts = AnalyseOptions {
+        min_confidence: MinConfidence {
+            changed_content_bytes: Some(ConfidenceLevel::Medium),
+            ..MinConfidence::default()
+        },
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_logs("mem", &logs, opts).unwrap();
+    assert!(report.findings.iter().all(|f| f.id != "HIGH_WASTE_RATIO"));
+}
+
+#[test]
+fn min_confidence_gates_share_mode_top_offender() {
+    use patchwaste_core::rules::{BelowConfidence, MinConfidence};
+    use patchwaste_core::types::{ConfidenceLevel, Severity};
+
+    // No changed counter: changed_content_bytes falls back to new_bytes at Low.
+    let logs = vec![(
+        "preview.log".to_string(),
+        b"PREDICTED_UPDATE_BYTES=1000\nTOP_OFFENDER=Content/Paks/A.pak:900\n".to_vec(),
+    )];
+    let opts = |min_confidence: MinConfidence| {
+        let mut opts = AnalyseOptions {
+            min_confidence,
+            ..AnalyseOptions::default()
+        };
+        opts.rule_thresholds.top_offender_changed_share = Some(0.5);
+        opts
+    };
+    let top_offender = |opts| {
+        analyse_logs("mem", &logs, opts)
+            .unwrap()
+            .findings
+            .into_iter()
+            .find(|f| f.id == "LARGE_TOP_OFFENDER")
+    };
+
+    assert!(top_offender(opts(MinConfidence::default())).is_some());
+
+    let min = MinConfidence {
+        changed_content_bytes: Some(ConfidenceLevel::Medium),
+        ..MinConfidence::default()
+    };
+    assert!(top_offender(opts(min.clone())).is_none());
+
+    let downgraded = top_offender(opts(MinConfidence {
+        action: BelowConfidence::Downgrade,
+        ..min
+    }))
+    .expect("downgraded, not dropped");
+    assert_eq!(downgraded.severity, Severity::Low);
+}
+
+#[test]
+fn depot_configs_apply_their_own_thresholds() {
+    use patchwaste_core::types::Severity;
+
+    let dir = tempfile::tempdir().unwrap();
+    for (id, amber_max) in [("11111", "0.2"), ("22222", "0.9")] {
+        let depot_dir = dir.path().join(format!("depot_{id}"));
+        fs::create_dir_all(&depot_dir).unwrap();
+        // waste_ratio 0.4 in both depots
+        fs::write(
+            depot_dir.join("preview.log"),
+            "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=600\n",
+        )
+        .unwrap();
+        fs::write(
+            depot_dir.join("patchwaste.toml"),
+            format!("[waste_bands]\ngreen_max = 0.1\namber_max = {amber_max}\n"),
+        )
+        .unwrap();
+    }
+
+    let opts = AnalyseOptions {
+        depot_configs: true,
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_dir(dir.path(), opts).unwrap();
+
+    let waste_severity = |id: &str| {
+        report
+            .per_depot
+            .iter()
+            .find(|d| d.depot_id == id)
+            .unwrap()
+            .findings
+            .iter()
+            .find(|f| f.id == "HIGH_WASTE_RATIO")
+            .map(|f| f.severity)
+    };
+    assert_eq!(waste_severity("11111"), Some(Severity::High));
+    assert_eq!(waste_severity("22222"), Some(Severity::Low));
+    // The root has no config, so the 0.5 default applies to the aggregate.
+    assert!(report.findings.iter().all(|f| f.id != "HIGH_WASTE_RATIO"));
+}
@@ -1,6 +1,10 @@
 use std::io::Write;
 
-use patchwaste_core::config::Config;
+use patchwaste_core::config::{Config, ConfigWarning};
+use patchwaste_core::parser::{parse_steampipe_log_with, LogOptions, ParseMode};
+use patchwaste_core::{
+    compute_metrics, parser::ParsedBuildOutput, rules::run_rules, types::Severity,
+};
 
 #[test]
 fn parse_valid_toml() {
@@ -23,7 +27,7 @@ strict = true
 
     let cfg = Config::load(f.path()).unwrap();
     assert_eq!(cfg.app_id, Some(480));
-    assert_eq!(cfg.depot_ids, vec![481, 482]);
+    assert_eq!(cfg.depot_ids, vec!["481", "482"]);
     assert_eq!(cfg.branches, vec!["main", "staging"]);
     assert_eq!(cfg.budget_ratio, Some(1.25));
     assert_eq!(cfg.strict, Some(true));
@@ -31,6 +35,25 @@ strict = true
     assert_eq!(cfg.depot_budgets["481"], 1.5);
 }
 
+#[test]
+fn depot_lists_accept_numbers_and_names() {
+    let cfg: Config = toml::from_str(
+        r#"
+depot_ids = [481, "content"]
+content_depots = ["content"]
+exclude_depots = [482]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(cfg.depot_ids, vec!["481", "content"]);
+    assert_eq!(cfg.exclude_depots, vec!["482"]);
+    assert_eq!(
+        cfg.depot_classes().get("content"),
+        Some(&patchwaste_core::types::DepotClass::Content)
+    );
+}
+
 #[test]
 fn parse_empty_toml_gives_defaults() {
     let mut f = tempfile::NamedTempFile::new().unwrap();
@@ -53,3 +76,161 @@ fn parse_invalid_toml_returns_error() {
     let result = Config::load(f.path());
     assert!(result.is_err());
 }
+
+#[test]
+fn find_upwards_locates_config_in_parent_dir() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::write(root.path().join("patchwaste.toml"), "budget_ratio = 1.5\n").unwrap();
+    let nested = root.path().join("build").join("BuildOutput");
+    std::fs::create_dir_all(&nested).unwrap();
+
+    let found = Config::find_upwards(&nested).expect("config found in ancestor");
+    assert_eq!(found, root.path().join("patchwaste.toml"));
+    assert_eq!(Config::load(&found).unwrap().budget_ratio, Some(1.5));
+}
+
+#[test]
+fn custom_patterns_populate_counters() {
+    let mut f = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        f,
+        r#"
+[patterns]
+predicted_update_bytes = ['SHIPPED=(\d+)']
+"#
+    )
+    .unwrap();
+
+    let cfg = Config::load(f.path()).unwrap();
+    let opts = LogOptions {
+        patterns: cfg.counter_patterns().unwrap(),
+        ..LogOptions::default()
+    };
+    let mut r = std::io::Cursor::new(&b"SHIPPED=4242\n"[..]);
+    let parsed = parse_steampipe_log_with(&mut r, ParseMode::BestEffort, &opts).unwrap();
+    assert_eq!(parsed.counters.predicted_update_bytes, Some(4242));
+
+    let mut bad = tempfile::NamedTempFile::new().unwrap();
+    write!(bad, "[patterns]\nchanged_content_bytes = ['(unclosed']\n").unwrap();
+    assert!(Config::load(bad.path()).is_err());
+}
+
+#[test]
+fn depot_id_pattern_extracts_non_numeric_ids() {
+    use patchwaste_core::{analyse_logs, AnalyseOptions};
+
+    let mut f = tempfile::NamedTempFile::new().unwrap();
+    writeln!(f, "depot_id_pattern = 'depot_(\\w+)'").unwrap();
+    let cfg = Config::load(f.path()).unwrap();
+
+    let logs = vec![(
+        "logs/depot_content.log".to_string(),
+        b"PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=500\n".to_vec(),
+    )];
+    let opts = AnalyseOptions {
+        depot_id_pattern: cfg.depot_id_pattern().unwrap(),
+        ..AnalyseOptions::default()
+    };
+    let report = analyse_logs("mem", &logs, opts).unwrap();
+    let ids: Vec<&str> = report
+        .per_depot
+        .iter()
+        .map(|d| d.depot_id.as_str())
+        .collect();
+    assert_eq!(ids, ["content"]);
+
+    let mut bad = tempfile::NamedTempFile::new().unwrap();
+    writeln!(bad, "depot_id_pattern = 'depot_\\w+'").unwrap();
+    assert!(Config::load(bad.path()).is_err());
+}
+
+#[test]
+fn waste_bands_emit_low_finding_in_amber() {
+    let mut f = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        f,
+        r#"
+[waste_bands]
+green_max = 0.3
+amber_max = 0.5
+"#
+    )
+    .unwrap();
+
+    let cfg = Config::load(f.path()).unwrap();
+    let log = parse_steampipe_log_with(
+        &mut std::io::Cursor::new(&b"PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=600\n"[..]),
+        ParseMode::BestEffort,
+        &LogOptions {
+            patterns: cfg.counter_patterns().unwrap(),
+            ..LogOptions::default()
+        },
+    )
+    .unwrap();
+    let parsed = ParsedBuildOutput {
+        offenders: log.offenders,
+        ..ParsedBuildOutput::from_counters(ParseMode::BestEffort, log.counters)
+    };
+    let (metrics, _) = compute_metrics(&parsed);
+    assert!((metrics.waste_ratio - 0.4).abs() < 1e-9);
+
+    let findings = run_rules(&parsed, &metrics, &cfg.rule_thresholds(), None);
+    let waste = findings
+        .iter()
+        .find(|f| f.id == "HIGH_WASTE_RATIO")
+        .expect("waste finding in amber band");
+    assert_eq!(waste.severity, Severity::Low);
+
+    let no_bands = run_rules(&parsed, &metrics, &Default::default(), None);
+    assert!(no_bands.iter().all(|f| f.id != "HIGH_WASTE_RATIO"));
+}
+
+#[test]
+fn waste_ratio_threshold_lowers_high_waste_firing_point() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("preview.log"),
+        "PREDICTED_UPDATE_BYTES=1000\nCHANGED_CONTENT_BYTES=550\n",
+    )
+    .unwrap();
+    let fires = |cfg: &Config| {
+        let opts = patchwaste_core::AnalyseOptions {
+            rule_thresholds: cfg.rule_thresholds(),
+            ..Default::default()
+        };
+        let report = patchwaste_core::analyse_dir(dir.path(), opts).unwrap();
+        assert!((report.metrics.waste_ratio - 0.45).abs() < 1e-9);
+        report.findings.iter().any(|f| f.id == "HIGH_WASTE_RATIO")
+    };
+
+    let lowered: Config = toml::from_str("waste_ratio_threshold = 0.40").unwrap();
+    assert!(fires(&lowered));
+    // Without the setting the 0.50 default still applies.
+    assert!(!fires(&Config::default()));
+}
+
+#[test]
+fn validate_flags_negative_budget_and_unlisted_depot() {
+    let cfg: Config = toml::from_str(
+        r#"
+depot_ids = [481]
+budget_ratio = -1.0
+
+[depot_budgets]
+"481" = 1.5
+"999" = 2.0
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        cfg.validate(),
+        vec![
+            ConfigWarning::NonPositiveBudgetRatio(-1.0),
+            ConfigWarning::UnlistedDepotBudget {
+                depot_id: "999".to_string()
+            },
+        ]
+    );
+    assert!(Config::default().validate().is_empty());
+}
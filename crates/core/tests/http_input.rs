@@ -0,0 +1,71 @@
+#![cfg(feature = "http")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+
+use patchwaste_core::{analyse_url, AnalyseOptions};
+
+#[test]
+fn analyse_url_fetches_and_parses_a_single_log() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/steampipe_preview.log",
+        listener.local_addr().unwrap()
+    );
+    let server = std::thread::spawn(move || {
+        let body = "PREDICTED_UPDATE_BYTES=7000\nCHANGED_CONTENT_BYTES=3500\n";
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+        let resp = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(resp.as_bytes()).unwrap();
+    });
+
+    let report = analyse_url(&url, AnalyseOptions::default()).expect("analyse url");
+    server.join().unwrap();
+
+    assert_eq!(report.metrics.new_bytes, 7000);
+    assert_eq!(report.metrics.changed_content_bytes, 3500);
+    assert_eq!(report.inputs.sources, vec![url]);
+}
+
+#[test]
+fn fetch_logs_stops_a_download_over_the_scan_cap() {
+    use patchwaste_core::http::{fetch_logs, RetryPolicy};
+    use patchwaste_core::parser::SkipReason;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/huge.log", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf);
+        // Advertise far more than the cap; the client hangs up early, so
+        // later writes may fail.
+        let total = 64 * 1024 * 1024;
+        let _ = stream.write_all(
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {total}\r\nConnection: close\r\n\r\n")
+                .as_bytes(),
+        );
+        let chunk = vec![b'x'; 64 * 1024];
+        let mut sent = 0;
+        while sent < total && stream.write_all(&chunk).is_ok() {
+            sent += chunk.len();
+        }
+        sent
+    });
+
+    let (logs, stats) = fetch_logs(&url, &RetryPolicy::default(), 1024).unwrap();
+    assert!(logs.is_empty());
+    assert_eq!(stats.files_skipped.len(), 1);
+    assert_eq!(stats.files_skipped[0].path, url);
+    assert_eq!(stats.files_skipped[0].reason, SkipReason::ScanLimit);
+    assert_eq!(stats.bytes_scanned, 0);
+
+    let sent = server.join().unwrap();
+    assert!(sent < 64 * 1024 * 1024, "whole body was downloaded");
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use patchwaste_core::sink::OutputSink;
+use patchwaste_core::{analyse_dir, AnalyseOptions};
+
+/// Keeps every artifact in memory, as an embedder might before uploading.
+#[derive(Default)]
+struct MemorySink {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl OutputSink for MemorySink {
+    fn write(&self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[test]
+fn memory_sink_captures_report_json() {
+    let report = analyse_dir(
+        Path::new("../../fixtures/synthetic_case_01/BuildOutput"),
+        AnalyseOptions::default(),
+    )
+    .unwrap();
+
+    let sink = MemorySink::default();
+    sink.write_with("report.json", &mut |w| Ok(report.write_json(w)?))
+        .unwrap();
+    sink.write("report.md", report.to_markdown().as_bytes())
+        .unwrap();
+
+    let files = sink.files.lock().unwrap();
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        ["report.json", "report.md"]
+    );
+    let json: serde_json::Value = serde_json::from_slice(&files["report.json"]).unwrap();
+    assert_eq!(json["metrics"]["new_bytes"], report.metrics.new_bytes);
+}
@@ -20,16 +20,13 @@ proptest! {
         new_bytes in 1u64..10_000_000_000,
         changed in 1u64..10_000_000_000,
     ) {
-        let parsed = ParsedBuildOutput {
-            mode: ParseMode::BestEffort,
-            counters: SteamPipeCounters {
+        let parsed = ParsedBuildOutput::from_counters(
+            ParseMode::BestEffort,
+            SteamPipeCounters {
                 predicted_update_bytes: Some(new_bytes),
                 changed_content_bytes: Some(changed),
             },
-            offenders: vec![],
-            sources: vec![],
-            per_depot: vec![],
-        };
+        );
 
         let (metrics, _) = compute_metrics(&parsed);
         let sum = metrics.waste_ratio + metrics.delta_efficiency;
@@ -1,4 +1,8 @@
-use patchwaste_core::parser::{parse_steampipe_log, ParseMode, SteamPipeCounters};
+use patchwaste_core::parser::{
+    parse_buildoutput_dir_stats, parse_logs, parse_steampipe_log, parse_steampipe_log_str,
+    parse_steampipe_log_with, CounterMerge, DigitSeparators, InputEncoding, LogOptions, ParseMode,
+    ParseOptions, SteamPipeCounters,
+};
 
 #[test]
 fn merge_prefers_latest_non_none() {
@@ -27,3 +31,260 @@ fn parse_pretty_update_and_offender() {
     assert_eq!(parsed.offenders[0].path, "foo.pak");
     assert_eq!(parsed.offenders[0].bytes, 2048);
 }
+
+#[test]
+fn parse_offender_with_quoted_path_containing_colon() {
+    let input = b"TOP_OFFENDER = \"Content/My Level: Act 1.pak\": 4_096\n";
+    let mut r = std::io::Cursor::new(&input[..]);
+    let parsed = parse_steampipe_log(&mut r, ParseMode::BestEffort).unwrap();
+
+    assert_eq!(parsed.offenders[0].path, "Content/My Level: Act 1.pak");
+    assert_eq!(parsed.offenders[0].bytes, 4096);
+}
+
+#[test]
+fn parse_offender_with_windows_drive_path() {
+    let input = b"TOP_OFFENDER=C:\\game\\foo.pak: 1024\r\n";
+    let mut r = std::io::Cursor::new(&input[..]);
+    let parsed = parse_steampipe_log(&mut r, ParseMode::BestEffort).unwrap();
+
+    assert_eq!(parsed.offenders[0].path, "C:\\game\\foo.pak");
+    assert_eq!(parsed.offenders[0].bytes, 1024);
+}
+
+#[test]
+fn windows_1252_log_decodes_non_ascii_offender_path() {
+    // 0xE9 is 'é' in Windows-1252 and invalid on its own in UTF-8.
+    let log = b"PREDICTED_UPDATE_BYTES=10\nTOP_OFFENDER=Caf\xe9.pak:123\n".to_vec();
+    let opts = ParseOptions {
+        encoding: InputEncoding::Windows1252,
+        ..ParseOptions::default()
+    };
+
+    let parsed = parse_logs(&[("preview.log".to_string(), log)], &opts).unwrap();
+
+    assert_eq!(parsed.offenders[0].path, "Café.pak");
+    assert_eq!(parsed.offenders[0].bytes, 123);
+}
+
+#[test]
+fn offenders_beyond_the_top_n_are_tallied_but_not_retained() {
+    use patchwaste_core::parser::RETAINED_OFFENDERS;
+
+    // 5_000 distinct offenders of 1..=5_000 bytes in a fixed scrambled order
+    // (7919 is coprime to 5_000), spread over two logs, plus one path listed
+    // by both.
+    let log = |range: std::ops::Range<u64>| {
+        let mut text = String::from("PREDICTED_UPDATE_BYTES=1\nTOP_OFFENDER=Shared.pak:9000\n");
+        for i in range {
+            let bytes = (i * 7919) % 5_000 + 1;
+            text.push_str(&format!("TOP_OFFENDER=f{bytes}.pak:{bytes}\n"));
+        }
+        text.into_bytes()
+    };
+    let logs = [
+        ("a.log".to_string(), log(0..2_500)),
+        ("b.log".to_string(), log(2_500..5_000)),
+    ];
+    let parsed = parse_logs(&logs, &ParseOptions::default()).unwrap();
+
+    assert_eq!(parsed.offenders.len(), RETAINED_OFFENDERS);
+    assert_eq!(parsed.offenders[0].path, "Shared.pak");
+    assert_eq!(parsed.offenders[0].sources, ["a.log", "b.log"]);
+    assert_eq!(parsed.offenders[1].path, "f5000.pak");
+    assert_eq!(
+        parsed.offenders.last().unwrap().bytes,
+        5_001 - RETAINED_OFFENDERS as u64 + 1
+    );
+
+    let tally = &parsed.offender_tally;
+    assert_eq!(tally.count(), 5_002);
+    assert_eq!(tally.bytes(), 5_000 * 5_001 / 2 + 2 * 9_000);
+    let stats = tally.stats().unwrap();
+    assert!(stats.p50_bytes.abs_diff(2_501) < 100, "{stats:?}");
+}
+
+#[test]
+fn merge_with_sum_totals_counters() {
+    let counters = |changed| SteamPipeCounters {
+        predicted_update_bytes: None,
+        changed_content_bytes: Some(changed),
+    };
+    let mut total = SteamPipeCounters::default();
+    for c in [counters(100), counters(250), counters(650)] {
+        total.merge_with(c, CounterMerge::Sum);
+    }
+
+    assert_eq!(total.changed_content_bytes, Some(1000));
+    assert_eq!(total.predicted_update_bytes, None);
+
+    let log = "CHANGED_CONTENT_BYTES=100\nCHANGED_CONTENT_BYTES=250\nCHANGED_CONTENT_BYTES=650\n";
+    let opts = LogOptions {
+        counter_merge: CounterMerge::Sum,
+        ..LogOptions::default()
+    };
+    let parsed =
+        parse_steampipe_log_with(&mut log.as_bytes(), ParseMode::BestEffort, &opts).unwrap();
+    assert_eq!(parsed.counters.changed_content_bytes, Some(1000));
+}
+
+#[test]
+fn percentage_offenders_take_bytes_from_the_predicted_total() {
+    // The total comes after the offenders; bytes are derived at end of parse.
+    let log = "TOP_OFFENDER = Content/Level1.pak: 12.5%\n\
+               TOP_OFFENDER=\"Content/UI.pak\": 1,5 %\n\
+               TOP_OFFENDER=Content/Exact.pak: 300\n\
+               PREDICTED_UPDATE_BYTES=8000\n";
+    let parsed = parse_steampipe_log(&mut log.as_bytes(), ParseMode::BestEffort).unwrap();
+
+    assert_eq!(parsed.matches.offenders, 3);
+    let level = &parsed.offenders[0];
+    assert_eq!(level.path, "Content/Level1.pak");
+    assert_eq!(level.percent, Some(12.5));
+    assert_eq!(level.bytes, 1000);
+    assert_eq!(parsed.offenders[1].percent, Some(1.5));
+    assert_eq!(parsed.offenders[1].bytes, 120);
+    assert_eq!(parsed.offenders[2].percent, None);
+    assert_eq!(parsed.offenders[2].bytes, 300);
+
+    // Without a total there is nothing to derive bytes from.
+    let parsed = parse_steampipe_log(
+        &mut "TOP_OFFENDER=a.pak: 50%\n".as_bytes(),
+        ParseMode::BestEffort,
+    )
+    .unwrap();
+    assert_eq!(parsed.offenders[0].bytes, 0);
+    assert_eq!(parsed.offenders[0].percent, Some(50.0));
+}
+
+#[test]
+fn parse_pretty_update_with_size_units() {
+    let predicted = |log: &str| {
+        parse_steampipe_log(&mut log.as_bytes(), ParseMode::BestEffort)
+            .unwrap()
+            .counters
+            .predicted_update_bytes
+    };
+
+    assert_eq!(
+        predicted("predicted update size: 1.2 GiB\n"),
+        Some(1_288_490_189)
+    );
+    assert_eq!(
+        predicted("Predicted update size: 500 MB\n"),
+        Some(500_000_000)
+    );
+    assert_eq!(predicted("Predicted update size: 64 KiB\n"), Some(65_536));
+    assert_eq!(
+        predicted("predicted update size: 1,234 bytes\n"),
+        Some(1234)
+    );
+
+    // An exact byte count wins over a rounded size wherever it appears.
+    assert_eq!(
+        predicted("Predicted update size: 1.2 GB\nPredicted update size: 1,234 bytes\n"),
+        Some(1234)
+    );
+    assert_eq!(
+        predicted("Predicted update size: 1,234 bytes\nPredicted update size: 1.2 GB\n"),
+        Some(1234)
+    );
+}
+
+#[test]
+fn eu_separators_read_dot_grouped_counts_and_reject_decimals() {
+    let opts = LogOptions {
+        digit_separators: DigitSeparators::Eu,
+        ..LogOptions::default()
+    };
+    let parse = |log: &str| {
+        parse_steampipe_log_with(&mut log.as_bytes(), ParseMode::BestEffort, &opts).unwrap()
+    };
+
+    let parsed = parse(
+        "PREDICTED_UPDATE_BYTES=1.234.567\nCHANGED_CONTENT_BYTES=2.000.000\nTOP_OFFENDER=a.pak: 12.345\n",
+    );
+    assert_eq!(parsed.counters.predicted_update_bytes, Some(1_234_567));
+    assert_eq!(parsed.counters.changed_content_bytes, Some(2_000_000));
+    assert_eq!(parsed.offenders[0].bytes, 12_345);
+
+    let parsed = parse("Predicted update size: 1.234.567 bytes\n");
+    assert_eq!(parsed.counters.predicted_update_bytes, Some(1_234_567));
+
+    // A decimal point or comma is never read as a group separator.
+    for log in [
+        "PREDICTED_UPDATE_BYTES=1.5\n",
+        "PREDICTED_UPDATE_BYTES=1.2345\n",
+        "PREDICTED_UPDATE_BYTES=1.234,5\n",
+        "Predicted update size: 1,5 bytes\n",
+    ] {
+        assert_eq!(parse(log).counters.predicted_update_bytes, None, "{log}");
+    }
+
+    // The default separators are unchanged.
+    let parsed = parse_steampipe_log_with(
+        &mut "Predicted update size: 1,234,567 bytes\n".as_bytes(),
+        ParseMode::BestEffort,
+        &LogOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(parsed.counters.predicted_update_bytes, Some(1_234_567));
+}
+
+#[test]
+fn mmap_and_buffered_parsing_agree_on_a_large_log() {
+    // Mixed line endings and no trailing newline on the last line.
+    let mut log = String::new();
+    for i in 0..100_000 {
+        match i % 4 {
+            0 => log.push_str(&format!(
+                "TOP_OFFENDER = Content/Paks/p{i}.pak: {}\n",
+                i * 3
+            )),
+            1 => log.push_str(&format!("progress {i}\r\n")),
+            2 => log.push_str(&format!(
+                "TOP_OFFENDER = \"C:/with space/{i}.pak\": {i}\r\n"
+            )),
+            _ => log.push_str("LogInit: noise\n"),
+        }
+    }
+    log.push_str("PREDICTED_UPDATE_BYTES=123456789\nCHANGED_CONTENT_BYTES=98765");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("preview.log");
+    std::fs::write(&path, &log).unwrap();
+
+    let opts = LogOptions::default();
+    let mut r = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+    let buffered = parse_steampipe_log_with(&mut r, ParseMode::BestEffort, &opts).unwrap();
+    let in_memory = parse_steampipe_log_str(&log, ParseMode::BestEffort, &opts).unwrap();
+    assert_eq!(buffered.counters.changed_content_bytes, Some(98765));
+    assert_eq!(
+        buffered.counters.predicted_update_bytes,
+        in_memory.counters.predicted_update_bytes
+    );
+    assert_eq!(
+        buffered.counters.changed_content_bytes,
+        in_memory.counters.changed_content_bytes
+    );
+    assert_eq!(buffered.offenders, in_memory.offenders);
+    assert_eq!(buffered.matches, in_memory.matches);
+
+    let parse_dir = |mmap_min_bytes| {
+        let opts = ParseOptions {
+            mmap_min_bytes,
+            max_total_bytes_scanned: u64::MAX,
+            ..ParseOptions::default()
+        };
+        parse_buildoutput_dir_stats(dir.path(), &opts).unwrap()
+    };
+    let (mapped, mapped_stats) = parse_dir(Some(1024));
+    let (read, read_stats) = parse_dir(None);
+    assert_eq!(mapped.counters.predicted_update_bytes, Some(123_456_789));
+    assert_eq!(
+        mapped.counters.changed_content_bytes,
+        read.counters.changed_content_bytes
+    );
+    assert_eq!(mapped.offenders, read.offenders);
+    assert_eq!(mapped_stats, read_stats);
+}